@@ -7,6 +7,7 @@
 use bytesize::ByteSize;
 use std::error::Error;
 use tabled::{builder::Builder, settings::Style};
+use veneer::nvenums::ObjsetType;
 use veneer::zfs;
 
 enum Field<'a> {
@@ -35,7 +36,7 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     let mut tb = Builder::default();
     tb.set_header(
-        ["name".to_string()]
+        ["name".to_string(), "type".to_string()]
             .into_iter()
             .chain(FIELDS.iter().map(|f| f.name().to_string()))
             .map(|ref s| s.to_string())
@@ -44,8 +45,14 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     for pool in z.pools()? {
         for dataset in pool.datasets()? {
+            let kind = match dataset.kind() {
+                Ok(ObjsetType::Zfs) => "filesystem",
+                Ok(ObjsetType::Zvol) => "volume",
+                Ok(_) => "-",
+                Err(_) => "?",
+            };
             tb.push_record(
-                [dataset.name()]
+                [dataset.name().to_string(), kind.to_string()]
                     .into_iter()
                     .chain(
                         FIELDS
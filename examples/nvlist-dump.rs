@@ -0,0 +1,85 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+// Copyright (c) 2023, Rob Norris <robn@despairlabs.com>
+
+// dump the raw result nvlists from a few ioctls into files, for use as test fixtures:
+//
+//   cargo run --example nvlist-dump -- tests/fixtures
+
+use std::error::Error;
+use std::ffi::CString;
+use std::fs::File;
+use std::io::Result as IOResult;
+use std::os::raw::{c_uint, c_ulong};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use veneer::ioc::{self, Transport, ZFSCommand};
+
+// passes everything through to the real device, keeping a copy of the last result buffer
+#[derive(Debug)]
+struct Recorder {
+    dev: File,
+    last: Arc<Mutex<Vec<u8>>>,
+}
+
+impl Transport for Recorder {
+    fn ioctl(&mut self, req: c_ulong, cmd: &mut ZFSCommand) -> IOResult<c_uint> {
+        let r = Transport::ioctl(&mut self.dev, req, cmd)?;
+        *self.last.lock().unwrap() = cmd.dst().to_vec();
+        Ok(r)
+    }
+
+    fn try_clone(&self) -> IOResult<Box<dyn Transport>> {
+        Ok(Box::new(Recorder {
+            dev: self.dev.try_clone()?,
+            last: self.last.clone(),
+        }))
+    }
+}
+
+fn save(dir: &Path, name: &str, last: &Mutex<Vec<u8>>) -> IOResult<()> {
+    let path: PathBuf = dir.join(format!("{}.nvlist", name.replace('/', "_")));
+    std::fs::write(&path, last.lock().unwrap().as_slice())?;
+    println!("{}", path.display());
+    Ok(())
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let dir = PathBuf::from(std::env::args().nth(1).unwrap_or(".".to_string()));
+
+    let last = Arc::new(Mutex::new(vec![]));
+    let mut h = ioc::Handle::with_transport(Box::new(Recorder {
+        dev: File::open("/dev/zfs")?,
+        last: last.clone(),
+    }));
+
+    let configs = h.pool_configs()?;
+    save(&dir, "pool_configs", &last)?;
+
+    for pool in configs.keys() {
+        let name = pool.to_string_lossy();
+        h.pool_stats(pool)?;
+        save(&dir, &format!("pool_stats-{}", name), &last)?;
+
+        h.objset_stats(pool)?;
+        save(&dir, &format!("objset_stats-{}", name), &last)?;
+
+        let mut cookie = 0;
+        while let Ok(is) = h.dataset_list_next(&CString::from(pool), cookie) {
+            h.objset_stats(&is.name)?;
+            save(
+                &dir,
+                &format!("objset_stats-{}", is.name.to_string_lossy()),
+                &last,
+            )?;
+
+            h.get_fsacl(&is.name)?;
+            save(&dir, &format!("fsacl-{}", is.name.to_string_lossy()), &last)?;
+            cookie = is.cookie;
+        }
+    }
+
+    Ok(())
+}
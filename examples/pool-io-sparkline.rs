@@ -13,22 +13,18 @@ use ratatui::widgets::*;
 use std::error::Error;
 use std::iter;
 use std::time::{Duration, Instant};
-use veneer::zfs::{self, Pool};
-
-fn get_stats(pool: &Pool) -> Result<(u64, u64), Box<dyn Error>> {
-    let vs = pool.root_vdev()?.stats()?;
-    Ok((vs.bytes[1], vs.bytes[2]))
-}
+use veneer::zfs::{self, IoStatSampler, Pool};
 
 struct PoolState {
-    read_last: u64,
-    write_last: u64,
+    sampler: IoStatSampler,
     read_history: Vec<u64>,
     write_history: Vec<u64>,
 }
 
 fn render_pool<B: Backend>(frame: &mut Frame<B>, rect: Rect, pool: &Pool, state: &PoolState) {
-    let block = Block::default().title(pool.name()).borders(Borders::ALL);
+    let block = Block::default()
+        .title(pool.name().as_str())
+        .borders(Borders::ALL);
     let inner = block.inner(rect);
     frame.render_widget(block, rect);
     let rows = Layout::default()
@@ -52,14 +48,15 @@ fn main() -> Result<(), Box<dyn Error>> {
     let mut pool_state: Vec<(Pool, PoolState)> = vec![];
 
     for pool in z.pools()? {
-        let (r, w) = get_stats(&pool)?;
+        // the first sample is everything since import, so it's not part of the history
+        let mut sampler = IoStatSampler::new(&pool);
+        sampler.sample()?;
         let read_v: Vec<u64> = iter::repeat(0).take(200).collect();
         let write_v: Vec<u64> = iter::repeat(0).take(200).collect();
         pool_state.push((
             pool,
             PoolState {
-                read_last: r,
-                write_last: w,
+                sampler,
                 read_history: read_v,
                 write_history: write_v,
             },
@@ -104,15 +101,12 @@ fn main() -> Result<(), Box<dyn Error>> {
             }
         }
         if last_tick.elapsed() >= tick {
-            for (pool, state) in pool_state.iter_mut() {
-                let (r, w) = get_stats(&pool)?;
-                let (dr, dw) = (r - state.read_last, w - state.write_last);
+            for (_, state) in pool_state.iter_mut() {
+                let delta = state.sampler.sample()?;
                 state.read_history.pop();
-                state.read_history.insert(0, dr);
+                state.read_history.insert(0, delta.read_bytes);
                 state.write_history.pop();
-                state.write_history.insert(0, dw);
-                state.read_last = r;
-                state.write_last = w;
+                state.write_history.insert(0, delta.write_bytes);
             }
 
             last_tick = Instant::now();
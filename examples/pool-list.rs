@@ -6,7 +6,24 @@
 
 use std::error::Error;
 use tabled::{builder::Builder, settings::Style};
-use veneer::zfs::{self, Vdev};
+use veneer::zfs::{self, VdevNode};
+
+// the vdev and everything under it, indented by depth, like zpool status
+fn push_vdev(tb: &mut Builder, vd: &VdevNode, depth: usize) {
+    let vs = &vd.stats;
+    tb.push_record([
+        format!("{}{}", "  ".repeat(depth), vd.name),
+        format!("{:?}{}", vd.typ, if vd.is_log { " (log)" } else { "" }),
+        format!("{}", vs.state),
+        format!("{}", vs.read_errors),
+        format!("{}", vs.write_errors),
+        format!("{}", vs.checksum_errors),
+        format!("{}", vs.slow_ios),
+    ]);
+    for cvd in &vd.children {
+        push_vdev(tb, cvd, depth + 1);
+    }
+}
 
 fn main() -> Result<(), Box<dyn Error>> {
     let z = zfs::open()?;
@@ -14,28 +31,8 @@ fn main() -> Result<(), Box<dyn Error>> {
     let mut tb = Builder::default();
     tb.set_header(["name", "type", "state", "read", "write", "cksum", "slow"]);
 
-    let mut push_vdev = |name: String, vd: &Vdev| -> Result<(), Box<dyn Error>> {
-        let vs = vd.stats()?;
-        tb.push_record([
-            format!("{}", name),
-            format!("{:?}", vd.typ()),
-            format!("{}", vs.state),
-            format!("{}", vs.read_errors),
-            format!("{}", vs.write_errors),
-            format!("{}", vs.checksum_errors),
-            format!("{}", vs.slow_ios),
-        ]);
-        Ok(())
-    };
-
     for pool in z.pools()? {
-        let root = pool.root_vdev()?;
-
-        push_vdev(pool.name(), &root)?;
-
-        for vd in root.children()? {
-            push_vdev(vd.guid().to_string(), &vd)?;
-        }
+        push_vdev(&mut tb, &pool.vdev_tree()?, 0);
     }
 
     let table = tb.build().with(Style::rounded()).to_string();
@@ -0,0 +1,132 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+// Copyright (c) 2023, Rob Norris <robn@despairlabs.com>
+
+// async access to the control device, for programs running under tokio. the ioctls themselves
+// still block: each call gets its own clone of the handle and runs on tokio's blocking thread
+// pool, so the executor keeps going, but every call in flight holds one of those threads until
+// the kernel returns. for a pool sync or a big diff, that can be a while
+
+use crate::ioc::{self, Transport};
+use crate::nvenums::WaitActivity;
+use crate::nvpair::{PairList, ParseError};
+use std::error::Error;
+use std::ffi::CString;
+use std::future::Future;
+use std::io::{Error as IOError, Result as IOResult};
+use std::os::fd::OwnedFd;
+
+type AsyncResult<T> = Result<T, Box<dyn Error + Send + Sync>>;
+
+// the errors from ioc can't cross threads as they are. everything it actually returns is one
+// of these two; anything else is kept as its message
+fn sendable(e: Box<dyn Error>) -> Box<dyn Error + Send + Sync> {
+    let e = match e.downcast::<IOError>() {
+        Ok(e) => return e,
+        Err(e) => e,
+    };
+    match e.downcast::<ParseError>() {
+        Ok(e) => e,
+        Err(e) => e.to_string().into(),
+    }
+}
+
+// only the transport is kept here. a Handle carries its whole result buffer, so one is made
+// fresh on the blocking thread for each call rather than being moved through the future
+#[derive(Debug)]
+pub struct AsyncHandle(Box<dyn Transport>);
+
+impl From<ioc::Handle> for AsyncHandle {
+    fn from(h: ioc::Handle) -> Self {
+        AsyncHandle(h.into_transport())
+    }
+}
+
+// these are plain fns returning futures rather than async fns, so the transport is cloned
+// before the future is made and it doesn't borrow self. that keeps them Send, for tokio::spawn
+impl AsyncHandle {
+    pub fn open() -> IOResult<AsyncHandle> {
+        Ok(ioc::Handle::open()?.into())
+    }
+
+    pub fn with_transport(dev: Box<dyn Transport>) -> AsyncHandle {
+        AsyncHandle(dev)
+    }
+
+    // run any ioc call (or several) on a blocking thread, with a handle of its own
+    pub fn run<F, R>(&self, f: F) -> impl Future<Output = AsyncResult<R>>
+    where
+        F: FnOnce(&mut ioc::Handle) -> Result<R, Box<dyn Error>> + Send + 'static,
+        R: Send + 'static,
+    {
+        let dev = self.0.try_clone();
+        async move {
+            let dev = dev?;
+            tokio::task::spawn_blocking(move || {
+                let mut h = ioc::Handle::with_transport(dev);
+                f(&mut h).map_err(sendable)
+            })
+            .await?
+        }
+    }
+
+    pub fn pool_configs(&self) -> impl Future<Output = AsyncResult<PairList>> {
+        self.run(|h| h.pool_configs())
+    }
+
+    pub fn pool_stats(&self, pool: CString) -> impl Future<Output = AsyncResult<PairList>> {
+        self.run(move |h| h.pool_stats(&pool))
+    }
+
+    // waits for the txg to sync, which on a busy pool can be several seconds
+    pub fn pool_sync(&self, pool: CString, force: bool) -> impl Future<Output = AsyncResult<()>> {
+        self.run(move |h| h.pool_sync(&pool, force))
+    }
+
+    // as ioc::Handle::wait(). scrubs and resilvers can take hours
+    pub fn wait(
+        &self,
+        pool: CString,
+        activity: WaitActivity,
+        tag: Option<u64>,
+    ) -> impl Future<Output = AsyncResult<bool>> {
+        self.run(move |h| h.wait(&pool, activity, tag))
+    }
+
+    // as ioc::Handle::recv(). in_fd is held open until the whole stream is read; if it's a
+    // pipe, write the other end from another task
+    pub fn recv(
+        &self,
+        snap: CString,
+        in_fd: OwnedFd,
+        props: Option<PairList>,
+        origin: Option<CString>,
+        force: bool,
+        resumable: bool,
+    ) -> impl Future<Output = AsyncResult<PairList>> {
+        self.run(move |h| {
+            h.recv(
+                &snap,
+                None,
+                &in_fd,
+                props.as_ref(),
+                origin.as_deref(),
+                force,
+                resumable,
+            )
+        })
+    }
+
+    // as ioc::Handle::diff(). out_fd is held open until the stream is complete; if it's a pipe,
+    // read the other end from another task, or this will never finish
+    pub fn diff(
+        &self,
+        snap: CString,
+        from: CString,
+        out_fd: OwnedFd,
+    ) -> impl Future<Output = AsyncResult<()>> {
+        self.run(move |h| h.diff(&snap, &from, &out_fd))
+    }
+}
@@ -4,41 +4,444 @@
 
 // Copyright (c) 2023, Rob Norris <robn@despairlabs.com>
 
-use crate::nvpair::{self, PairList};
-use crate::sys::{self, ZFSCommand};
+use crate::nvenums::{ObjsetType, UserQuotaType, VdevState, WaitActivity};
+use crate::nvpair::{self, PairList, PairValue};
+use crate::sys;
 use std::error::Error;
 use std::ffi::{CStr, CString};
+use std::fmt;
 use std::fs::File;
-use std::io::Result as IOResult;
-use std::os::raw::c_ulong;
+use std::io::{self, Read, Result as IOResult};
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, OwnedFd};
+use std::os::raw::{c_int, c_uint, c_ulong};
 use std::path::Path;
 
+pub use crate::sys::{ObjsetStats, ZFSCommand, ZInjectRecord};
+
+// something that can carry a command to ZFS and back. normally that's the control device, but
+// anything that can fill in a command can stand in for it (eg to replay canned responses in tests)
+pub trait Transport: Send + fmt::Debug {
+    fn ioctl(&mut self, req: c_ulong, cmd: &mut ZFSCommand) -> IOResult<c_uint>;
+    fn try_clone(&self) -> IOResult<Box<dyn Transport>>;
+
+    // a new, separate open of the device, for commands that tie something (a temporary
+    // snapshot or hold) to a cleanup fd. the kernel undoes it when the last copy of that fd is
+    // closed, so it can't be a dup of the one the commands go through
+    fn cleanup_fd(&self) -> IOResult<OwnedFd> {
+        Err(io::Error::from(io::ErrorKind::Unsupported))
+    }
+}
+
+impl Transport for File {
+    fn ioctl(&mut self, req: c_ulong, cmd: &mut ZFSCommand) -> IOResult<c_uint> {
+        sys::zfs_ioctl(self, req, cmd)
+    }
+
+    fn try_clone(&self) -> IOResult<Box<dyn Transport>> {
+        Ok(Box::new(File::try_clone(self)?))
+    }
+
+    // opening the fd's /proc link opens the device afresh, wherever it was opened from.
+    // elsewhere, it's the usual device
+    fn cleanup_fd(&self) -> IOResult<OwnedFd> {
+        let path = if cfg!(target_os = "linux") {
+            format!("/proc/self/fd/{}", self.as_raw_fd())
+        } else {
+            "/dev/zfs".into()
+        };
+        let f = File::options().read(true).write(true).open(path)?;
+        Ok(f.into())
+    }
+}
+
 #[derive(Debug)]
 pub struct Handle {
-    dev: File,
+    dev: Box<dyn Transport>,
     cmd: ZFSCommand,
     buf: [u8; 262144],
+    src: Vec<u8>,
+    conf: Vec<u8>,
 }
 
+// the raw pointers in the command struct make Handle !Send by default. they only ever point into
+// our own buffer, and are reset immediately before every ioctl, so nothing is shared across the
+// move. a Handle can be sent to another thread; to issue ioctls from several threads at once,
+// give each its own Handle via try_clone()
+unsafe impl Send for Handle {}
+
 #[derive(Debug)]
 pub struct IterState {
     pub name: CString,
-    pub list: PairList,
+    pub list: PairList, // empty from the _simple commands
+    pub stats: ObjsetStats,
     pub cookie: u64,
 }
 
+// a state change to ask for on a vdev. the kernel takes the target vdev_state_t in zc_cookie,
+// and flags for it in zc_obj
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VdevStateCmd {
+    Online { expand: bool }, // VDEV_STATE_ONLINE; expand to fill the device if it grew
+    Offline { temporary: bool }, // VDEV_STATE_OFFLINE; temporary is forgotten on export/reboot
+    Faulted,                 // VDEV_STATE_FAULTED
+    Degraded,                // VDEV_STATE_DEGRADED
+}
+
+impl VdevStateCmd {
+    fn cookie(&self) -> u64 {
+        match self {
+            VdevStateCmd::Online { .. } => 7, // VDEV_STATE_ONLINE == VDEV_STATE_HEALTHY
+            VdevStateCmd::Offline { .. } => 2,
+            VdevStateCmd::Faulted => 5,
+            VdevStateCmd::Degraded => 6,
+        }
+    }
+
+    fn obj(&self) -> u64 {
+        match self {
+            VdevStateCmd::Online { expand } => {
+                if *expand {
+                    0x8 // ZFS_ONLINE_EXPAND
+                } else {
+                    0
+                }
+            }
+            VdevStateCmd::Offline { temporary } => {
+                if *temporary {
+                    0x1 // ZFS_OFFLINE_TEMPORARY
+                } else {
+                    0
+                }
+            }
+            // the reason, as vdev_aux_t; same as zpool offline -f
+            VdevStateCmd::Faulted | VdevStateCmd::Degraded => 14, // VDEV_AUX_EXTERNAL
+        }
+    }
+}
+
+// a share to publish or withdraw, as zfs_share_op_t in the command's zfs_share_t
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShareOp {
+    ShareNfs,   // ZFS_SHARE_NFS
+    UnshareNfs, // ZFS_UNSHARE_NFS
+    ShareSmb,   // ZFS_SHARE_SMB
+    UnshareSmb, // ZFS_UNSHARE_SMB
+}
+
+impl From<ShareOp> for u64 {
+    fn from(op: ShareOp) -> Self {
+        match op {
+            ShareOp::ShareNfs => 0,
+            ShareOp::UnshareNfs => 1,
+            ShareOp::ShareSmb => 2,
+            ShareOp::UnshareSmb => 3,
+        }
+    }
+}
+
+// what a share is of, for the share ioctl: the path to export (the dataset's mountpoint) and
+// the options to share it with (the sharenfs or sharesmb value). the command's zfs_share_t
+// points at these while it runs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShareData {
+    pub export: CString,
+    pub options: CString,
+}
+
+// a change to the SMB ACL resources under a dataset's .zfs/shares, as zfs_smb_acl_op_t
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SmbAclOp {
+    Add(CString),                          // ZFS_SMB_ACL_ADD: a resource, by name
+    Remove(CString),                       // ZFS_SMB_ACL_REMOVE
+    Rename { from: CString, to: CString }, // ZFS_SMB_ACL_RENAME
+    Purge,                                 // ZFS_SMB_ACL_PURGE: every resource
+}
+
+impl SmbAclOp {
+    fn cookie(&self) -> u64 {
+        match self {
+            SmbAclOp::Add(_) => 0,
+            SmbAclOp::Remove(_) => 1,
+            SmbAclOp::Rename { .. } => 2,
+            SmbAclOp::Purge => 3,
+        }
+    }
+}
+
+// what happened to a range of objects between two snapshots
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffKind {
+    InUse, // DDR_INUSE: created or changed
+    Free,  // DDR_FREE: removed
+}
+
+// one dmu_diff_record_t from the diff stream: a kind, and an inclusive range of object ids
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiffEntry {
+    pub kind: DiffKind,
+    pub first: u64,
+    pub last: u64,
+}
+
+// read the stream of records written by diff(). each is three u64s: type, first, last.
+// DDR_NONE records carry nothing and are skipped
+pub fn read_diff<R: Read>(mut r: R) -> IOResult<Vec<DiffEntry>> {
+    let mut entries = vec![];
+    let mut rec = [0u8; 24];
+    loop {
+        // end of stream between records is the normal end; in the middle of one, it's not
+        let mut n = 0;
+        while n < rec.len() {
+            match r.read(&mut rec[n..]) {
+                Ok(0) => break,
+                Ok(k) => n += k,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        match n {
+            0 => return Ok(entries),
+            24 => (),
+            _ => return Err(io::Error::from(io::ErrorKind::UnexpectedEof)),
+        }
+        let field = |n: usize| u64::from_ne_bytes(rec[n * 8..n * 8 + 8].try_into().unwrap());
+        let kind = match field(0) {
+            0x1 => continue, // DDR_NONE
+            0x2 => DiffKind::InUse,
+            0x4 => DiffKind::Free,
+            _ => return Err(io::Error::from(io::ErrorKind::InvalidData)),
+        };
+        entries.push(DiffEntry {
+            kind,
+            first: field(1),
+            last: field(2),
+        });
+    }
+}
+
+// a send stream starts with a begin record (a dmu_replay_record_t of type DRR_BEGIN), which
+// says what's in it. it's always this size, whatever the record type
+pub const BEGIN_RECORD_SIZE: usize = 312;
+
+const DMU_BACKUP_MAGIC: u64 = 0x2f5bacbac;
+
+// drr_versioninfo is the stream header type in the low two bits, and the DMU_BACKUP_FEATURE_*
+// flags above them
+const DMU_COMPOUNDSTREAM: u64 = 0x2;
+const DMU_FEATUREFLAGS_SHIFT: u64 = 2;
+const DMU_FEATUREFLAGS_MASK: u64 = 0x3fffffff;
+
+// what a send stream's begin record (struct drr_begin) says about it: enough to tell what it
+// will make before receiving it, or to check a stream file is one at all
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SendStreamHeader {
+    pub toname: CString,         // the snapshot it was made from
+    pub toguid: u64,             // that snapshot's guid
+    pub fromguid: u64,           // the snapshot it's incremental from, or 0 for a full stream
+    pub creation_time: u64,      // when the snapshot was taken, in seconds since the epoch
+    pub objset_type: ObjsetType, // filesystem or volume
+    pub compound: bool,          // a stream of streams (zfs send -R), not a single one
+    pub features: u64,           // DMU_BACKUP_FEATURE_* flags the receiver has to support
+    pub flags: u32,              // DRR_FLAG_* (clone, ci_data, freerecords, spill_block)
+    pub swapped: bool,           // written on a machine of the other byte order
+}
+
+impl SendStreamHeader {
+    // whether it's an incremental stream, and needs fromguid's snapshot to receive into
+    pub fn is_incremental(&self) -> bool {
+        self.fromguid != 0
+    }
+}
+
+// decode the begin record at the start of a send stream. a stream from the other byte order
+// is fine, and is decoded the right way round
+pub fn parse_stream_header(begin: &[u8]) -> IOResult<SendStreamHeader> {
+    let invalid = || io::Error::from(io::ErrorKind::InvalidData);
+    if begin.len() < BEGIN_RECORD_SIZE || begin[0..4] != [0; 4] {
+        return Err(invalid()); // not DRR_BEGIN
+    }
+    let magic = u64::from_ne_bytes(begin[8..16].try_into().unwrap());
+    let swapped = magic != DMU_BACKUP_MAGIC;
+    if swapped && magic != DMU_BACKUP_MAGIC.swap_bytes() {
+        return Err(invalid());
+    }
+    let u64_at = |off: usize| {
+        let n = u64::from_ne_bytes(begin[off..off + 8].try_into().unwrap());
+        if swapped {
+            n.swap_bytes()
+        } else {
+            n
+        }
+    };
+    let u32_at = |off: usize| {
+        let n = u32::from_ne_bytes(begin[off..off + 4].try_into().unwrap());
+        if swapped {
+            n.swap_bytes()
+        } else {
+            n
+        }
+    };
+    let versioninfo = u64_at(16);
+    Ok(SendStreamHeader {
+        toname: CStr::from_bytes_until_nul(&begin[56..BEGIN_RECORD_SIZE])
+            .map_err(|_| invalid())?
+            .into(),
+        toguid: u64_at(40),
+        fromguid: u64_at(48),
+        creation_time: u64_at(24),
+        objset_type: ObjsetType::from(u32_at(32) as c_int),
+        compound: versioninfo & 0x3 == DMU_COMPOUNDSTREAM,
+        features: (versioninfo >> DMU_FEATUREFLAGS_SHIFT) & DMU_FEATUREFLAGS_MASK,
+        flags: u32_at(36),
+        swapped,
+    })
+}
+
+// the full name of the snapshot a send stream was made from (drr_toname), from its begin
+// record
+pub fn stream_toname(begin: &[u8]) -> IOResult<CString> {
+    Ok(parse_stream_header(begin)?.toname)
+}
+
+// commands that act on several things at once (set properties, and the bulk snapshot, destroy
+// and hold commands) report the things that failed in an errlist, mapping each one's name to
+// an int32 errno. this is what comes back when any of them did, split into the names that
+// worked and the ones that didn't. some commands are all or nothing; for those, succeeded is
+// what would have worked, had the rest not failed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PartialFailure {
+    pub succeeded: Vec<CString>,
+    pub failed: Vec<(CString, i32)>,
+}
+
+impl PartialFailure {
+    // the errno for one name, if it failed
+    pub fn errno(&self, name: &CStr) -> Option<i32> {
+        self.failed
+            .iter()
+            .find(|(n, _)| n.as_c_str() == name)
+            .map(|(_, e)| *e)
+    }
+}
+
+impl fmt::Display for PartialFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} of {} failed:",
+            self.failed.len(),
+            self.failed.len() + self.succeeded.len()
+        )?;
+        for (name, errno) in &self.failed {
+            write!(
+                f,
+                " {}: {};",
+                name.to_string_lossy(),
+                io::Error::from_raw_os_error(*errno)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for PartialFailure {}
+
+// the pool the first of these snapshots or bookmarks is in, for the commands that act on
+// several at once and are sent to their pool. InvalidInput if there aren't any
+pub(crate) fn pool_of<'a>(
+    mut snaps: impl Iterator<Item = &'a CStr>,
+) -> Result<CString, Box<dyn Error>> {
+    let first = snaps
+        .next()
+        .ok_or_else(|| io::Error::from(io::ErrorKind::InvalidInput))?;
+    let pool = first
+        .to_bytes()
+        .split(|&c| c == b'/' || c == b'@' || c == b'#')
+        .next()
+        .unwrap_or_default();
+    Ok(CString::new(pool)?)
+}
+
+// the name -> errno pairs from an errlist. anything else in the list isn't an error, and is
+// left out
+fn errlist(list: &PairList) -> Vec<(CString, i32)> {
+    list.pairs()
+        .filter_map(|p| match p.value() {
+            PairValue::Int32(e) => Some((p.key().into(), *e)),
+            _ => None,
+        })
+        .collect()
+}
+
+// a name and its NUL must fit in a command buffer of the given size
+fn fits(s: &CStr, len: usize) -> IOResult<()> {
+    if s.to_bytes_with_nul().len() > len {
+        return Err(io::Error::from(io::ErrorKind::InvalidInput));
+    }
+    Ok(())
+}
+
+// how many times an ioctl is retried after EINTR
+const EINTR_RETRIES: usize = 16;
+
+// the commands that can be run again after EINTR: the ones that only look, and the ones that
+// only wait (a sync, or for background work), which do no harm if they happen twice. anything
+// that changes something might have got far enough to, so EINTR goes back to the caller
+fn restartable(req: c_ulong) -> bool {
+    matches!(
+        req,
+        sys::ZFS_IOC_POOL_CONFIGS
+            | sys::ZFS_IOC_POOL_STATS
+            | sys::ZFS_IOC_POOL_TRYIMPORT
+            | sys::ZFS_IOC_POOL_GET_HISTORY
+            | sys::ZFS_IOC_POOL_GET_PROPS
+            | sys::ZFS_IOC_POOL_SYNC
+            | sys::ZFS_IOC_OBJSET_STATS
+            | sys::ZFS_IOC_OBJSET_ZPLPROPS
+            | sys::ZFS_IOC_OBJSET_RECVD_PROPS
+            | sys::ZFS_IOC_DATASET_LIST_NEXT
+            | sys::ZFS_IOC_SNAPSHOT_LIST_NEXT
+            | sys::ZFS_IOC_INJECT_LIST_NEXT
+            | sys::ZFS_IOC_ERROR_LOG
+            | sys::ZFS_IOC_DSOBJ_TO_DSNAME
+            | sys::ZFS_IOC_OBJ_TO_PATH
+            | sys::ZFS_IOC_OBJ_TO_STATS
+            | sys::ZFS_IOC_NEXT_OBJ
+            | sys::ZFS_IOC_GET_FSACL
+            | sys::ZFS_IOC_GET_HOLDS
+            | sys::ZFS_IOC_GET_BOOKMARKS
+            | sys::ZFS_IOC_GET_BOOKMARK_PROPS
+            | sys::ZFS_IOC_USERSPACE_ONE
+            | sys::ZFS_IOC_USERSPACE_MANY
+            | sys::ZFS_IOC_SPACE_WRITTEN
+            | sys::ZFS_IOC_SPACE_SNAPS
+            | sys::ZFS_IOC_SEND_SPACE
+            | sys::ZFS_IOC_SEND_PROGRESS
+            | sys::ZFS_IOC_WAIT
+            | sys::ZFS_IOC_WAIT_FS
+    )
+}
+
 type IOCResult = Result<(), Box<dyn Error>>;
 type IOCResultList = Result<PairList, Box<dyn Error>>;
 type IOCResultIter = Result<IterState, Box<dyn Error>>;
+type IOCResultFault = Result<Option<(u64, CString, ZInjectRecord)>, Box<dyn Error>>;
 
 impl Handle {
     // open the control device node. you only need this if its not on /dev/zfs
     pub fn open_dev<P: AsRef<Path>>(path: P) -> IOResult<Handle> {
-        Ok(Handle {
-            dev: File::open(path)?,
+        Ok(Handle::with_transport(Box::new(File::open(path)?)))
+    }
+
+    // create a handle that sends commands somewhere other than a real control device
+    pub fn with_transport(dev: Box<dyn Transport>) -> Handle {
+        Handle {
+            dev,
             cmd: Default::default(),
             buf: [0; 262144],
-        })
+            src: vec![],
+            conf: vec![],
+        }
     }
 
     // open the control device via /dev/zfs
@@ -46,6 +449,23 @@ impl Handle {
         Handle::open_dev("/dev/zfs")
     }
 
+    // make a new handle on the same control device (dup'ing the fd), with its own command
+    // buffer, for use on another thread
+    pub fn try_clone(&self) -> IOResult<Handle> {
+        Ok(Handle::with_transport(self.dev.try_clone()?))
+    }
+
+    // a cleanup fd for tmp_snapshot(), from the transport
+    pub fn cleanup_fd(&self) -> IOResult<OwnedFd> {
+        self.dev.cleanup_fd()
+    }
+
+    // give up the handle, keeping only where its commands were going
+    #[cfg(feature = "tokio")]
+    pub(crate) fn into_transport(self) -> Box<dyn Transport> {
+        self.dev
+    }
+
     // most of the zfs ioctls have a common form: fill out a couple of details
     // inside the (enormous, mostly obsolete) command structure, submit it,
     // then explode the returned nvlist. this is nice for us, as we can
@@ -54,41 +474,169 @@ impl Handle {
     // helper: reset the handle state ready for the next command
     fn reset(&mut self) {
         self.cmd = Default::default();
-        self.cmd.nvlist_dst = self.buf.as_ptr();
+        self.cmd.nvlist_dst = self.buf.as_mut_ptr();
         self.cmd.nvlist_dst_size = self.buf.len() as u64;
     }
 
-    // helper: invoke the command
+    // helpers: fill the fixed-size name, value and string buffers in the command. these check
+    // that the whole thing fits, NUL and all, so an overlong name is an error and not a panic
+    fn set_name(&mut self, name: &CStr) -> IOCResult {
+        fits(name, self.cmd.name.len())?;
+        self.cmd.set_name(name);
+        Ok(())
+    }
+
+    fn set_value(&mut self, value: &CStr) -> IOCResult {
+        fits(value, self.cmd.value.len())?;
+        self.cmd.set_value(value);
+        Ok(())
+    }
+
+    fn set_string(&mut self, string: &CStr) -> IOCResult {
+        fits(string, self.cmd.string.len())?;
+        self.cmd.set_string(string);
+        Ok(())
+    }
+
+    // helper: the value buffer, as the kernel left it
+    fn read_value_cstr(&self) -> &CStr {
+        self.cmd.value()
+    }
+
+    // helper: pack a list into our src buffer and point the command at it
+    fn set_src(&mut self, list: &PairList) -> IOCResult {
+        self.src = nvpair::pack(list)?;
+        self.cmd.nvlist_src = self.src.as_ptr();
+        self.cmd.nvlist_src_size = self.src.len() as u64;
+        Ok(())
+    }
+
+    // helper: pack a list into our conf buffer and point the command at it. the legacy ioctls
+    // that take a config (pool create, vdev add/attach, etc) read it from here, not from
+    // nvlist_src
+    fn set_conf(&mut self, list: &PairList) -> IOCResult {
+        self.conf = nvpair::pack(list)?;
+        self.cmd.nvlist_conf = self.conf.as_ptr();
+        self.cmd.nvlist_conf_size = self.conf.len() as u64;
+        Ok(())
+    }
+
+    // how results come back varies by ioctl:
+    //
+    //   - most fill the dst nvlist (stats, props, configs, and everything new-style)
+    //   - the legacy ones put single values in the command: cookie (iterator position, vdev
+    //     state after set_state), obj (next_obj), value (dsobj_to_dsname), objset_stats
+    //   - errors come back as the errno from the ioctl itself. ENOMEM with nvlist_dst_size
+    //     changed means the dst buffer was too small, and the size is what it needs to be
+    //
+    // the ioctl's own return value is never anything but 0 or an error, so nothing looks at it
+
+    // helper: invoke the command, when all that matters is that it worked. a signal arriving
+    // while the kernel waits (eg for a txg to sync) fails the ioctl with EINTR. if the command
+    // is restartable() it's just run again, but not forever, in case the signals never stop
     fn invoke(&mut self, req: c_ulong) -> IOCResult {
-        sys::zfs_ioctl(&mut self.dev, req, &mut self.cmd)?;
+        let retries = if restartable(req) { EINTR_RETRIES } else { 0 };
+        let mut tries = 0;
+        loop {
+            match self.dev.ioctl(req, &mut self.cmd) {
+                Err(e) if e.kind() == io::ErrorKind::Interrupted && tries < retries => tries += 1,
+                r => {
+                    r?;
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    // helper: invoke a command that moves a stream through a file descriptor, exactly once.
+    // by the time one of these is interrupted, some of the stream may have gone, so running
+    // it again would lose or repeat it. EINTR goes back to the caller
+    fn invoke_stream(&mut self, req: c_ulong) -> IOCResult {
+        self.dev.ioctl(req, &mut self.cmd)?;
         Ok(())
     }
 
     // helper: invoke, explode the result list and return it
     fn invoke_list(&mut self, req: c_ulong) -> IOCResultList {
         self.invoke(req)?;
-        let nvbuf = &self.buf[0..self.cmd.nvlist_dst_size as usize];
-        Ok(nvpair::parse(nvbuf)?)
+        self.dst_list()
+    }
+
+    // helper: invoke a command that reports failures in an errlist (see PartialFailure). the
+    // kernel can return one whether the command as a whole failed or not, so it's looked for
+    // either way. asked is every name the command was given, so the ones that worked can be
+    // told apart. with no errlist, this is the same as invoke()
+    fn invoke_errlist<'a>(
+        &mut self,
+        req: c_ulong,
+        asked: impl IntoIterator<Item = &'a CStr>,
+    ) -> IOCResult {
+        let res = self.invoke(req);
+        // the size is only changed when a list is written back
+        let failed = if self.cmd.nvlist_dst_size < self.buf.len() as u64 {
+            self.dst_list().map(|l| errlist(&l)).unwrap_or_default()
+        } else {
+            vec![]
+        };
+        if failed.is_empty() {
+            res?;
+            return Ok(());
+        }
+        let succeeded = asked
+            .into_iter()
+            .filter(|n| !failed.iter().any(|(f, _)| f.as_c_str() == *n))
+            .map(CString::from)
+            .collect();
+        Err(Box::new(PartialFailure { succeeded, failed }))
+    }
+
+    // helper: explode the result list from the last command
+    fn dst_list(&self) -> IOCResultList {
+        // a transport can report more than we gave it; don't trust it
+        let size = self.cmd.nvlist_dst_size;
+        if size > self.buf.len() as u64 {
+            return Err(io::Error::from(io::ErrorKind::InvalidData).into());
+        }
+        Ok(nvpair::parse_slice(&self.buf[..size as usize])?)
+    }
+
+    // helper: reset, setup named object, invoke
+    fn ioc_name(&mut self, req: c_ulong, cname: &CStr) -> IOCResult {
+        self.reset();
+        self.set_name(cname)?;
+        self.invoke(req)
     }
 
     // helper: reset, setup named object, invoke, return nvlist
     fn ioc_name_list(&mut self, req: c_ulong, cname: &CStr) -> IOCResultList {
         self.reset();
-        let name = cname.to_bytes_with_nul();
-        self.cmd.name[..name.len()].copy_from_slice(&name);
+        self.set_name(cname)?;
         self.invoke_list(req)
     }
 
     // helper: reset, setup named object+cookie, invoke, return name+nvlist+cookie
-    fn ioc_name_list_cookie(&mut self, req: c_ulong, cname: &CStr, cookie: u64) -> IOCResultIter {
+    // simple asks for no property list, which the kernel then doesn't write back at all
+    fn ioc_name_list_cookie(
+        &mut self,
+        req: c_ulong,
+        cname: &CStr,
+        cookie: u64,
+        simple: bool,
+    ) -> IOCResultIter {
         self.reset();
-        let name = cname.to_bytes_with_nul();
-        self.cmd.name[..name.len()].copy_from_slice(&name);
+        self.set_name(cname)?;
         self.cmd.cookie = cookie;
-        let list = self.invoke_list(req)?;
+        self.cmd.simple = simple.into();
+        let list = if simple {
+            self.invoke(req)?;
+            PairList::new()
+        } else {
+            self.invoke_list(req)?
+        };
         Ok(IterState {
             name: CStr::from_bytes_until_nul(&self.cmd.name)?.into(),
             list,
+            stats: self.cmd.objset_stats(),
             cookie: self.cmd.cookie,
         })
     }
@@ -101,6 +649,15 @@ impl Handle {
         self.invoke_list(sys::ZFS_IOC_POOL_CONFIGS)
     }
 
+    // ask the kernel what it would make of a pool, from a config assembled from its devices'
+    // labels, without importing it. what comes back is the config it would import, with the
+    // vdev states and stats filled in (like zpool import with no arguments)
+    pub fn pool_tryimport(&mut self, config: &PairList) -> IOCResultList {
+        self.reset();
+        self.set_conf(config)?;
+        self.invoke_list(sys::ZFS_IOC_POOL_TRYIMPORT)
+    }
+
     // per-pool ioctls
 
     // get pool stats (iostat counters, config, features, real mixed bag)
@@ -113,6 +670,162 @@ impl Handle {
         self.ioc_name_list(sys::ZFS_IOC_POOL_GET_PROPS, pool)
     }
 
+    // set pool properties (like zpool set). features are enabled by setting "feature@<name>" to
+    // the u64 0
+    pub fn pool_set_props(&mut self, pool: &CStr, props: &PairList) -> IOCResult {
+        self.reset();
+        self.set_name(pool)?;
+        self.set_src(props)?;
+        self.invoke(sys::ZFS_IOC_POOL_SET_PROPS)
+    }
+
+    // raise the pool's on-disk version. it can't go down, and 5000 (SPA_VERSION_FEATURES) is
+    // the last; after that, new things come as features
+    pub fn pool_upgrade(&mut self, pool: &CStr, version: u64) -> IOCResult {
+        self.reset();
+        self.set_name(pool)?;
+        self.cmd.cookie = version;
+        self.invoke(sys::ZFS_IOC_POOL_UPGRADE)
+    }
+
+    // write out everything dirty in the pool and wait for the txg to sync. with force, sync
+    // even if there's nothing to write
+    pub fn pool_sync(&mut self, pool: &CStr, force: bool) -> IOCResult {
+        let mut args = PairList::new();
+        args.add("force", PairValue::BooleanValue(force));
+        self.reset();
+        self.set_name(pool)?;
+        self.set_src(&args)?;
+        self.invoke(sys::ZFS_IOC_POOL_SYNC)
+    }
+
+    // block until there's no more of activity going on in the pool, returning whether there
+    // was any to wait for. tag picks out one vdev (by guid) for initialize and trim, or one
+    // removal or replace; None waits for all of them
+    pub fn wait(
+        &mut self,
+        pool: &CStr,
+        activity: WaitActivity,
+        tag: Option<u64>,
+    ) -> Result<bool, Box<dyn Error>> {
+        let mut args = PairList::new();
+        args.add("wait_activity", PairValue::Int32(activity.into()));
+        if let Some(tag) = tag {
+            args.add("wait_tag", PairValue::UInt64(tag));
+        }
+        self.reset();
+        self.set_name(pool)?;
+        self.set_src(&args)?;
+        let out = self.invoke_list(sys::ZFS_IOC_WAIT)?;
+        Ok(matches!(
+            out.get("waited").map(|p| p.value()),
+            Some(PairValue::BooleanValue(true))
+        ))
+    }
+
+    // give the pool a new random guid (eg after cloning its disks, so both can be imported)
+    pub fn pool_reguid(&mut self, pool: &CStr) -> IOCResult {
+        self.ioc_name(sys::ZFS_IOC_POOL_REGUID, pool)
+    }
+
+    // the name of the dataset with the given object id in the pool's meta-objset (as found in
+    // the error log, or in objset_stats' "objsetid")
+    pub fn dsobj_to_dsname(&mut self, pool: &CStr, obj: u64) -> Result<CString, Box<dyn Error>> {
+        self.reset();
+        self.set_name(pool)?;
+        self.cmd.obj = obj;
+        self.invoke(sys::ZFS_IOC_DSOBJ_TO_DSNAME)?;
+        Ok(self.read_value_cstr().into())
+    }
+
+    // per-vdev ioctls
+
+    // add new top-level vdevs (or log, cache or spare devices) to the pool. config is a root
+    // vdev, like the pool's vdev_tree. with check_ashift, refuse to add vdevs whose ashift
+    // doesn't match the pool
+    pub fn vdev_add(&mut self, pool: &CStr, config: &PairList, check_ashift: bool) -> IOCResult {
+        self.reset();
+        self.set_name(pool)?;
+        self.cmd.flags = check_ashift.into();
+        self.set_conf(config)?;
+        self.invoke(sys::ZFS_IOC_VDEV_ADD)
+    }
+
+    // remove a top-level vdev, or a log, cache or spare device. top-level removal copies the data
+    // off in the background; progress is in the pool's "removal_stats"
+    pub fn vdev_remove(&mut self, pool: &CStr, guid: u64) -> IOCResult {
+        self.reset();
+        self.set_name(pool)?;
+        self.cmd.guid = guid;
+        self.invoke(sys::ZFS_IOC_VDEV_REMOVE)
+    }
+
+    // attach new_vdev (a root vdev with a single leaf child) to the existing vdev, making or
+    // extending a mirror. if replacing, the existing vdev is replaced and detached once the
+    // resilver finishes. returns once the resilver is queued; it runs in the background
+    pub fn vdev_attach(
+        &mut self,
+        pool: &CStr,
+        existing_guid: u64,
+        new_vdev: &PairList,
+        replacing: bool,
+    ) -> IOCResult {
+        self.reset();
+        self.set_name(pool)?;
+        self.cmd.guid = existing_guid;
+        self.cmd.cookie = replacing.into();
+        self.set_conf(new_vdev)?;
+        self.invoke(sys::ZFS_IOC_VDEV_ATTACH)
+    }
+
+    // change a vdev's state, returning the state it ended up in. for online, that may not be
+    // healthy (eg if the device is still missing). only online sends the new state back in
+    // the cookie; for the rest it's read from the pool's vdev stats, which costs another fetch
+    pub fn vdev_set_state(
+        &mut self,
+        pool: &CStr,
+        guid: u64,
+        new_state: VdevStateCmd,
+    ) -> Result<VdevState, Box<dyn Error>> {
+        self.reset();
+        self.set_name(pool)?;
+        self.cmd.guid = guid;
+        self.cmd.cookie = new_state.cookie();
+        self.cmd.obj = new_state.obj();
+        self.invoke(sys::ZFS_IOC_VDEV_SET_STATE)?;
+        if let VdevStateCmd::Online { .. } = new_state {
+            return Ok(self.cmd.cookie.into());
+        }
+
+        let stats = self.pool_stats(pool)?;
+        let top = stats
+            .get_list("vdev_tree")
+            .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))?;
+        let mut vds = vec![top];
+        while let Some(vd) = vds.pop() {
+            if vd.get_u64("guid") == Some(guid) {
+                // vdev_stats_t, whose second field is the state
+                let state = vd
+                    .get_u64_slice("vdev_stats")
+                    .and_then(|vs| vs.get(1).copied())
+                    .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))?;
+                return Ok(state.into());
+            }
+            for key in ["children", "spares", "l2cache"] {
+                vds.extend(vd.get_list_slice(key).into_iter().flatten());
+            }
+        }
+        Err(io::Error::from(io::ErrorKind::NotFound).into())
+    }
+
+    // detach a vdev from a mirror (or a replacing/spare vdev)
+    pub fn vdev_detach(&mut self, pool: &CStr, guid: u64) -> IOCResult {
+        self.reset();
+        self.set_name(pool)?;
+        self.cmd.guid = guid;
+        self.invoke(sys::ZFS_IOC_VDEV_DETACH)
+    }
+
     // per-dataset ioctls
 
     // get dataset properties (like zfs get)
@@ -120,8 +833,400 @@ impl Handle {
         self.ioc_name_list(sys::ZFS_IOC_OBJSET_STATS, objset)
     }
 
+    // get dataset properties, and the fixed stats (guid, txg, origin, etc) that come with them
+    pub fn objset_stats_full(
+        &mut self,
+        objset: &CStr,
+    ) -> Result<(PairList, ObjsetStats), Box<dyn Error>> {
+        let list = self.ioc_name_list(sys::ZFS_IOC_OBJSET_STATS, objset)?;
+        Ok((list, self.cmd.objset_stats()))
+    }
+
+    // get POSIX layer properties (version, normalization, utf8only, casesensitivity, acltype)
+    pub fn objset_zplprops(&mut self, objset: &CStr) -> IOCResultList {
+        self.ioc_name_list(sys::ZFS_IOC_OBJSET_ZPLPROPS, objset)
+    }
+
+    // get properties received with zfs recv, separate from local overrides
+    pub fn objset_recvd_props(&mut self, objset: &CStr) -> IOCResultList {
+        self.ioc_name_list(sys::ZFS_IOC_OBJSET_RECVD_PROPS, objset)
+    }
+
+    // get a bookmark's properties (guid, createtxg, creation, and redact_snaps if it's a
+    // redaction bookmark), each a list with its value under "value", as for a dataset
+    pub fn get_bookmark_props(&mut self, bookmark: &CStr) -> IOCResultList {
+        self.ioc_name_list(sys::ZFS_IOC_GET_BOOKMARK_PROPS, bookmark)
+    }
+
+    // destroy bookmarks (full names, dataset#bookmark), all in pool. ones that are already
+    // gone are passed over, as the kernel does; any that can't be destroyed make the error a
+    // PartialFailure naming them
+    pub fn destroy_bookmarks(&mut self, pool: &CStr, bookmarks: &[&CStr]) -> IOCResult {
+        let mut args = PairList::new();
+        bookmarks
+            .iter()
+            .for_each(|b| args.add(b.to_bytes(), PairValue::Boolean));
+        self.reset();
+        self.set_name(pool)?;
+        self.set_src(&args)?;
+        self.invoke_errlist(sys::ZFS_IOC_DESTROY_BOOKMARKS, bookmarks.iter().copied())
+    }
+
+    // get delegated permissions (like zfs allow). the list is keyed by dataset, this one and
+    // any ancestors that have permissions set, and each of those maps "who" keys to lists of
+    // permission names
+    pub fn get_fsacl(&mut self, dataset: &CStr) -> IOCResultList {
+        self.ioc_name_list(sys::ZFS_IOC_GET_FSACL, dataset)
+    }
+
+    // set dataset properties (like zfs set). index and boolean properties take their u64 value,
+    // not the name zfs get shows. each is set on its own, so if some can't be, the rest still
+    // are, and the error is a PartialFailure saying which
+    pub fn set_props(&mut self, dataset: &CStr, props: &PairList) -> IOCResult {
+        self.reset();
+        self.set_name(dataset)?;
+        self.set_src(props)?;
+        self.invoke_errlist(sys::ZFS_IOC_SET_PROP, props.keys())
+    }
+
+    // add delegated permissions (like zfs allow), or with unset, take them away (zfs unallow).
+    // perms is the "who" keys to permission lists part, as for one dataset from get_fsacl
+    pub fn set_fsacl(&mut self, dataset: &CStr, perms: &PairList, unset: bool) -> IOCResult {
+        self.reset();
+        self.set_name(dataset)?;
+        self.set_src(perms)?;
+        self.cmd.perm_action = unset.into();
+        self.invoke(sys::ZFS_IOC_SET_FSACL)
+    }
+
+    // take snapshots (full names, like tank/fs@snap) of any number of datasets in one pool, in
+    // one go: either every one is taken, or none are. if any can't be, the error is a
+    // PartialFailure naming them, and succeeded is the ones that could have been. props are set
+    // on every snapshot
+    pub fn snapshot(&mut self, snaps: &[&CStr], props: Option<&PairList>) -> IOCResult {
+        let pool = pool_of(snaps.iter().copied())?;
+
+        let mut snapl = PairList::new();
+        snaps
+            .iter()
+            .for_each(|snap| snapl.add(snap.to_bytes(), PairValue::Boolean));
+        let mut args = PairList::new();
+        args.add("snaps", PairValue::List(snapl));
+        if let Some(props) = props {
+            args.add("props", PairValue::List(props.clone()));
+        }
+
+        self.reset();
+        self.set_name(&pool)?;
+        self.set_src(&args)?;
+        self.invoke_errlist(sys::ZFS_IOC_SNAPSHOT, snaps.iter().copied())
+    }
+
+    // put holds on snapshots, each (snapshot, tag), like zfs hold. a snapshot can't be
+    // destroyed while it has any holds. all in one pool; if any can't be taken, the error is a
+    // PartialFailure naming the snapshots. with a cleanup fd (see cleanup_fd()), the kernel
+    // releases the holds itself when it's closed, however that happens
+    pub fn hold(&mut self, holds: &[(&CStr, &CStr)], cleanup_fd: Option<BorrowedFd>) -> IOCResult {
+        let mut holdl = PairList::new();
+        holds
+            .iter()
+            .for_each(|(snap, tag)| holdl.add(snap.to_bytes(), PairValue::String((*tag).into())));
+        let mut args = PairList::new();
+        args.add("holds", PairValue::List(holdl));
+        if let Some(fd) = cleanup_fd {
+            args.add("cleanup_fd", PairValue::Int32(fd.as_raw_fd()));
+        }
+        self.reset();
+        self.set_name(&pool_of(holds.iter().map(|(snap, _)| *snap))?)?;
+        self.set_src(&args)?;
+        self.invoke_errlist(sys::ZFS_IOC_HOLD, holds.iter().map(|(snap, _)| *snap))
+    }
+
+    // take holds off again, each (snapshot, tag), like zfs release
+    pub fn release(&mut self, holds: &[(&CStr, &CStr)]) -> IOCResult {
+        let mut args = PairList::new();
+        for (snap, tag) in holds {
+            let mut tags = args.get_list(snap.to_bytes()).cloned().unwrap_or_default();
+            tags.add(tag.to_bytes(), PairValue::Boolean);
+            args.add(snap.to_bytes(), PairValue::List(tags));
+        }
+        self.reset();
+        self.set_name(&pool_of(holds.iter().map(|(snap, _)| *snap))?)?;
+        self.set_src(&args)?;
+        self.invoke_errlist(sys::ZFS_IOC_RELEASE, holds.iter().map(|(snap, _)| *snap))
+    }
+
+    // take a snapshot of dataset that only lasts as long as cleanup_fd (see cleanup_fd()) is
+    // open. the name is prefix and a unique suffix, and the short name (after the @) is
+    // returned. the snapshot is held, and destroyed as soon as the fd is closed
+    pub fn tmp_snapshot(
+        &mut self,
+        dataset: &CStr,
+        prefix: &CStr,
+        cleanup_fd: &impl AsRawFd,
+    ) -> Result<CString, Box<dyn Error>> {
+        self.reset();
+        self.set_name(dataset)?;
+        self.set_value(prefix)?;
+        self.cmd.cleanup_fd = cleanup_fd.as_raw_fd();
+        self.invoke(sys::ZFS_IOC_TMP_SNAPSHOT)?;
+        Ok(self.read_value_cstr().into())
+    }
+
+    // make a redaction bookmark for snap (the part after the #, in the same dataset): a record
+    // of the blocks in snap that are also in any of redact_snaps, which must be clones (or
+    // snapshots of clones) of it. a redacted send from the bookmark leaves those blocks out
+    pub fn redact(&mut self, snap: &CStr, bookmark: &CStr, redact_snaps: &[&CStr]) -> IOCResult {
+        let mut snaps = PairList::new();
+        redact_snaps
+            .iter()
+            .for_each(|s| snaps.add(s.to_bytes(), PairValue::Boolean));
+        let mut args = PairList::new();
+        args.add("bookname", PairValue::String(bookmark.into()));
+        args.add("snapnv", PairValue::List(snaps));
+        self.reset();
+        self.set_name(snap)?;
+        self.set_src(&args)?;
+        self.invoke(sys::ZFS_IOC_REDACT)
+    }
+
+    // after a top-level vdev is removed, rewrite the dataset's block pointers to point past the
+    // indirect mappings left behind. OpenZFS 0.8 and later keep the mappings for good and
+    // take this as a no-op
+    pub fn remap(&mut self, dataset: &CStr) -> IOCResult {
+        self.reset();
+        self.set_name(dataset)?;
+        self.set_src(&PairList::new())?;
+        self.invoke(sys::ZFS_IOC_REMAP)
+    }
+
+    // ask the kernel to share or unshare a dataset over NFS or SMB. this was how illumos (and
+    // older OpenZFS) published shares; OpenZFS 2.0 and later do it all in userspace, from the
+    // sharenfs and sharesmb properties, and fail this with ENOSYS
+    pub fn share(&mut self, dataset: &CStr, share: &ShareData, op: ShareOp) -> IOCResult {
+        self.reset();
+        self.set_name(dataset)?;
+        self.cmd.share.exportdata = share.export.as_ptr() as u64;
+        self.cmd.share.sharedata = share.options.as_ptr() as u64;
+        self.cmd.share.sharemax = share.options.as_bytes_with_nul().len() as u64;
+        self.cmd.share.sharetype = op.into();
+        self.invoke(sys::ZFS_IOC_SHARE)
+    }
+
+    // add, remove or rename the SMB ACL resources kept for a dataset's SMB shares, in
+    // .zfs/shares under path, its mountpoint. only illumos has these; everywhere else fails
+    // this with ENOTSUP
+    pub fn smb_acl(&mut self, dataset: &CStr, path: &CStr, op: &SmbAclOp) -> IOCResult {
+        self.reset();
+        self.set_name(dataset)?;
+        self.set_value(path)?;
+        self.cmd.cookie = op.cookie();
+        match op {
+            SmbAclOp::Add(resource) | SmbAclOp::Remove(resource) => self.set_string(resource)?,
+            SmbAclOp::Rename { from, to } => {
+                let mut args = PairList::new();
+                args.add("src", PairValue::String(from.clone()));
+                args.add("target", PairValue::String(to.clone()));
+                self.set_src(&args)?;
+            }
+            SmbAclOp::Purge => (),
+        }
+        self.invoke(sys::ZFS_IOC_SMB_ACL)
+    }
+
+    // one user's, group's or project's usage or quota on a dataset, as zfs userspace shows it.
+    // rid is the uid, gid or project id; domain is empty for those, or the SID domain for an
+    // SMB principal. someone with nothing there (or no quota) comes back as 0
+    pub fn userspace_one(
+        &mut self,
+        dataset: &CStr,
+        prop: UserQuotaType,
+        domain: &CStr,
+        rid: u64,
+    ) -> Result<u64, Box<dyn Error>> {
+        self.reset();
+        self.set_name(dataset)?;
+        self.set_value(domain)?;
+        self.cmd.guid = rid;
+        self.cmd.objset_type = prop.into();
+        self.invoke(sys::ZFS_IOC_USERSPACE_ONE)?;
+        Ok(self.cmd.cookie)
+    }
+
+    // write the object changes from one snapshot to a later one of the same dataset to out_fd,
+    // as a stream of diff records (see read_diff()). blocks until the whole stream is written,
+    // so if out_fd is a pipe, something else must be reading it
+    pub fn diff(&mut self, snap: &CStr, from: &CStr, out_fd: &impl AsRawFd) -> IOCResult {
+        self.reset();
+        self.set_name(snap)?;
+        self.set_value(from)?;
+        self.cmd.cookie = out_fd.as_raw_fd() as u64;
+        self.invoke_stream(sys::ZFS_IOC_DIFF)
+    }
+
+    // how many bytes a send of snap to out_fd has written so far, for showing progress (like
+    // zfs send -v). the send is found by the number of its output fd, and only sends from this
+    // process count. the send ioctl blocks until it's done, so this has to be called from
+    // another thread, on another handle (see try_clone()). ENOENT if there's no such send
+    pub fn send_progress(
+        &mut self,
+        snap: &CStr,
+        out_fd: &impl AsRawFd,
+    ) -> Result<u64, Box<dyn Error>> {
+        self.reset();
+        self.set_name(snap)?;
+        self.cmd.cookie = out_fd.as_raw_fd() as u64;
+        self.invoke(sys::ZFS_IOC_SEND_PROGRESS)?;
+        Ok(self.cmd.cookie)
+    }
+
+    // receive a send stream from in_fd as the snapshot snap (fs@snap), creating the filesystem
+    // if it's a full stream. the begin record is read off the front here, unless the caller
+    // already has it (to see what the stream is first) and passes it as begin; either way the
+    // kernel reads the rest, so if in_fd is a pipe, something else must be writing it. a
+    // compound stream (zfs send -R) is a series of streams, which the kernel can't take in one
+    // go, so it's refused with Unsupported. props are set on the received filesystem, and
+    // origin makes it a clone of that snapshot. with force, the filesystem is rolled back to
+    // its latest snapshot first. with resumable, a receive that's cut short keeps what it got,
+    // and the filesystem's receive_resume_token property says where to send the rest from. the
+    // result list has "read_bytes", and "error_flags" and "errors" if any props couldn't be set
+    #[allow(clippy::too_many_arguments)]
+    pub fn recv(
+        &mut self,
+        snap: &CStr,
+        begin: Option<&[u8]>,
+        in_fd: &impl AsFd,
+        props: Option<&PairList>,
+        origin: Option<&CStr>,
+        force: bool,
+        resumable: bool,
+    ) -> IOCResultList {
+        let at = snap
+            .to_bytes()
+            .iter()
+            .position(|&c| c == b'@')
+            .ok_or_else(|| io::Error::from(io::ErrorKind::InvalidInput))?;
+        let fsname = CString::new(&snap.to_bytes()[..at])?;
+
+        let begin = match begin {
+            Some(begin) => begin.to_vec(),
+            None => {
+                let mut begin = vec![0; BEGIN_RECORD_SIZE];
+                File::from(in_fd.as_fd().try_clone_to_owned()?).read_exact(&mut begin)?;
+                begin
+            }
+        };
+        if parse_stream_header(&begin)?.compound {
+            return Err(io::Error::from(io::ErrorKind::Unsupported).into());
+        }
+
+        let mut args = PairList::new();
+        args.add("snapname", PairValue::String(snap.into()));
+        if let Some(props) = props {
+            args.add("props", PairValue::List(props.clone()));
+        }
+        if let Some(origin) = origin {
+            args.add("origin", PairValue::String(origin.into()));
+        }
+        args.add("begin_record", PairValue::ByteArray(begin));
+        args.add("input_fd", PairValue::Int32(in_fd.as_fd().as_raw_fd()));
+        if force {
+            args.add("force", PairValue::Boolean);
+        }
+        if resumable {
+            args.add("resumable", PairValue::Boolean);
+        }
+
+        self.reset();
+        self.set_name(&fsname)?;
+        self.set_src(&args)?;
+        self.invoke_stream(sys::ZFS_IOC_RECV_NEW)?;
+        self.dst_list()
+    }
+
+    // fault injection ioctls (like zinject)
+
+    // start injecting the fault described by record into the pool, returning its id for
+    // clear_fault(). flags are ZINJECT_* bits; 0x2 (ZINJECT_FLUSH_ARC) empties the ARC first,
+    // so reads of cached data go to disk and hit the fault
+    pub fn inject_fault(
+        &mut self,
+        pool: &CStr,
+        record: &ZInjectRecord,
+        flags: u64,
+    ) -> Result<u64, Box<dyn Error>> {
+        self.reset();
+        self.set_name(pool)?;
+        self.cmd.set_inject_record(record);
+        self.cmd.guid = flags;
+        self.invoke(sys::ZFS_IOC_INJECT_FAULT)?;
+        Ok(self.cmd.guid)
+    }
+
+    // stop an injection started by inject_fault()
+    pub fn clear_fault(&mut self, id: u64) -> IOCResult {
+        self.reset();
+        self.cmd.guid = id;
+        self.invoke(sys::ZFS_IOC_CLEAR_FAULT)
+    }
+
+    // the next active injection after id, across all pools: its id, pool and record. start
+    // from 0 and feed each id back in; None means there are no more
+    pub fn inject_list_next(&mut self, id: u64) -> IOCResultFault {
+        self.reset();
+        self.cmd.guid = id;
+        match self.invoke(sys::ZFS_IOC_INJECT_LIST_NEXT) {
+            Ok(()) => Ok(Some((
+                self.cmd.guid,
+                self.cmd.name().into(),
+                self.cmd.inject_record().clone(),
+            ))),
+            Err(e) => {
+                let ioe = e.downcast::<io::Error>()?;
+                ioe.raw_os_error().filter(|n| *n == 2).ok_or(ioe)?; // ENOENT
+                Ok(None)
+            }
+        }
+    }
+
+    // find the next allocated object in the dataset after prev_obj. start from 0 and feed each
+    // result back in; None means there are no more
+    pub fn next_obj(
+        &mut self,
+        dataset: &CStr,
+        prev_obj: u64,
+    ) -> Result<Option<u64>, Box<dyn Error>> {
+        self.reset();
+        self.set_name(dataset)?;
+        self.cmd.obj = prev_obj;
+        match self.invoke(sys::ZFS_IOC_NEXT_OBJ) {
+            Ok(()) => Ok(Some(self.cmd.obj)),
+            Err(e) => {
+                let ioe = e.downcast::<io::Error>()?;
+                ioe.raw_os_error().filter(|n| *n == 3).ok_or(ioe)?; // ESRCH
+                Ok(None)
+            }
+        }
+    }
+
     // dataset iterator ioctls
     pub fn dataset_list_next(&mut self, dataset: &CStr, cookie: u64) -> IOCResultIter {
-        self.ioc_name_list_cookie(sys::ZFS_IOC_DATASET_LIST_NEXT, dataset, cookie)
+        self.ioc_name_list_cookie(sys::ZFS_IOC_DATASET_LIST_NEXT, dataset, cookie, false)
+    }
+
+    // the same, but with only the name and stats (whose typ says what it is), not the
+    // properties, which are most of the kernel's work for each one
+    pub fn dataset_list_next_simple(&mut self, dataset: &CStr, cookie: u64) -> IOCResultIter {
+        self.ioc_name_list_cookie(sys::ZFS_IOC_DATASET_LIST_NEXT, dataset, cookie, true)
+    }
+
+    // snapshot iterator; returns the full snapshot name (dataset@snap)
+    pub fn snapshot_list_next(&mut self, dataset: &CStr, cookie: u64) -> IOCResultIter {
+        self.ioc_name_list_cookie(sys::ZFS_IOC_SNAPSHOT_LIST_NEXT, dataset, cookie, false)
+    }
+
+    // the same, with only the name. the stats aren't filled in
+    pub fn snapshot_list_next_simple(&mut self, dataset: &CStr, cookie: u64) -> IOCResultIter {
+        self.ioc_name_list_cookie(sys::ZFS_IOC_SNAPSHOT_LIST_NEXT, dataset, cookie, true)
     }
 }
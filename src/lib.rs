@@ -4,10 +4,23 @@
 
 // Copyright (c) 2023, Rob Norris <robn@despairlabs.com>
 
+// without std, only the nvlist parser is built; everything that talks to the kernel needs it
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "tokio")]
+pub mod aio;
+#[cfg(feature = "std")]
 pub mod ioc;
+#[cfg(feature = "std")]
 pub mod nvenums;
-mod nvpair;
+pub mod nvpair;
+#[cfg(feature = "std")]
 pub mod nvtypes;
+#[cfg(feature = "std")]
 mod sys;
-mod util;
+#[cfg(feature = "std")]
+pub mod util;
+#[cfg(feature = "std")]
 pub mod zfs;
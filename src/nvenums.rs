@@ -5,6 +5,7 @@
 // Copyright (c) 2023, Rob Norris <robn@despairlabs.com>
 
 use std::ffi::CStr;
+use std::os::raw::c_int;
 
 #[derive(Debug, Clone, Copy)]
 pub enum VdevType {
@@ -46,3 +47,276 @@ impl<T: ?Sized + AsRef<CStr>> From<&T> for VdevType {
         }
     }
 }
+
+// which allocation class a top-level vdev serves, from its "alloc_bias". vdevs without one
+// hold ordinary pool data
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AllocBias {
+    Log,
+    Special,
+    Dedup,
+    Unknown,
+}
+
+impl<T: ?Sized + AsRef<CStr>> From<&T> for AllocBias {
+    fn from(s: &T) -> Self {
+        match s.as_ref().to_string_lossy().as_ref() {
+            "log" => AllocBias::Log,
+            "special" => AllocBias::Special,
+            "dedup" => AllocBias::Dedup,
+            _ => AllocBias::Unknown,
+        }
+    }
+}
+
+// zfs_canmount_type_t. noauto can be mounted, but only by asking for it; zfs mount -a (and so
+// boot) passes it over
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CanMount {
+    Off,
+    On,
+    NoAuto,
+}
+
+impl From<u64> for CanMount {
+    fn from(n: u64) -> Self {
+        match n {
+            0 => CanMount::Off,
+            2 => CanMount::NoAuto,
+            _ => CanMount::On,
+        }
+    }
+}
+
+impl From<CanMount> for u64 {
+    fn from(c: CanMount) -> Self {
+        match c {
+            CanMount::Off => 0,
+            CanMount::On => 1,
+            CanMount::NoAuto => 2,
+        }
+    }
+}
+
+// zfs_userquota_prop_t: what to look up for a user, group or project. the obj ones count
+// objects (files, directories, etc) rather than bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UserQuotaType {
+    UserUsed = 0,
+    UserQuota = 1,
+    GroupUsed = 2,
+    GroupQuota = 3,
+    UserObjUsed = 4,
+    UserObjQuota = 5,
+    GroupObjUsed = 6,
+    GroupObjQuota = 7,
+    ProjectUsed = 8,
+    ProjectQuota = 9,
+    ProjectObjUsed = 10,
+    ProjectObjQuota = 11,
+}
+
+impl From<UserQuotaType> for u64 {
+    fn from(t: UserQuotaType) -> Self {
+        t as u64
+    }
+}
+
+// zpool_wait_activity_t: the background work ioc::Handle::wait() can wait for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WaitActivity {
+    Discard = 0, // the checkpoint being discarded
+    Free = 1,    // space from destroyed datasets being freed
+    Initialize = 2,
+    Replace = 3,
+    Remove = 4,
+    Resilver = 5,
+    Scrub = 6,
+    Trim = 7,
+    RaidzExpand = 8,
+}
+
+impl From<WaitActivity> for i32 {
+    fn from(a: WaitActivity) -> Self {
+        a as i32
+    }
+}
+
+// pool_state_t, as written in a device's label
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PoolState {
+    Active,
+    Exported,
+    Destroyed,
+    Spare,
+    L2cache,
+    Uninitialized,
+    Unavail,
+    PotentiallyActive,
+    Unknown,
+}
+
+impl From<u64> for PoolState {
+    fn from(n: u64) -> Self {
+        match n {
+            0 => PoolState::Active,
+            1 => PoolState::Exported,
+            2 => PoolState::Destroyed,
+            3 => PoolState::Spare,
+            4 => PoolState::L2cache,
+            5 => PoolState::Uninitialized,
+            6 => PoolState::Unavail,
+            7 => PoolState::PotentiallyActive,
+            _ => PoolState::Unknown,
+        }
+    }
+}
+
+// vdev_state_t
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VdevState {
+    Unknown,
+    Closed,
+    Offline,
+    Removed,
+    CantOpen,
+    Faulted,
+    Degraded,
+    Healthy,
+}
+
+impl From<u64> for VdevState {
+    fn from(n: u64) -> Self {
+        match n {
+            1 => VdevState::Closed,
+            2 => VdevState::Offline,
+            3 => VdevState::Removed,
+            4 => VdevState::CantOpen,
+            5 => VdevState::Faulted,
+            6 => VdevState::Degraded,
+            7 => VdevState::Healthy,
+            _ => VdevState::Unknown,
+        }
+    }
+}
+
+// pool feature state, as zpool get shows it. derived from the refcount in the pool's
+// "feature_stats": absent means disabled, zero is enabled, anything else is active
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeatureState {
+    Disabled,
+    Enabled,
+    Active,
+}
+
+impl From<Option<u64>> for FeatureState {
+    fn from(refcount: Option<u64>) -> Self {
+        match refcount {
+            None => FeatureState::Disabled,
+            Some(0) => FeatureState::Enabled,
+            Some(_) => FeatureState::Active,
+        }
+    }
+}
+
+// feature guids known to OpenZFS 2.2 (spa_feature_table, module/zcommon/zfeature_common.c).
+// features a pool has enabled are always listed in its stats; this is how we know about the
+// ones it doesn't
+pub(crate) const FEATURES: &[&str] = &[
+    "com.delphix:async_destroy",
+    "com.delphix:empty_bpobj",
+    "org.illumos:lz4_compress",
+    "com.joyent:multi_vdev_crash_dump",
+    "com.delphix:spacemap_histogram",
+    "com.delphix:enabled_txg",
+    "com.delphix:hole_birth",
+    "com.delphix:zpool_checkpoint",
+    "com.delphix:spacemap_v2",
+    "com.delphix:extensible_dataset",
+    "com.delphix:bookmarks",
+    "com.joyent:filesystem_limits",
+    "com.delphix:embedded_data",
+    "com.delphix:livelist",
+    "com.delphix:log_spacemap",
+    "org.open-zfs:large_blocks",
+    "org.zfsonlinux:large_dnode",
+    "org.illumos:sha512",
+    "org.illumos:skein",
+    "org.illumos:edonr",
+    "org.zfsonlinux:userobj_accounting",
+    "com.datto:bookmark_v2",
+    "com.datto:encryption",
+    "org.zfsonlinux:project_quota",
+    "com.delphix:device_removal",
+    "com.delphix:obsolete_counts",
+    "org.zfsonlinux:allocation_classes",
+    "com.datto:resilver_defer",
+    "com.delphix:redaction_bookmarks",
+    "com.delphix:redacted_datasets",
+    "com.delphix:bookmark_written",
+    "org.openzfs:device_rebuild",
+    "org.freebsd:zstd_compress",
+    "org.openzfs:draid",
+    "org.openzfs:zilsaxattr",
+    "com.delphix:head_errlog",
+    "org.openzfs:blake3",
+    "com.fudosecurity:block_cloning",
+    "com.klarasystems:vdev_zaps_v2",
+    "com.delphix:redaction_list_spill",
+];
+
+// enum dmu_objset_type. what kind of thing an objset holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObjsetType {
+    None,
+    Meta,
+    Zfs,  // filesystem
+    Zvol, // volume
+    Other,
+    Any,
+    Unknown,
+}
+
+impl From<c_int> for ObjsetType {
+    fn from(n: c_int) -> Self {
+        match n {
+            0 => ObjsetType::None,
+            1 => ObjsetType::Meta,
+            2 => ObjsetType::Zfs,
+            3 => ObjsetType::Zvol,
+            4 => ObjsetType::Other,
+            5 => ObjsetType::Any,
+            _ => ObjsetType::Unknown,
+        }
+    }
+}
+
+// the "type" property in the objset stats list is the same enum, as a u64
+impl From<u64> for ObjsetType {
+    fn from(n: u64) -> Self {
+        c_int::try_from(n).map_or(ObjsetType::Unknown, ObjsetType::from)
+    }
+}
+
+// where a dataset property value came from. derived from the "source" key in the property
+// nvlist, the same way libzfs does it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PropSource {
+    None,              // no source; read-only or statistic
+    Default,           // not set anywhere, default value
+    Local,             // set on this dataset
+    Inherited(String), // inherited, from the named dataset
+    Received,          // arrived with zfs recv
+}
+
+impl PropSource {
+    pub(crate) fn new(source: Option<&CStr>, dataset: &CStr) -> PropSource {
+        match source {
+            None => PropSource::None,
+            Some(s) if s.is_empty() => PropSource::Default,
+            Some(s) if s.to_bytes() == b"$recvd" => PropSource::Received, // ZPROP_SOURCE_VAL_RECVD
+            Some(s) if s == dataset => PropSource::Local,
+            Some(s) => PropSource::Inherited(s.to_string_lossy().to_string()),
+        }
+    }
+}
@@ -4,15 +4,23 @@
 
 // Copyright (c) 2023, Rob Norris <robn@despairlabs.com>
 
-use desert::FromBytesLE;
-use num_derive::FromPrimitive;
-use num_traits::FromPrimitive;
-use std::ffi::{CStr, CString};
-use std::fmt;
+// everything here needs only core and alloc, so it builds without std (see the "std" feature).
+// with std, there's also parse() and parse_full() to read from anything that implements Read
+
+use alloc::borrow::Cow;
+use alloc::ffi::CString;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cell::Cell;
+use core::ffi::CStr;
+use core::fmt;
+use core::mem::size_of;
+#[cfg(feature = "std")]
 use std::io::{self, Read};
 
 // data_type_t from include/sys/nvpair.h
-#[derive(Debug, FromPrimitive)]
+#[derive(Debug)]
 enum PairType {
     Boolean = 1,
     Byte = 2,
@@ -43,7 +51,46 @@ enum PairType {
     Double = 27,
 }
 
-#[derive(Debug, Clone)]
+impl TryFrom<i32> for PairType {
+    type Error = ParseError;
+
+    fn try_from(n: i32) -> Result<Self, Self::Error> {
+        Ok(match n {
+            1 => PairType::Boolean,
+            2 => PairType::Byte,
+            3 => PairType::Int16,
+            4 => PairType::UInt16,
+            5 => PairType::Int32,
+            6 => PairType::UInt32,
+            7 => PairType::Int64,
+            8 => PairType::UInt64,
+            9 => PairType::String,
+            10 => PairType::ByteArray,
+            11 => PairType::Int16Array,
+            12 => PairType::UInt16Array,
+            13 => PairType::Int32Array,
+            14 => PairType::UInt32Array,
+            15 => PairType::Int64Array,
+            16 => PairType::UInt64Array,
+            17 => PairType::StringArray,
+            18 => PairType::HiResTime,
+            19 => PairType::NVList,
+            20 => PairType::NVListArray,
+            21 => PairType::BooleanValue,
+            22 => PairType::Int8,
+            23 => PairType::UInt8,
+            24 => PairType::BooleanArray,
+            25 => PairType::Int8Array,
+            26 => PairType::UInt8Array,
+            27 => PairType::Double,
+            _ => return Err(ParseError::UnknownPairType(n)),
+        })
+    }
+}
+
+// values are equal if they're the same type and hold the same thing; a UInt32(1) is not a
+// UInt64(1). arrays compare element by element, lists as for PairList
+#[derive(Debug, Clone, PartialEq)]
 pub enum PairValue {
     Boolean,
     Byte(u8),
@@ -58,7 +105,7 @@ pub enum PairValue {
     Int16Array(Vec<i16>),
     UInt16Array(Vec<u16>),
     Int32Array(Vec<i32>),
-    UInt32Array(Vec<i32>),
+    UInt32Array(Vec<u32>),
     Int64Array(Vec<i64>),
     UInt64Array(Vec<u64>),
     StringArray(Vec<CString>),
@@ -72,9 +119,13 @@ pub enum PairValue {
     Int8Array(Vec<i8>),
     UInt8Array(Vec<u8>),
     Double(f64),
+
+    // a type newer than this code. raw is the value exactly as it was in the buffer, in
+    // whatever encoding that was
+    Unknown { type_code: i32, raw: Vec<u8> },
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Pair(CString, PairValue);
 
 impl From<Pair> for (CString, PairValue) {
@@ -84,6 +135,11 @@ impl From<Pair> for (CString, PairValue) {
 }
 
 impl Pair {
+    // panics if the key contains a NUL
+    pub fn new<T: Into<Vec<u8>>>(key: T, value: PairValue) -> Pair {
+        Pair(CString::new(key).expect("nvpair key contains NUL"), value)
+    }
+
     pub fn key(&self) -> &CStr {
         self.0.as_c_str()
     }
@@ -125,12 +181,102 @@ impl Pair {
             _ => None,
         }
     }
+
+    // a string value, borrowed if it's valid UTF-8, with any bad bytes replaced if not
+    pub fn as_str(&self) -> Option<Cow<'_, str>> {
+        match self.1 {
+            PairValue::String(ref s) => Some(s.to_string_lossy()),
+            _ => None,
+        }
+    }
 }
 
-#[derive(Debug, Clone)]
+// anything a pair can be looked up by. keys are only ever compared byte for byte, so a &CStr
+// (or &str) the caller already has is used as it is, with no CString made for each lookup. a
+// key with a NUL in it can't be in a list, so it just finds nothing
+pub trait PairKey {
+    fn key_bytes(&self) -> &[u8];
+}
+
+impl PairKey for str {
+    fn key_bytes(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
+impl PairKey for String {
+    fn key_bytes(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
+impl PairKey for [u8] {
+    fn key_bytes(&self) -> &[u8] {
+        self
+    }
+}
+
+impl PairKey for Vec<u8> {
+    fn key_bytes(&self) -> &[u8] {
+        self
+    }
+}
+
+impl PairKey for CStr {
+    fn key_bytes(&self) -> &[u8] {
+        self.to_bytes()
+    }
+}
+
+impl PairKey for CString {
+    fn key_bytes(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
+impl<T: PairKey + ?Sized> PairKey for &T {
+    fn key_bytes(&self) -> &[u8] {
+        (**self).key_bytes()
+    }
+}
+
+#[derive(Debug, Clone, Default)]
 pub struct PairList(Vec<Pair>);
 
 impl PairList {
+    pub fn new() -> PairList {
+        PairList(vec![])
+    }
+
+    // add a pair at the end, or if there's already one with the same key, put it in that one's
+    // place (NV_UNIQUE_NAME). panics if the key contains a NUL
+    pub fn add<T: Into<Vec<u8>>>(&mut self, key: T, value: PairValue) {
+        let pair = Pair::new(key, value);
+        match self.0.iter().position(|p| p.0 == pair.0) {
+            Some(i) => self.0[i] = pair,
+            None => self.0.push(pair),
+        }
+    }
+
+    // a list of these keys and values, added in order as with add(). for making lists by hand,
+    // eg a made-up config to test against. panics if a key contains a NUL
+    pub fn from_pairs<K, I>(pairs: I) -> PairList
+    where
+        K: Into<Vec<u8>>,
+        I: IntoIterator<Item = (K, PairValue)>,
+    {
+        let mut l = PairList::new();
+        pairs.into_iter().for_each(|(k, v)| l.add(k, v));
+        l
+    }
+
+    // add every pair from other, as with add(). where both have the same key, other's pair
+    // wins, and takes the place of this one; the rest of other's are added at the end, in
+    // order. eg start from defaults, then merge in what the user asked for
+    pub fn merge(&mut self, other: PairList) {
+        other.into_iter().for_each(|p| self.add(p.0, p.1));
+    }
+
     pub fn pairs(&self) -> impl Iterator<Item = &Pair> {
         self.0.iter()
     }
@@ -138,48 +284,379 @@ impl PairList {
         self.0.iter().map(|p| p.0.as_ref())
     }
 
+    // the first pair with this name. a list parsed with NV_UNIQUE_NAME_TYPE can have the same
+    // name more than once with different types; the typed getters below skip any of the wrong
+    // type, so they find the right one either way
     pub fn get<T>(&self, t: T) -> Option<&Pair>
     where
-        T: Into<Vec<u8>>,
+        T: PairKey,
     {
-        CString::new(t)
-            .ok()
-            .and_then(|key| self.pairs().filter(|p| p.0 == key).next())
+        let key = t.key_bytes();
+        self.pairs().find(|p| p.0.as_bytes() == key)
+    }
+
+    // helper: the first pair with this name that f can take a value from
+    fn get_with<'a, T, R>(&'a self, t: T, f: impl Fn(&'a Pair) -> Option<R>) -> Option<R>
+    where
+        T: PairKey,
+    {
+        let key = t.key_bytes();
+        self.pairs().filter(|p| p.0.as_bytes() == key).find_map(f)
     }
 
     pub fn get_list<T>(&self, t: T) -> Option<&PairList>
     where
-        T: Into<Vec<u8>>,
+        T: PairKey,
     {
-        self.get(t).and_then(|p| p.as_list())
+        self.get_with(t, |p| p.as_list())
     }
 
     pub fn get_list_slice<T>(&self, t: T) -> Option<&[PairList]>
     where
-        T: Into<Vec<u8>>,
+        T: PairKey,
     {
-        self.get(t).and_then(|p| p.as_list_slice())
+        self.get_with(t, |p| p.as_list_slice())
     }
 
     pub fn get_u64<T>(&self, t: T) -> Option<u64>
     where
-        T: Into<Vec<u8>>,
+        T: PairKey,
     {
-        self.get(t).and_then(|p| p.to_u64())
+        self.get_with(t, |p| p.to_u64())
     }
 
     pub fn get_u64_slice<T>(&self, t: T) -> Option<&[u64]>
     where
-        T: Into<Vec<u8>>,
+        T: PairKey,
     {
-        self.get(t).and_then(|p| p.as_u64_slice())
+        self.get_with(t, |p| p.as_u64_slice())
     }
 
     pub fn get_c_string<T>(&self, t: T) -> Option<CString>
     where
-        T: Into<Vec<u8>>,
+        T: PairKey,
+    {
+        self.get_with(t, |p| p.to_c_string())
+    }
+
+    pub fn get_str<T>(&self, t: T) -> Option<Cow<'_, str>>
+    where
+        T: PairKey,
     {
-        self.get(t).and_then(|p| p.to_c_string())
+        self.get_with(t, |p| p.as_str())
+    }
+}
+
+// helper: write a slice of displayable things inline, like [1, 2, 3]
+fn fmt_slice<T: fmt::Display>(f: &mut fmt::Formatter, s: &[T]) -> fmt::Result {
+    f.write_str("[")?;
+    for (i, v) in s.iter().enumerate() {
+        if i > 0 {
+            f.write_str(", ")?;
+        }
+        write!(f, "{}", v)?;
+    }
+    f.write_str("]")
+}
+
+// scalars and arrays render on one line; lists render as their (multiline) PairList form
+impl fmt::Display for PairValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PairValue::Boolean => Ok(()),
+            PairValue::Byte(n) => write!(f, "{}", n),
+            PairValue::Int16(n) => write!(f, "{}", n),
+            PairValue::UInt16(n) => write!(f, "{}", n),
+            PairValue::Int32(n) => write!(f, "{}", n),
+            PairValue::UInt32(n) => write!(f, "{}", n),
+            PairValue::Int64(n) => write!(f, "{}", n),
+            PairValue::UInt64(n) => write!(f, "{}", n),
+            PairValue::String(s) => write!(f, "{}", s.to_string_lossy()),
+            PairValue::ByteArray(v) => fmt_slice(f, v),
+            PairValue::Int16Array(v) => fmt_slice(f, v),
+            PairValue::UInt16Array(v) => fmt_slice(f, v),
+            PairValue::Int32Array(v) => fmt_slice(f, v),
+            PairValue::UInt32Array(v) => fmt_slice(f, v),
+            PairValue::Int64Array(v) => fmt_slice(f, v),
+            PairValue::UInt64Array(v) => fmt_slice(f, v),
+            PairValue::StringArray(v) => fmt_slice(
+                f,
+                &v.iter().map(|s| s.to_string_lossy()).collect::<Vec<_>>(),
+            ),
+            PairValue::HiResTime(n) => write!(f, "{}", n),
+            PairValue::List(l) => write!(f, "{}", l),
+            PairValue::ListArray(v) => v.iter().try_for_each(|l| write!(f, "{}", l)),
+            PairValue::BooleanValue(b) => write!(f, "{}", b),
+            PairValue::Int8(n) => write!(f, "{}", n),
+            PairValue::UInt8(n) => write!(f, "{}", n),
+            PairValue::BooleanArray(v) => fmt_slice(f, v),
+            PairValue::Int8Array(v) => fmt_slice(f, v),
+            PairValue::UInt8Array(v) => fmt_slice(f, v),
+            PairValue::Double(n) => write!(f, "{}", n),
+            PairValue::Unknown { type_code, raw } => {
+                write!(f, "<unknown type {}, {} bytes>", type_code, raw.len())
+            }
+        }
+    }
+}
+
+impl PairList {
+    // render one line per pair, indenting nested lists by four spaces per level
+    fn fmt_indent(&self, f: &mut fmt::Formatter, depth: usize) -> fmt::Result {
+        let indent = "    ".repeat(depth);
+        for pair in self.pairs() {
+            let key = pair.key().to_string_lossy();
+            match pair.value() {
+                PairValue::Boolean => writeln!(f, "{}{}", indent, key)?,
+                PairValue::List(l) => {
+                    writeln!(f, "{}{}", indent, key)?;
+                    l.fmt_indent(f, depth + 1)?;
+                }
+                PairValue::ListArray(v) => {
+                    for (i, l) in v.iter().enumerate() {
+                        writeln!(f, "{}{}[{}]", indent, key, i)?;
+                        l.fmt_indent(f, depth + 1)?;
+                    }
+                }
+                v => writeln!(f, "{}{} = {}", indent, key, v)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+// zfs-style tree dump, eg:
+//
+//   name = tank
+//   vdev_tree
+//       type = root
+//       children[0]
+//           type = disk
+impl fmt::Display for PairList {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_indent(f, 0)
+    }
+}
+
+// lists are equal if they have the same pairs, in any order. a list without NV_UNIQUE_NAME
+// can have a name more than once, so each pair is matched to a different one in the other
+// list, and they must have the same number of each
+impl PartialEq for PairList {
+    fn eq(&self, other: &Self) -> bool {
+        if self.0.len() != other.0.len() {
+            return false;
+        }
+        let mut used = vec![false; other.0.len()];
+        self.pairs().all(|p| {
+            let found = other
+                .pairs()
+                .enumerate()
+                .position(|(i, op)| !used[i] && op.0 == p.0 && op.1 == p.1);
+            match found {
+                Some(i) => {
+                    used[i] = true;
+                    true
+                }
+                None => false,
+            }
+        })
+    }
+}
+
+// one difference between two lists, from PairList::diff()
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    Added(CString, PairValue),
+    Removed(CString, PairValue),
+    Modified(CString, PairValue, PairValue), // key, old, new
+}
+
+impl PairList {
+    // what changed going from this list to other: keys only here are removed, keys only in
+    // other are added, and keys in both with different values are modified. a key that's in a
+    // list more than once is matched up in order: the first here with the first there, and so
+    // on, and any extra are removed or added. nested lists are compared whole, not descended
+    // into
+    pub fn diff(&self, other: &PairList) -> Vec<Change> {
+        let mut changes = vec![];
+        for (p, n) in self.numbered() {
+            match other.nth_named(&p.0, n) {
+                None => changes.push(Change::Removed(p.0.clone(), p.1.clone())),
+                Some(op) if op.1 != p.1 => {
+                    changes.push(Change::Modified(p.0.clone(), p.1.clone(), op.1.clone()))
+                }
+                Some(_) => (),
+            }
+        }
+        other
+            .numbered()
+            .filter(|(op, n)| self.nth_named(&op.0, *n).is_none())
+            .for_each(|(op, _)| changes.push(Change::Added(op.0.clone(), op.1.clone())));
+        changes
+    }
+
+    // helper: each pair, with how many before it have the same name
+    fn numbered(&self) -> impl Iterator<Item = (&Pair, usize)> {
+        self.pairs()
+            .enumerate()
+            .map(|(i, p)| (p, self.0[..i].iter().filter(|q| q.0 == p.0).count()))
+    }
+
+    // helper: the nth pair (from 0) with this name
+    fn nth_named(&self, key: &CStr, n: usize) -> Option<&Pair> {
+        self.pairs().filter(|p| p.0.as_c_str() == key).nth(n)
+    }
+}
+
+impl FromIterator<Pair> for PairList {
+    fn from_iter<I: IntoIterator<Item = Pair>>(iter: I) -> Self {
+        let mut l = PairList::new();
+        iter.into_iter().for_each(|p| l.add(p.0, p.1));
+        l
+    }
+}
+
+impl IntoIterator for PairList {
+    type Item = Pair;
+    type IntoIter = vec::IntoIter<Pair>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+// returned when a PairValue can't be converted to the requested type
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TryFromPairValueError(());
+
+impl fmt::Display for TryFromPairValueError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("pair value has wrong type for conversion")
+    }
+}
+
+impl core::error::Error for TryFromPairValueError {}
+
+// conversions to plain Rust types. integers widen from any smaller type of the same signedness,
+// but never change sign
+impl TryFrom<&PairValue> for u64 {
+    type Error = TryFromPairValueError;
+    fn try_from(v: &PairValue) -> Result<Self, Self::Error> {
+        match *v {
+            PairValue::Byte(n) => Ok(n.into()),
+            PairValue::UInt8(n) => Ok(n.into()),
+            PairValue::UInt16(n) => Ok(n.into()),
+            PairValue::UInt32(n) => Ok(n.into()),
+            PairValue::UInt64(n) => Ok(n),
+            _ => Err(TryFromPairValueError(())),
+        }
+    }
+}
+
+impl TryFrom<&PairValue> for i64 {
+    type Error = TryFromPairValueError;
+    fn try_from(v: &PairValue) -> Result<Self, Self::Error> {
+        match *v {
+            PairValue::Int8(n) => Ok(n.into()),
+            PairValue::Int16(n) => Ok(n.into()),
+            PairValue::Int32(n) => Ok(n.into()),
+            PairValue::Int64(n) => Ok(n),
+            PairValue::HiResTime(n) => Ok(n),
+            _ => Err(TryFromPairValueError(())),
+        }
+    }
+}
+
+// a valueless boolean is true by its presence
+impl TryFrom<&PairValue> for bool {
+    type Error = TryFromPairValueError;
+    fn try_from(v: &PairValue) -> Result<Self, Self::Error> {
+        match *v {
+            PairValue::Boolean => Ok(true),
+            PairValue::BooleanValue(b) => Ok(b),
+            _ => Err(TryFromPairValueError(())),
+        }
+    }
+}
+
+// lossy, like AutoString
+impl TryFrom<&PairValue> for String {
+    type Error = TryFromPairValueError;
+    fn try_from(v: &PairValue) -> Result<Self, Self::Error> {
+        match v {
+            PairValue::String(s) => Ok(s.to_string_lossy().into_owned()),
+            _ => Err(TryFromPairValueError(())),
+        }
+    }
+}
+
+impl TryFrom<&PairValue> for Vec<u64> {
+    type Error = TryFromPairValueError;
+    fn try_from(v: &PairValue) -> Result<Self, Self::Error> {
+        match v {
+            PairValue::UInt64Array(s) => Ok(s.clone()),
+            _ => Err(TryFromPairValueError(())),
+        }
+    }
+}
+
+// JSON trees, for filtering or reshaping before writing out. lists become objects (if a name
+// appears more than once, the last one wins), arrays become arrays, strings are lossy like
+// AutoString, and a valueless boolean is true. doubles that JSON can't hold (NaN, infinities)
+// and unknown types become null
+#[cfg(feature = "json")]
+impl From<&PairValue> for serde_json::Value {
+    fn from(v: &PairValue) -> Self {
+        use serde_json::Value;
+        fn array<T: Copy + Into<Value>>(s: &[T]) -> Value {
+            Value::Array(s.iter().map(|&n| n.into()).collect())
+        }
+        let string = |s: &CStr| Value::String(s.to_string_lossy().into());
+        match v {
+            PairValue::Boolean => Value::Bool(true),
+            PairValue::BooleanValue(b) => Value::Bool(*b),
+            PairValue::Byte(n) | PairValue::UInt8(n) => (*n).into(),
+            PairValue::Int8(n) => (*n).into(),
+            PairValue::Int16(n) => (*n).into(),
+            PairValue::UInt16(n) => (*n).into(),
+            PairValue::Int32(n) => (*n).into(),
+            PairValue::UInt32(n) => (*n).into(),
+            PairValue::Int64(n) | PairValue::HiResTime(n) => (*n).into(),
+            PairValue::UInt64(n) => (*n).into(),
+            PairValue::Double(n) => (*n).into(),
+            PairValue::String(s) => string(s),
+            PairValue::ByteArray(s) | PairValue::UInt8Array(s) => array(s),
+            PairValue::Int8Array(s) => array(s),
+            PairValue::Int16Array(s) => array(s),
+            PairValue::UInt16Array(s) => array(s),
+            PairValue::Int32Array(s) => array(s),
+            PairValue::UInt32Array(s) => array(s),
+            PairValue::Int64Array(s) => array(s),
+            PairValue::UInt64Array(s) => array(s),
+            PairValue::BooleanArray(s) => array(s),
+            PairValue::StringArray(s) => Value::Array(s.iter().map(|s| string(s)).collect()),
+            PairValue::List(l) => l.into(),
+            PairValue::ListArray(s) => Value::Array(s.iter().map(Value::from).collect()),
+            PairValue::Unknown { .. } => Value::Null,
+        }
+    }
+}
+
+#[cfg(feature = "json")]
+impl From<&PairList> for serde_json::Value {
+    fn from(l: &PairList) -> Self {
+        serde_json::Value::Object(
+            l.pairs()
+                .map(|p| (p.key().to_string_lossy().into(), p.value().into()))
+                .collect(),
+        )
+    }
+}
+
+#[cfg(feature = "json")]
+impl PairList {
+    pub fn to_json(&self) -> serde_json::Value {
+        self.into()
     }
 }
 
@@ -187,72 +664,192 @@ impl PairList {
 pub enum ParseError {
     InvalidEncoding,
     InvalidEndian,
-    ShortRead,
-    UnterminatedString,
+    InvalidVersion,
+    InvalidFlags,
+    NestingTooDeep,
+    ShortRead(usize),          // offset into the buffer of the thing that didn't fit
+    UnterminatedString(usize), // offset of the start of the string
     UnknownPairType(i32),
+    #[cfg(feature = "std")]
     IOError(io::Error),
 }
 
 impl fmt::Display for ParseError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match *self {
-            // XXX get the values in
+        match self {
             ParseError::InvalidEncoding => f.write_str("invalid encoding"),
             ParseError::InvalidEndian => f.write_str("invalid endian"),
-            ParseError::ShortRead => f.write_str("short read"),
-            ParseError::UnterminatedString => f.write_str("unterminated string"),
-            ParseError::UnknownPairType(_) => f.write_str("unknown pair type"),
-            ParseError::IOError(_) => f.write_str("io error"),
+            ParseError::InvalidVersion => f.write_str("invalid version"),
+            ParseError::InvalidFlags => f.write_str("invalid flags"),
+            ParseError::NestingTooDeep => f.write_str("nesting too deep"),
+            ParseError::ShortRead(off) => write!(f, "short read at offset {}", off),
+            ParseError::UnterminatedString(off) => {
+                write!(f, "unterminated string at offset {}", off)
+            }
+            ParseError::UnknownPairType(t) => write!(f, "unknown pair type {}", t),
+            #[cfg(feature = "std")]
+            ParseError::IOError(e) => write!(f, "io error: {}", e),
         }
     }
 }
 
-impl std::error::Error for ParseError {}
+impl core::error::Error for ParseError {}
 
+#[cfg(feature = "std")]
 impl From<io::Error> for ParseError {
     fn from(e: io::Error) -> Self {
         ParseError::IOError(e)
     }
 }
 
-impl From<core::ffi::FromBytesUntilNulError> for ParseError {
-    fn from(_: core::ffi::FromBytesUntilNulError) -> Self {
-        ParseError::UnterminatedString
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PackError {
+    UnknownPairType(CString, i32), // the pair's name, and its type
+}
+
+impl fmt::Display for PackError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PackError::UnknownPairType(name, t) => {
+                write!(f, "can't pack pair {:?} of unknown type {}", name, t)
+            }
+        }
     }
 }
 
-#[derive(PartialEq, Debug)]
+impl core::error::Error for PackError {}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
 pub enum Encoding {
     Native,
     XDR,
 }
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone, Copy)]
 pub enum Endian {
     Big,
     Little,
 }
 
-#[derive(Debug)]
-pub struct Parser;
+// a parsed nvlist, along with the header details that came with it
+#[derive(Debug, Clone)]
+pub struct ParsedNvList {
+    pub encoding: Encoding,
+    pub endian: Endian,
+    pub version: i32,
+    pub flags: u32,
+    pub list: PairList,
+}
+
+impl From<ParsedNvList> for PairList {
+    fn from(p: ParsedNvList) -> Self {
+        p.list
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Parser {
+    base: Cell<usize>, // address of the buffer being parsed, for error offsets
+}
+
+// same limit as userspace libnvpair (nvpair_max_recursion); stops a hostile buffer from
+// recursing us off the end of the stack
+const MAX_DEPTH: usize = 100;
+
+// nvl_nvflag. with NV_UNIQUE_NAME, a name appears at most once in a list. with
+// NV_UNIQUE_NAME_TYPE, it can appear once for each type
+const NV_UNIQUE_NAME: u32 = 0x1;
+const NV_UNIQUE_NAME_TYPE: u32 = 0x2;
+
+// size of nvlist_t: i32 nvl_version, u32 nvl_nvflag, u64 nvl_priv, u32 nvl_flag, i32 nvl_pad
+const NVLIST_T_SIZE: usize = 24;
 
 #[inline(always)]
 fn align(n: usize) -> usize {
     (n + 7) & !7
 }
 
-pub fn parse<R: Read>(mut r: R) -> Result<PairList, ParseError> {
+// parse a packed nvlist, returning just the list. either encoding is accepted, so this reads
+// ioctl results (native) and on-disk lists like zpool.cache (XDR) alike
+#[cfg(feature = "std")]
+pub fn parse<R: Read>(r: R) -> Result<PairList, ParseError> {
+    Ok(parse_full(r)?.list)
+}
+
+// parse a packed nvlist, returning the list and its header
+#[cfg(feature = "std")]
+pub fn parse_full<R: Read>(mut r: R) -> Result<ParsedNvList, ParseError> {
     let mut buf: Vec<u8> = vec![];
     r.read_to_end(&mut buf)?;
-    Parser::new().parse(&buf)
+    parse_full_slice(&buf)
+}
+
+// like parse(), for a list that's already all in memory. doesn't need std
+pub fn parse_slice(buf: &[u8]) -> Result<PairList, ParseError> {
+    Ok(parse_full_slice(buf)?.list)
+}
+
+// like parse_full(), for a list that's already all in memory. doesn't need std
+pub fn parse_full_slice(buf: &[u8]) -> Result<ParsedNvList, ParseError> {
+    Parser::new().parse(buf)
 }
 
+// fixed-size ints, as the native encoding has them (little-endian only, for now)
+trait FromLE: Sized {
+    fn from_le(buf: &[u8]) -> Self;
+}
+
+macro_rules! impl_from_le {
+    ($($t:ty),*) => {
+        $(impl FromLE for $t {
+            fn from_le(buf: &[u8]) -> Self {
+                <$t>::from_le_bytes(buf[..size_of::<$t>()].try_into().unwrap())
+            }
+        })*
+    };
+}
+
+impl_from_le!(u8, i8, u16, i16, u32, i32, u64, i64);
+
 impl Parser {
     pub fn new() -> Parser {
-        Parser
+        Parser::default()
+    }
+
+    // every part of the buffer we look at is a subslice of the whole, so its position is just
+    // how far its start is from the start of the whole
+    fn offset(&self, buf: &[u8]) -> usize {
+        (buf.as_ptr() as usize).wrapping_sub(self.base.get())
+    }
+
+    fn short(&self, buf: &[u8]) -> ParseError {
+        ParseError::ShortRead(self.offset(buf))
+    }
+
+    // room for the elements an array says it has, once it's sure they'd fit in what's left of
+    // the buffer at (at least) min_size bytes each. nelems comes straight off the wire, so a
+    // corrupt count is caught here, before it's looped over or allocated for
+    fn array_vec<T>(&self, buf: &[u8], nelems: i32, min_size: usize) -> Result<Vec<T>, ParseError> {
+        usize::try_from(nelems)
+            .ok()
+            .filter(|&n| n.checked_mul(min_size).is_some_and(|sz| sz <= buf.len()))
+            .map(Vec::with_capacity)
+            .ok_or_else(|| self.short(buf))
+    }
+
+    fn unterminated(&self, buf: &[u8]) -> ParseError {
+        ParseError::UnterminatedString(self.offset(buf))
     }
 
-    fn parse<'a>(&'a self, buf: &'a [u8]) -> Result<PairList, ParseError> {
+    fn parse<'a>(&'a self, buf: &'a [u8]) -> Result<ParsedNvList, ParseError> {
+        self.base.set(buf.as_ptr() as usize);
+
+        // not even the header, eg an ioctl that reported an empty result. everything past here
+        // is checked as it's read
+        if buf.len() < 4 {
+            return Err(self.short(buf));
+        }
+
         let encoding = match buf[0] {
             0 => Encoding::Native,
             1 => Encoding::XDR,
@@ -264,44 +861,108 @@ impl Parser {
             _ => return Err(ParseError::InvalidEndian),
         };
 
-        assert_eq!(encoding, Encoding::Native);
-        assert_eq!(endian, Endian::Little);
-
-        let lbuf = &buf[4..];
+        // XDR is always big-endian on the wire, whatever the header says
+        // XXX only native little-endian for now
+        if encoding == Encoding::Native && endian != Endian::Little {
+            return Err(ParseError::InvalidEndian);
+        }
 
-        let (version, lbuf) = self.parse_int::<i32>(&lbuf)?;
-        let (flags, lbuf) = self.parse_int::<u32>(&lbuf)?;
+        let (version, flags, lbuf) = match encoding {
+            Encoding::Native => self.parse_nvlist_header(&buf[4..])?,
+            Encoding::XDR => self.parse_xdr_nvlist_header(&buf[4..])?,
+        };
 
-        assert_eq!(version, 0); // NV_VERSION
-        assert_eq!(flags, 1); // XXX NV_UNIQUE_NAME|NV_UNIQUE_NAME_TYPE
+        // NV_UNIQUE_NAME, NV_UNIQUE_NAME_TYPE. nothing else is defined
+        if flags & !(NV_UNIQUE_NAME | NV_UNIQUE_NAME_TYPE) != 0 {
+            return Err(ParseError::InvalidFlags);
+        }
 
-        let (l, _) = self.parse_nvlist(&lbuf)?;
-        Ok(l)
+        let (list, _) = match encoding {
+            Encoding::Native => self.parse_nvlist(lbuf, 0)?,
+            Encoding::XDR => self.parse_xdr_nvlist(lbuf, 0)?,
+        };
+        Ok(ParsedNvList {
+            encoding,
+            endian,
+            version,
+            flags,
+            list,
+        })
     }
 
-    fn parse_int<'a, T>(&'a self, buf: &'a [u8]) -> Result<(T, &[u8]), ParseError>
+    fn parse_int<'a, T>(&'a self, buf: &'a [u8]) -> Result<(T, &'a [u8]), ParseError>
     where
-        T: FromBytesLE,
+        T: FromLE,
     {
-        let s = std::mem::size_of::<T>();
+        let s = size_of::<T>();
         if buf.len() < s {
-            return Err(ParseError::ShortRead);
+            return Err(self.short(buf));
         }
-        let v = T::from_bytes_le(&buf).unwrap().1;
+        let v = T::from_le(buf);
         Ok((v, &buf[s..]))
     }
 
-    fn parse_string<'a>(&'a self, buf: &'a [u8]) -> Result<(CString, &[u8]), ParseError> {
-        let cstr = CStr::from_bytes_until_nul(buf)?;
+    fn parse_array<'a, T>(&'a self, buf: &'a [u8], nelems: i32) -> Result<Vec<T>, ParseError>
+    where
+        T: FromLE,
+    {
+        let mut v = self.array_vec(buf, nelems, size_of::<T>())?;
+        let mut pbuf = buf;
+        for _ in 0..nelems {
+            let n;
+            (n, pbuf) = self.parse_int::<T>(pbuf)?;
+            v.push(n);
+        }
+        Ok(v)
+    }
+
+    fn parse_string<'a>(&'a self, buf: &'a [u8]) -> Result<(CString, &'a [u8]), ParseError> {
+        let cstr = CStr::from_bytes_until_nul(buf).map_err(|_| self.unterminated(buf))?;
         let s = align(cstr.to_bytes_with_nul().len());
+        if buf.len() < s {
+            return Err(self.short(buf));
+        }
         Ok((cstr.into(), &buf[s..]))
     }
 
-    fn parse_nvlist<'a>(&'a self, buf: &'a [u8]) -> Result<(PairList, &[u8]), ParseError> {
+    // the version and flags for a list are always the first two ints of a nvlist_t, but where
+    // that is depends on where the list is:
+    //
+    // - top-level: straight after the 4-byte stream header, followed by the pairs
+    // - embedded, native: the whole nvlist_t is copied into the value of the owning pair (for
+    //   NVLIST_ARRAY, after a table of pointers). the pairs are not there; they start after
+    //   the end of the owning pair, that is, where the next pair would have been. the real
+    //   next pair follows the embedded list's terminator
+    // - embedded, XDR: version and flags are inline at the value position, followed by the
+    //   pairs, same as top-level
+    //
+    // so this reads just the header, and parse_nvlist() reads just the pairs, and every kind
+    // of list goes through both
+    fn parse_nvlist_header<'a>(
+        &'a self,
+        buf: &'a [u8],
+    ) -> Result<(i32, u32, &'a [u8]), ParseError> {
+        let (version, buf) = self.parse_int::<i32>(buf)?;
+        let (flags, buf) = self.parse_int::<u32>(buf)?;
+        if version != 0 {
+            // NV_VERSION
+            return Err(ParseError::InvalidVersion);
+        }
+        Ok((version, flags, buf))
+    }
+
+    fn parse_nvlist<'a>(
+        &'a self,
+        buf: &'a [u8],
+        depth: usize,
+    ) -> Result<(PairList, &'a [u8]), ParseError> {
+        if depth > MAX_DEPTH {
+            return Err(ParseError::NestingTooDeep);
+        }
         let mut pairs = vec![];
         let mut nbuf = buf;
         loop {
-            nbuf = match self.parse_pair(nbuf)? {
+            nbuf = match self.parse_pair(nbuf, depth)? {
                 (Some(pair), buf) => {
                     pairs.push(pair);
                     buf
@@ -311,89 +972,584 @@ impl Parser {
         }
     }
 
-    fn parse_pair<'a>(&'a self, buf: &'a [u8]) -> Result<(Option<Pair>, &[u8]), ParseError> {
-        let (len, buf) = self.parse_int::<i32>(&buf)?;
+    fn parse_pair<'a>(
+        &'a self,
+        buf: &'a [u8],
+        depth: usize,
+    ) -> Result<(Option<Pair>, &'a [u8]), ParseError> {
+        let (len, buf) = self.parse_int::<i32>(buf)?;
         if len == 0 {
             return Ok((None, buf));
         }
 
-        let (buf, mut nbuf) = buf.split_at((len - 4) as usize);
+        // len covers the whole pair, including itself
+        if len < 4 {
+            return Err(self.short(buf));
+        }
+        let plen = (len - 4) as usize;
+        if buf.len() < plen {
+            return Err(self.short(buf));
+        }
+        let (buf, mut nbuf) = buf.split_at(plen);
 
-        let (_, buf) = self.parse_int::<i16>(&buf)?; // name_len
-        let (_, buf) = self.parse_int::<i16>(&buf)?; // nvp_reserve
+        let (name_len, buf) = self.parse_int::<i16>(buf)?;
+        let (_, buf) = self.parse_int::<i16>(buf)?; // nvp_reserve
 
-        let (nelems, buf) = self.parse_int::<i32>(&buf)?;
-        let (ityp, buf) = self.parse_int::<i32>(&buf)?;
+        let (nelems, buf) = self.parse_int::<i32>(buf)?;
+        let (ityp, buf) = self.parse_int::<i32>(buf)?;
+
+        // name_len includes the trailing NUL, and must fit in what's left of the pair
+        if name_len < 1 || name_len as usize > buf.len() {
+            return Err(self.short(buf));
+        }
+        let (name, buf) = self.parse_string(buf)?;
+        if name.as_bytes_with_nul().len() != name_len as usize {
+            return Err(self.short(buf));
+        }
 
-        let (name, buf) = self.parse_string(&buf)?;
+        // every element takes at least one byte of value, so anything claiming more elements
+        // than there are bytes left is lying
+        if nelems < 0 || nelems as usize > buf.len() {
+            return Err(self.short(buf));
+        }
 
-        let typ: PairType =
-            FromPrimitive::from_i32(ityp).ok_or(ParseError::UnknownPairType(ityp))?;
+        // a type we don't know. we can't make sense of the value, but the pair's length says
+        // where the next one starts, so keep it as-is and carry on. (if it were some new kind
+        // of embedded list, its pairs would follow this one and we'd misread them; there's no
+        // way to know)
+        let Ok(typ) = PairType::try_from(ityp) else {
+            let data = PairValue::Unknown {
+                type_code: ityp,
+                raw: buf.to_vec(),
+            };
+            return Ok((Some(Pair(name, data)), nbuf));
+        };
 
         //println!("name {:?} nelems {:?} typ {:?}", name, nelems, typ);
 
         let data = match typ {
             PairType::Boolean => PairValue::Boolean,
 
-            PairType::Byte => todo!(),
-            PairType::Int16 => todo!(),
-            PairType::UInt16 => todo!(),
-            PairType::Int32 => todo!(),
-            PairType::UInt32 => todo!(),
-            PairType::Int64 => todo!(),
+            PairType::Byte => PairValue::Byte(self.parse_int::<u8>(buf)?.0),
+            PairType::Int16 => PairValue::Int16(self.parse_int::<i16>(buf)?.0),
+            PairType::UInt16 => PairValue::UInt16(self.parse_int::<u16>(buf)?.0),
+            PairType::Int32 => PairValue::Int32(self.parse_int::<i32>(buf)?.0),
+            PairType::UInt32 => PairValue::UInt32(self.parse_int::<u32>(buf)?.0),
+            PairType::Int64 => PairValue::Int64(self.parse_int::<i64>(buf)?.0),
+            PairType::UInt64 => PairValue::UInt64(self.parse_int::<u64>(buf)?.0),
+            PairType::String => PairValue::String(self.parse_string(buf)?.0),
 
-            PairType::UInt64 => PairValue::UInt64(self.parse_int::<u64>(&buf)?.0),
-            PairType::String => PairValue::String(self.parse_string(&buf)?.0),
+            PairType::ByteArray => PairValue::ByteArray(self.parse_array::<u8>(buf, nelems)?),
+            PairType::Int16Array => PairValue::Int16Array(self.parse_array::<i16>(buf, nelems)?),
+            PairType::UInt16Array => PairValue::UInt16Array(self.parse_array::<u16>(buf, nelems)?),
+            PairType::Int32Array => PairValue::Int32Array(self.parse_array::<i32>(buf, nelems)?),
+            PairType::UInt32Array => PairValue::UInt32Array(self.parse_array::<u32>(buf, nelems)?),
+            PairType::Int64Array => PairValue::Int64Array(self.parse_array::<i64>(buf, nelems)?),
+            PairType::UInt64Array => PairValue::UInt64Array(self.parse_array::<u64>(buf, nelems)?),
 
-            PairType::ByteArray => todo!(),
-            PairType::Int16Array => todo!(),
-            PairType::UInt16Array => todo!(),
-            PairType::Int32Array => todo!(),
-            PairType::UInt32Array => todo!(),
-            PairType::Int64Array => todo!(),
-
-            PairType::UInt64Array => {
-                let mut v = vec![];
-                let mut pbuf = buf;
+            // string arrays are a table of (meaningless) pointers, followed by the strings
+            // themselves, packed end-to-end without alignment
+            PairType::StringArray => {
+                let skip = usize::try_from(nelems)
+                    .ok()
+                    .and_then(|n| n.checked_mul(size_of::<u64>()))
+                    .filter(|&n| n <= buf.len())
+                    .ok_or_else(|| self.short(buf))?;
+                let mut pbuf = &buf[skip..];
+                let mut v = self.array_vec(pbuf, nelems, 1)?; // just a NUL, at the least
                 for _ in 0..nelems {
-                    let n;
-                    (n, pbuf) = self.parse_int::<u64>(&pbuf)?;
-                    v.push(n);
+                    let cstr =
+                        CStr::from_bytes_until_nul(pbuf).map_err(|_| self.unterminated(pbuf))?;
+                    pbuf = &pbuf[cstr.to_bytes_with_nul().len()..];
+                    v.push(cstr.into());
                 }
-                PairValue::UInt64Array(v)
+                PairValue::StringArray(v)
             }
 
-            PairType::StringArray => todo!(),
-            PairType::HiResTime => todo!(),
+            PairType::HiResTime => PairValue::HiResTime(self.parse_int::<i64>(buf)?.0),
 
             // embedded nvlists start at the "next" pair position, rather than at the "value"
-            // position of this pair. the real "next" pair follows after the nvlist
+            // position of this pair. the real "next" pair follows after the nvlist. see
+            // parse_nvlist_header()
             PairType::NVList => {
-                let (l, pbuf) = self.parse_nvlist(&nbuf)?;
+                if buf.len() < NVLIST_T_SIZE {
+                    return Err(self.short(buf));
+                }
+                self.parse_nvlist_header(buf)?;
+                let (l, pbuf) = self.parse_nvlist(nbuf, depth + 1)?;
                 nbuf = pbuf;
                 PairValue::List(l)
             }
             PairType::NVListArray => {
-                let mut v = vec![];
+                // pointer table, then one nvlist_t per element
+                let n = nelems as usize;
+                let skip = n * size_of::<u64>();
+                if buf.len() < skip + n * NVLIST_T_SIZE {
+                    return Err(self.short(buf));
+                }
+                let mut v = Vec::with_capacity(n);
                 let mut pbuf = nbuf;
-                for _ in 0..nelems {
+                for hbuf in buf[skip..].chunks_exact(NVLIST_T_SIZE).take(n) {
+                    self.parse_nvlist_header(hbuf)?;
                     let l;
-                    (l, pbuf) = self.parse_nvlist(&pbuf)?;
+                    (l, pbuf) = self.parse_nvlist(pbuf, depth + 1)?;
                     v.push(l);
                 }
                 nbuf = pbuf;
                 PairValue::ListArray(v)
             }
 
-            PairType::BooleanValue => todo!(),
-            PairType::Int8 => todo!(),
-            PairType::UInt8 => todo!(),
-            PairType::BooleanArray => todo!(),
-            PairType::Int8Array => todo!(),
-            PairType::UInt8Array => todo!(),
-            PairType::Double => todo!(),
+            // boolean_t is an int
+            PairType::BooleanValue => PairValue::BooleanValue(self.parse_int::<i32>(buf)?.0 != 0),
+            PairType::Int8 => PairValue::Int8(self.parse_int::<i8>(buf)?.0),
+            PairType::UInt8 => PairValue::UInt8(self.parse_int::<u8>(buf)?.0),
+            PairType::BooleanArray => PairValue::BooleanArray(
+                self.parse_array::<i32>(buf, nelems)?
+                    .into_iter()
+                    .map(|n| n != 0)
+                    .collect(),
+            ),
+            PairType::Int8Array => PairValue::Int8Array(self.parse_array::<i8>(buf, nelems)?),
+            PairType::UInt8Array => PairValue::UInt8Array(self.parse_array::<u8>(buf, nelems)?),
+            PairType::Double => PairValue::Double(f64::from_bits(self.parse_int::<u64>(buf)?.0)),
         };
 
         Ok((Some(Pair(name, data)), nbuf))
     }
 }
+
+// XDR encoding, as used for nvlists on disk (zpool.cache, vdev labels). unlike native, it's a
+// plain stream: everything is big-endian and padded out to 4 bytes, ints narrower than 4 bytes
+// are widened to 4, strings carry their length instead of a NUL, and embedded lists are written
+// in full at the value position, with no nvlist_t copies or pointer tables
+impl Parser {
+    fn xdr_int<'a>(&'a self, buf: &'a [u8]) -> Result<(i32, &'a [u8]), ParseError> {
+        if buf.len() < 4 {
+            return Err(self.short(buf));
+        }
+        let (n, buf) = buf.split_at(4);
+        Ok((i32::from_be_bytes(n.try_into().unwrap()), buf))
+    }
+
+    fn xdr_hyper<'a>(&'a self, buf: &'a [u8]) -> Result<(u64, &'a [u8]), ParseError> {
+        if buf.len() < 8 {
+            return Err(self.short(buf));
+        }
+        let (n, buf) = buf.split_at(8);
+        Ok((u64::from_be_bytes(n.try_into().unwrap()), buf))
+    }
+
+    // length, then the bytes, padded to 4. like libnvpair, a string stops at the first NUL
+    fn xdr_string<'a>(&'a self, buf: &'a [u8]) -> Result<(CString, &'a [u8]), ParseError> {
+        let (len, buf) = self.xdr_int(buf)?;
+        let len = usize::try_from(len).map_err(|_| self.short(buf))?;
+        let padded = len.checked_add(3).ok_or_else(|| self.short(buf))? & !3;
+        if buf.len() < padded {
+            return Err(self.short(buf));
+        }
+        let s = &buf[..len];
+        let end = s.iter().position(|&c| c == 0).unwrap_or(len);
+        Ok((CString::new(&s[..end]).unwrap(), &buf[padded..]))
+    }
+
+    // xdr_array(): a count, which must agree with the pair's nelems, then the elements, each
+    // size bytes
+    fn xdr_array<'a, T, F>(
+        &'a self,
+        buf: &'a [u8],
+        nelems: i32,
+        size: usize,
+        elem: F,
+    ) -> Result<(Vec<T>, &'a [u8]), ParseError>
+    where
+        F: Fn(&'a [u8]) -> Result<(T, &'a [u8]), ParseError>,
+    {
+        let (count, mut pbuf) = self.xdr_int(buf)?;
+        if count != nelems {
+            return Err(self.short(buf));
+        }
+        let mut v = self.array_vec(pbuf, nelems, size)?;
+        for _ in 0..nelems {
+            let n;
+            (n, pbuf) = elem(pbuf)?;
+            v.push(n);
+        }
+        Ok((v, pbuf))
+    }
+
+    fn parse_xdr_nvlist_header<'a>(
+        &'a self,
+        buf: &'a [u8],
+    ) -> Result<(i32, u32, &'a [u8]), ParseError> {
+        let (version, buf) = self.xdr_int(buf)?;
+        let (flags, buf) = self.xdr_int(buf)?;
+        if version != 0 {
+            // NV_VERSION
+            return Err(ParseError::InvalidVersion);
+        }
+        Ok((version, flags as u32, buf))
+    }
+
+    fn parse_xdr_nvlist<'a>(
+        &'a self,
+        buf: &'a [u8],
+        depth: usize,
+    ) -> Result<(PairList, &'a [u8]), ParseError> {
+        if depth > MAX_DEPTH {
+            return Err(ParseError::NestingTooDeep);
+        }
+        let mut pairs = vec![];
+        let mut nbuf = buf;
+        loop {
+            nbuf = match self.parse_xdr_pair(nbuf, depth)? {
+                (Some(pair), buf) => {
+                    pairs.push(pair);
+                    buf
+                }
+                (None, buf) => return Ok((PairList(pairs), buf)),
+            }
+        }
+    }
+
+    fn parse_xdr_pair<'a>(
+        &'a self,
+        buf: &'a [u8],
+        depth: usize,
+    ) -> Result<(Option<Pair>, &'a [u8]), ParseError> {
+        // encoded size, then the size it would take in memory, which means nothing to us. an
+        // encoded size of zero ends the list
+        let (elen, buf) = self.xdr_int(buf)?;
+        let (_, buf) = self.xdr_int(buf)?;
+        if elen == 0 {
+            return Ok((None, buf));
+        }
+
+        // elen covers the whole pair, including both sizes and any embedded lists
+        if elen < 8 || (elen - 8) as usize > buf.len() {
+            return Err(self.short(buf));
+        }
+        let nbuf = &buf[(elen - 8) as usize..];
+
+        let (name, buf) = self.xdr_string(buf)?;
+        let (ityp, buf) = self.xdr_int(buf)?;
+        let (nelems, buf) = self.xdr_int(buf)?;
+
+        // every element takes at least one byte of value
+        if nelems < 0 || nelems as usize > buf.len() {
+            return Err(self.short(buf));
+        }
+
+        // a type we don't know; skip to the end of the pair, as for native
+        let Ok(typ) = PairType::try_from(ityp) else {
+            let vlen = buf
+                .len()
+                .checked_sub(nbuf.len())
+                .ok_or_else(|| self.short(buf))?;
+            let data = PairValue::Unknown {
+                type_code: ityp,
+                raw: buf[..vlen].to_vec(),
+            };
+            return Ok((Some(Pair(name, data)), nbuf));
+        };
+
+        let int = |buf| self.xdr_int(buf);
+        let hyper = |buf| self.xdr_hyper(buf);
+
+        let (data, buf) = match typ {
+            PairType::Boolean => (PairValue::Boolean, buf),
+
+            PairType::Byte => int(buf).map(|(n, b)| (PairValue::Byte(n as u8), b))?,
+            PairType::Int8 => int(buf).map(|(n, b)| (PairValue::Int8(n as i8), b))?,
+            PairType::UInt8 => int(buf).map(|(n, b)| (PairValue::UInt8(n as u8), b))?,
+            PairType::Int16 => int(buf).map(|(n, b)| (PairValue::Int16(n as i16), b))?,
+            PairType::UInt16 => int(buf).map(|(n, b)| (PairValue::UInt16(n as u16), b))?,
+            PairType::Int32 => int(buf).map(|(n, b)| (PairValue::Int32(n), b))?,
+            PairType::UInt32 => int(buf).map(|(n, b)| (PairValue::UInt32(n as u32), b))?,
+            PairType::BooleanValue => {
+                int(buf).map(|(n, b)| (PairValue::BooleanValue(n != 0), b))?
+            }
+            PairType::Int64 => hyper(buf).map(|(n, b)| (PairValue::Int64(n as i64), b))?,
+            PairType::UInt64 => hyper(buf).map(|(n, b)| (PairValue::UInt64(n), b))?,
+            PairType::HiResTime => hyper(buf).map(|(n, b)| (PairValue::HiResTime(n as i64), b))?,
+            PairType::Double => {
+                hyper(buf).map(|(n, b)| (PairValue::Double(f64::from_bits(n)), b))?
+            }
+            PairType::String => self
+                .xdr_string(buf)
+                .map(|(s, b)| (PairValue::String(s), b))?,
+
+            // xdr_opaque(): just the bytes, padded, with no count
+            PairType::ByteArray => {
+                let n = nelems as usize;
+                let padded = (n + 3) & !3;
+                if buf.len() < padded {
+                    return Err(self.short(buf));
+                }
+                (PairValue::ByteArray(buf[..n].to_vec()), &buf[padded..])
+            }
+
+            PairType::Int8Array => {
+                let (v, b) = self.xdr_array(buf, nelems, 4, int)?;
+                (
+                    PairValue::Int8Array(v.iter().map(|&n| n as i8).collect()),
+                    b,
+                )
+            }
+            PairType::UInt8Array => {
+                let (v, b) = self.xdr_array(buf, nelems, 4, int)?;
+                (
+                    PairValue::UInt8Array(v.iter().map(|&n| n as u8).collect()),
+                    b,
+                )
+            }
+            PairType::Int16Array => {
+                let (v, b) = self.xdr_array(buf, nelems, 4, int)?;
+                (
+                    PairValue::Int16Array(v.iter().map(|&n| n as i16).collect()),
+                    b,
+                )
+            }
+            PairType::UInt16Array => {
+                let (v, b) = self.xdr_array(buf, nelems, 4, int)?;
+                (
+                    PairValue::UInt16Array(v.iter().map(|&n| n as u16).collect()),
+                    b,
+                )
+            }
+            PairType::Int32Array => {
+                let (v, b) = self.xdr_array(buf, nelems, 4, int)?;
+                (PairValue::Int32Array(v), b)
+            }
+            PairType::UInt32Array => {
+                let (v, b) = self.xdr_array(buf, nelems, 4, int)?;
+                (
+                    PairValue::UInt32Array(v.iter().map(|&n| n as u32).collect()),
+                    b,
+                )
+            }
+            PairType::BooleanArray => {
+                let (v, b) = self.xdr_array(buf, nelems, 4, int)?;
+                (
+                    PairValue::BooleanArray(v.iter().map(|&n| n != 0).collect()),
+                    b,
+                )
+            }
+            PairType::Int64Array => {
+                let (v, b) = self.xdr_array(buf, nelems, 8, hyper)?;
+                (
+                    PairValue::Int64Array(v.iter().map(|&n| n as i64).collect()),
+                    b,
+                )
+            }
+            PairType::UInt64Array => {
+                let (v, b) = self.xdr_array(buf, nelems, 8, hyper)?;
+                (PairValue::UInt64Array(v), b)
+            }
+
+            // one string after another, with no count
+            PairType::StringArray => {
+                let mut v = self.array_vec(buf, nelems, 4)?; // a length, at the least
+                let mut pbuf = buf;
+                for _ in 0..nelems {
+                    let s;
+                    (s, pbuf) = self.xdr_string(pbuf)?;
+                    v.push(s);
+                }
+                (PairValue::StringArray(v), pbuf)
+            }
+
+            // embedded lists are complete lists, header and all, right here in the value
+            PairType::NVList => {
+                let (_, _, lbuf) = self.parse_xdr_nvlist_header(buf)?;
+                let (l, pbuf) = self.parse_xdr_nvlist(lbuf, depth + 1)?;
+                (PairValue::List(l), pbuf)
+            }
+            PairType::NVListArray => {
+                // a header and an end marker, at the least
+                let mut v = self.array_vec(buf, nelems, 16)?;
+                let mut pbuf = buf;
+                for _ in 0..nelems {
+                    let l;
+                    (_, _, pbuf) = self.parse_xdr_nvlist_header(pbuf)?;
+                    (l, pbuf) = self.parse_xdr_nvlist(pbuf, depth + 1)?;
+                    v.push(l);
+                }
+                (PairValue::ListArray(v), pbuf)
+            }
+        };
+
+        Ok((Some(Pair(name, data)), buf))
+    }
+}
+
+// pack a list in the native encoding, little-endian, as the kernel expects to receive it. a
+// pair of a type we don't know (PairValue::Unknown) can't be written, and is an error rather
+// than quietly left out
+pub fn pack(list: &PairList) -> Result<Vec<u8>, PackError> {
+    let mut buf = vec![0, 1, 0, 0]; // NV_ENCODE_NATIVE, little-endian
+    buf.extend_from_slice(&0i32.to_le_bytes()); // NV_VERSION
+    buf.extend_from_slice(&NV_UNIQUE_NAME.to_le_bytes());
+    pack_nvlist(&mut buf, list)?;
+    Ok(buf)
+}
+
+// pairs, then the four-byte zero terminator
+fn pack_nvlist(buf: &mut Vec<u8>, list: &PairList) -> Result<(), PackError> {
+    list.pairs().try_for_each(|p| pack_pair(buf, p))?;
+    buf.extend_from_slice(&0i32.to_le_bytes());
+    Ok(())
+}
+
+// the nvlist_t written into the value of a NVLIST or NVLIST_ARRAY pair. only the version and
+// flags mean anything once packed; see Parser::parse_nvlist_header()
+fn pack_nvlist_t(val: &mut Vec<u8>) {
+    val.extend_from_slice(&0i32.to_le_bytes()); // nvl_version
+    val.extend_from_slice(&NV_UNIQUE_NAME.to_le_bytes()); // nvl_nvflag
+    val.extend_from_slice(&[0; NVLIST_T_SIZE - 8]);
+}
+
+fn pack_pair(buf: &mut Vec<u8>, pair: &Pair) -> Result<(), PackError> {
+    fn ints<T, const N: usize>(val: &mut Vec<u8>, v: &[T], f: fn(&T) -> [u8; N]) {
+        v.iter().for_each(|n| val.extend_from_slice(&f(n)));
+    }
+
+    let mut val: Vec<u8> = vec![];
+    let (typ, nelems) = match pair.value() {
+        PairValue::Boolean => (PairType::Boolean, 0),
+        PairValue::Byte(n) => {
+            val.push(*n);
+            (PairType::Byte, 1)
+        }
+        PairValue::Int16(n) => {
+            val.extend_from_slice(&n.to_le_bytes());
+            (PairType::Int16, 1)
+        }
+        PairValue::UInt16(n) => {
+            val.extend_from_slice(&n.to_le_bytes());
+            (PairType::UInt16, 1)
+        }
+        PairValue::Int32(n) => {
+            val.extend_from_slice(&n.to_le_bytes());
+            (PairType::Int32, 1)
+        }
+        PairValue::UInt32(n) => {
+            val.extend_from_slice(&n.to_le_bytes());
+            (PairType::UInt32, 1)
+        }
+        PairValue::Int64(n) => {
+            val.extend_from_slice(&n.to_le_bytes());
+            (PairType::Int64, 1)
+        }
+        PairValue::UInt64(n) => {
+            val.extend_from_slice(&n.to_le_bytes());
+            (PairType::UInt64, 1)
+        }
+        PairValue::String(s) => {
+            val.extend_from_slice(s.as_bytes_with_nul());
+            (PairType::String, 1)
+        }
+        PairValue::ByteArray(v) => {
+            val.extend_from_slice(v);
+            (PairType::ByteArray, v.len())
+        }
+        PairValue::Int16Array(v) => {
+            ints(&mut val, v, |n| n.to_le_bytes());
+            (PairType::Int16Array, v.len())
+        }
+        PairValue::UInt16Array(v) => {
+            ints(&mut val, v, |n| n.to_le_bytes());
+            (PairType::UInt16Array, v.len())
+        }
+        PairValue::Int32Array(v) => {
+            ints(&mut val, v, |n| n.to_le_bytes());
+            (PairType::Int32Array, v.len())
+        }
+        PairValue::UInt32Array(v) => {
+            ints(&mut val, v, |n| n.to_le_bytes());
+            (PairType::UInt32Array, v.len())
+        }
+        PairValue::Int64Array(v) => {
+            ints(&mut val, v, |n| n.to_le_bytes());
+            (PairType::Int64Array, v.len())
+        }
+        PairValue::UInt64Array(v) => {
+            ints(&mut val, v, |n| n.to_le_bytes());
+            (PairType::UInt64Array, v.len())
+        }
+        // pointer table, then the strings end-to-end
+        PairValue::StringArray(v) => {
+            val.resize(v.len() * size_of::<u64>(), 0);
+            v.iter()
+                .for_each(|s| val.extend_from_slice(s.as_bytes_with_nul()));
+            (PairType::StringArray, v.len())
+        }
+        PairValue::HiResTime(n) => {
+            val.extend_from_slice(&n.to_le_bytes());
+            (PairType::HiResTime, 1)
+        }
+        PairValue::List(_) => {
+            pack_nvlist_t(&mut val);
+            (PairType::NVList, 1)
+        }
+        // pointer table, then one nvlist_t per element
+        PairValue::ListArray(v) => {
+            val.resize(v.len() * size_of::<u64>(), 0);
+            v.iter().for_each(|_| pack_nvlist_t(&mut val));
+            (PairType::NVListArray, v.len())
+        }
+        PairValue::BooleanValue(b) => {
+            val.extend_from_slice(&(*b as i32).to_le_bytes());
+            (PairType::BooleanValue, 1)
+        }
+        PairValue::Int8(n) => {
+            val.extend_from_slice(&n.to_le_bytes());
+            (PairType::Int8, 1)
+        }
+        PairValue::UInt8(n) => {
+            val.push(*n);
+            (PairType::UInt8, 1)
+        }
+        PairValue::BooleanArray(v) => {
+            ints(&mut val, v, |b| (*b as i32).to_le_bytes());
+            (PairType::BooleanArray, v.len())
+        }
+        PairValue::Int8Array(v) => {
+            ints(&mut val, v, |n| n.to_le_bytes());
+            (PairType::Int8Array, v.len())
+        }
+        PairValue::UInt8Array(v) => {
+            val.extend_from_slice(v);
+            (PairType::UInt8Array, v.len())
+        }
+        PairValue::Double(n) => {
+            val.extend_from_slice(&n.to_bits().to_le_bytes());
+            (PairType::Double, 1)
+        }
+
+        // we don't know how to write these, and the raw value may not even be native
+        PairValue::Unknown { type_code, .. } => {
+            return Err(PackError::UnknownPairType(pair.key().into(), *type_code))
+        }
+    };
+
+    // nvpair_t is 16 bytes, then the name and value, each padded to 8
+    let name = pair.key().to_bytes_with_nul();
+    let size = 16 + align(name.len()) + align(val.len());
+
+    buf.extend_from_slice(&(size as i32).to_le_bytes());
+    buf.extend_from_slice(&(name.len() as i16).to_le_bytes());
+    buf.extend_from_slice(&0i16.to_le_bytes()); // nvp_reserve
+    buf.extend_from_slice(&(nelems as i32).to_le_bytes());
+    buf.extend_from_slice(&(typ as i32).to_le_bytes());
+    buf.extend_from_slice(name);
+    buf.resize(buf.len() + align(name.len()) - name.len(), 0);
+    buf.extend_from_slice(&val);
+    buf.resize(buf.len() + align(val.len()) - val.len(), 0);
+
+    // embedded lists follow the pair
+    match pair.value() {
+        PairValue::List(l) => pack_nvlist(buf, l),
+        PairValue::ListArray(v) => v.iter().try_for_each(|l| pack_nvlist(buf, l)),
+        _ => Ok(()),
+    }
+}
@@ -4,6 +4,8 @@
 
 // Copyright (c) 2023, Rob Norris <robn@despairlabs.com>
 
+use crate::nvpair::PairList;
+
 // XXX this and other structures like it in fs/zfs.h can be extended with
 //     new versions, but not reduced. so we need to initialise to zero, and
 //     make sure we don't overrun, but its ok to come up short
@@ -51,12 +53,78 @@ pub struct VdevStats {
     pub pspace: u64,
 }
 
-impl From<&[u64]> for VdevStats {
-    fn from(s: &[u64]) -> Self {
-        let count = std::cmp::min(
-            s.len(),
-            std::mem::size_of::<VdevStats>() / std::mem::size_of::<u64>(),
-        );
+// the raw copy below relies on the struct being nothing but u64s, with no padding
+const _: () = assert!(std::mem::size_of::<VdevStats>().is_multiple_of(std::mem::size_of::<u64>()));
+
+const VDEV_STATS_FIELDS: usize = std::mem::size_of::<VdevStats>() / std::mem::size_of::<u64>();
+
+// zio_type_t, the index into ops[] and bytes[]. the kernel reports TRIM under the IOCTL slot,
+// to keep vdev_stat_t the same size
+const ZIO_TYPE_READ: usize = 1;
+const ZIO_TYPE_WRITE: usize = 2;
+const ZIO_TYPE_FREE: usize = 3;
+const ZIO_TYPE_CLAIM: usize = 4;
+const ZIO_TYPE_TRIM: usize = 5; // ZIO_TYPE_IOCTL
+
+impl VdevStats {
+    // decode from the vdev_stats array, returning the stats and the number of fields actually
+    // filled. if that's less than fields(), the ZFS on the other end is older than us and the
+    // remaining fields are zero
+    pub fn from_slice(s: &[u64]) -> (VdevStats, usize) {
+        VdevStats::decode(s)
+    }
+
+    // number of u64 fields in the struct
+    pub const fn fields() -> usize {
+        VDEV_STATS_FIELDS
+    }
+
+    pub fn read_ops(&self) -> u64 {
+        self.ops[ZIO_TYPE_READ]
+    }
+
+    pub fn write_ops(&self) -> u64 {
+        self.ops[ZIO_TYPE_WRITE]
+    }
+
+    pub fn free_ops(&self) -> u64 {
+        self.ops[ZIO_TYPE_FREE]
+    }
+
+    pub fn claim_ops(&self) -> u64 {
+        self.ops[ZIO_TYPE_CLAIM]
+    }
+
+    pub fn trim_ops(&self) -> u64 {
+        self.ops[ZIO_TYPE_TRIM]
+    }
+
+    pub fn read_bytes(&self) -> u64 {
+        self.bytes[ZIO_TYPE_READ]
+    }
+
+    pub fn write_bytes(&self) -> u64 {
+        self.bytes[ZIO_TYPE_WRITE]
+    }
+
+    pub fn free_bytes(&self) -> u64 {
+        self.bytes[ZIO_TYPE_FREE]
+    }
+
+    pub fn claim_bytes(&self) -> u64 {
+        self.bytes[ZIO_TYPE_CLAIM]
+    }
+
+    pub fn trim_bytes(&self) -> u64 {
+        self.bytes[ZIO_TYPE_TRIM]
+    }
+
+    // the struct is nothing but u64s, so the slice can be copied straight in. byte order is
+    // already dealt with: the parser turns the array into host-order u64s whatever the nvlist's
+    // encoding was (native little-endian, or big-endian XDR), so there's nothing to swap here
+    #[cfg(not(feature = "safe-decode"))]
+    fn decode(s: &[u64]) -> (VdevStats, usize) {
+        let count = std::cmp::min(s.len(), VDEV_STATS_FIELDS);
         let mut vs = VdevStats::default();
         unsafe {
             std::ptr::copy_nonoverlapping(
@@ -65,6 +133,254 @@ impl From<&[u64]> for VdevStats {
                 count,
             );
         }
-        vs
+        (vs, count)
+    }
+
+    // field-by-field decode, for auditing the raw copy above. field order must match
+    // vdev_stat_t, same as the struct definition
+    #[cfg(feature = "safe-decode")]
+    fn decode(s: &[u64]) -> (VdevStats, usize) {
+        let count = std::cmp::min(s.len(), VDEV_STATS_FIELDS);
+        let mut it = s.iter().copied();
+        let mut next = || it.next().unwrap_or(0);
+        let vs = VdevStats {
+            timestamp: next(),
+            state: next(),
+            aux: next(),
+            alloc: next(),
+            space: next(),
+            dspace: next(),
+            rsize: next(),
+            esize: next(),
+            ops: std::array::from_fn(|_| next()),
+            bytes: std::array::from_fn(|_| next()),
+            read_errors: next(),
+            write_errors: next(),
+            checksum_errors: next(),
+            initialize_errors: next(),
+            self_healed: next(),
+            scan_removing: next(),
+            scan_processed: next(),
+            fragmentation: next(),
+            initialize_bytes_done: next(),
+            initialize_bytes_est: next(),
+            initialize_state: next(),
+            initialize_action_time: next(),
+            checkpoint_space: next(),
+            resilver_deferred: next(),
+            slow_ios: next(),
+            trim_errors: next(),
+            trim_notsup: next(),
+            trim_bytes_done: next(),
+            trim_bytes_est: next(),
+            trim_state: next(),
+            trim_action_time: next(),
+            rebuild_processed: next(),
+            configured_ashift: next(),
+            logical_ashift: next(),
+            physical_ashift: next(),
+            noalloc: next(),
+            pspace: next(),
+        };
+        (vs, count)
+    }
+}
+
+impl From<&[u64]> for VdevStats {
+    fn from(s: &[u64]) -> Self {
+        VdevStats::from_slice(s).0
+    }
+}
+
+// pool_scan_stat_t, the pool's "scan_stats" (kept in its vdev_tree): the last or current scrub
+// or resilver. fields past the end of an older kernel's array come out as zero
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ScanStats {
+    pub func: u64,  // pool_scan_func_t
+    pub state: u64, // dsl_scan_state_t
+    pub start_time: u64,
+    pub end_time: u64,
+    pub to_examine: u64,
+    pub examined: u64,
+    pub skipped: u64,
+    pub processed: u64, // repaired, for a scrub
+    pub errors: u64,
+    pub pass_exam: u64,
+    pub pass_start: u64,
+    pub pass_scrub_pause: u64,
+    pub pass_scrub_spent_paused: u64,
+    pub pass_issued: u64,
+    pub issued: u64,
+}
+
+impl From<&[u64]> for ScanStats {
+    fn from(s: &[u64]) -> Self {
+        let mut it = s.iter().copied();
+        let mut next = || it.next().unwrap_or(0);
+        ScanStats {
+            func: next(),
+            state: next(),
+            start_time: next(),
+            end_time: next(),
+            to_examine: next(),
+            examined: next(),
+            skipped: next(),
+            processed: next(),
+            errors: next(),
+            pass_exam: next(),
+            pass_start: next(),
+            pass_scrub_pause: next(),
+            pass_scrub_spent_paused: next(),
+            pass_issued: next(),
+            issued: next(),
+        }
+    }
+}
+
+// vdev_stats_ex (ZPOOL_CONFIG_VDEV_STATS_EX). unlike vdev_stat_t, this is not a struct blob but
+// an nvlist with one key per counter or histogram, so it has to be picked apart key by key.
+// missing keys (older ZFS, or stats that don't apply to this vdev) come out as zero or empty.
+//
+// latency histograms have one bucket per power-of-two nanoseconds; size histograms have one
+// bucket per power-of-two bytes
+#[derive(Debug, Default)]
+pub struct VdevStatsEx {
+    // queue depths (vdev_*_active_queue, vdev_*_pend_queue)
+    pub sync_r_active_queue: u64,
+    pub sync_w_active_queue: u64,
+    pub async_r_active_queue: u64,
+    pub async_w_active_queue: u64,
+    pub scrub_active_queue: u64,
+    pub trim_active_queue: u64,
+    pub rebuild_active_queue: u64,
+    pub sync_r_pend_queue: u64,
+    pub sync_w_pend_queue: u64,
+    pub async_r_pend_queue: u64,
+    pub async_w_pend_queue: u64,
+    pub scrub_pend_queue: u64,
+    pub trim_pend_queue: u64,
+    pub rebuild_pend_queue: u64,
+
+    // latency histograms (vdev_*_lat_histo, vdev_*_histo)
+    pub tot_r_lat_histo: Vec<u64>,
+    pub tot_w_lat_histo: Vec<u64>,
+    pub disk_r_lat_histo: Vec<u64>,
+    pub disk_w_lat_histo: Vec<u64>,
+    pub sync_r_lat_histo: Vec<u64>,
+    pub sync_w_lat_histo: Vec<u64>,
+    pub async_r_lat_histo: Vec<u64>,
+    pub async_w_lat_histo: Vec<u64>,
+    pub scrub_lat_histo: Vec<u64>,
+    pub trim_lat_histo: Vec<u64>,
+    pub rebuild_lat_histo: Vec<u64>,
+
+    // request size histograms, individual (vdev_*_ind_*_histo)
+    pub sync_ind_r_histo: Vec<u64>,
+    pub sync_ind_w_histo: Vec<u64>,
+    pub async_ind_r_histo: Vec<u64>,
+    pub async_ind_w_histo: Vec<u64>,
+    pub ind_scrub_histo: Vec<u64>,
+    pub ind_trim_histo: Vec<u64>,
+    pub ind_rebuild_histo: Vec<u64>,
+
+    // request size histograms, aggregated (vdev_*_agg_*_histo)
+    pub sync_agg_r_histo: Vec<u64>,
+    pub sync_agg_w_histo: Vec<u64>,
+    pub async_agg_r_histo: Vec<u64>,
+    pub async_agg_w_histo: Vec<u64>,
+    pub agg_scrub_histo: Vec<u64>,
+    pub agg_trim_histo: Vec<u64>,
+    pub agg_rebuild_histo: Vec<u64>,
+}
+
+impl From<&PairList> for VdevStatsEx {
+    fn from(l: &PairList) -> Self {
+        let n = |k: &str| l.get_u64(k).unwrap_or_default();
+        let h = |k: &str| l.get_u64_slice(k).map(|s| s.to_vec()).unwrap_or_default();
+        VdevStatsEx {
+            sync_r_active_queue: n("vdev_sync_r_active_queue"),
+            sync_w_active_queue: n("vdev_sync_w_active_queue"),
+            async_r_active_queue: n("vdev_async_r_active_queue"),
+            async_w_active_queue: n("vdev_async_w_active_queue"),
+            scrub_active_queue: n("vdev_async_scrub_active_queue"),
+            trim_active_queue: n("vdev_async_trim_active_queue"),
+            rebuild_active_queue: n("vdev_async_rebuild_active_queue"),
+            sync_r_pend_queue: n("vdev_sync_r_pend_queue"),
+            sync_w_pend_queue: n("vdev_sync_w_pend_queue"),
+            async_r_pend_queue: n("vdev_async_r_pend_queue"),
+            async_w_pend_queue: n("vdev_async_w_pend_queue"),
+            scrub_pend_queue: n("vdev_async_scrub_pend_queue"),
+            trim_pend_queue: n("vdev_async_trim_pend_queue"),
+            rebuild_pend_queue: n("vdev_async_rebuild_pend_queue"),
+
+            tot_r_lat_histo: h("vdev_tot_r_lat_histo"),
+            tot_w_lat_histo: h("vdev_tot_w_lat_histo"),
+            disk_r_lat_histo: h("vdev_disk_r_lat_histo"),
+            disk_w_lat_histo: h("vdev_disk_w_lat_histo"),
+            sync_r_lat_histo: h("vdev_sync_r_lat_histo"),
+            sync_w_lat_histo: h("vdev_sync_w_lat_histo"),
+            async_r_lat_histo: h("vdev_async_r_lat_histo"),
+            async_w_lat_histo: h("vdev_async_w_lat_histo"),
+            scrub_lat_histo: h("vdev_scrub_histo"),
+            trim_lat_histo: h("vdev_trim_histo"),
+            rebuild_lat_histo: h("vdev_rebuild_histo"),
+
+            sync_ind_r_histo: h("vdev_sync_ind_r_histo"),
+            sync_ind_w_histo: h("vdev_sync_ind_w_histo"),
+            async_ind_r_histo: h("vdev_async_ind_r_histo"),
+            async_ind_w_histo: h("vdev_async_ind_w_histo"),
+            ind_scrub_histo: h("vdev_ind_scrub_histo"),
+            ind_trim_histo: h("vdev_ind_trim_histo"),
+            ind_rebuild_histo: h("vdev_ind_rebuild_histo"),
+
+            sync_agg_r_histo: h("vdev_sync_agg_r_histo"),
+            sync_agg_w_histo: h("vdev_sync_agg_w_histo"),
+            async_agg_r_histo: h("vdev_async_agg_r_histo"),
+            async_agg_w_histo: h("vdev_async_agg_w_histo"),
+            agg_scrub_histo: h("vdev_agg_scrub_histo"),
+            agg_trim_histo: h("vdev_agg_trim_histo"),
+            agg_rebuild_histo: h("vdev_agg_rebuild_histo"),
+        }
+    }
+}
+
+// the latency at the given percentile of a latency histogram, in nanoseconds: the bucket that
+// the running count first reaches that share of the total in. like zpool iostat -w, a bucket is
+// reported as its lower bound, so the true figure is anywhere up to twice this. buckets past
+// the 64th can't be counted in nanoseconds, and are all u64::MAX
+fn histo_percentile(histo: &[u64], pct: u64) -> u64 {
+    let total: u128 = histo.iter().map(|&n| n as u128).sum();
+    if total == 0 {
+        return 0;
+    }
+    let mut count: u128 = 0;
+    for (i, &n) in histo.iter().enumerate() {
+        count += n as u128;
+        if count * 100 >= total * pct as u128 {
+            return 1u64.checked_shl(i as u32).unwrap_or(u64::MAX);
+        }
+    }
+    unreachable!()
+}
+
+impl VdevStatsEx {
+    // p50, p95 and p99 read latency in nanoseconds, from the total (queued + disk) read latency
+    // histogram. all zero if there have been no reads
+    pub fn read_latency_percentiles(&self) -> (u64, u64, u64) {
+        VdevStatsEx::latency_percentiles(&self.tot_r_lat_histo)
+    }
+
+    // p50, p95 and p99 write latency, as for read_latency_percentiles()
+    pub fn write_latency_percentiles(&self) -> (u64, u64, u64) {
+        VdevStatsEx::latency_percentiles(&self.tot_w_lat_histo)
+    }
+
+    // p50, p95 and p99 from any of the latency histograms above
+    pub fn latency_percentiles(histo: &[u64]) -> (u64, u64, u64) {
+        (
+            histo_percentile(histo, 50),
+            histo_percentile(histo, 95),
+            histo_percentile(histo, 99),
+        )
     }
 }
@@ -4,11 +4,16 @@
 
 // Copyright (c) 2023, Rob Norris <robn@despairlabs.com>
 
+use crate::nvenums::ObjsetType;
 use derivative::Derivative;
+use std::ffi::{CStr, CString};
+use std::fs::File;
 use std::io::Error as IOError;
-use std::os::fd::AsRawFd;
-use std::os::raw::{c_int, c_uint, c_ulong, c_void};
-use std::ptr::null;
+use std::io::ErrorKind as IOErrorKind;
+use std::marker::PhantomData;
+use std::os::fd::{AsRawFd, FromRawFd};
+use std::os::raw::{c_char, c_int, c_uint, c_ulong};
+use std::ptr::{null, null_mut};
 
 // include/sys/fs/zfs.h
 const ZFS_MAX_DATASET_NAME_LEN: usize = 256;
@@ -42,6 +47,37 @@ pub(crate) struct DMUObjectStats {
     origin: [u8; ZFS_MAX_DATASET_NAME_LEN],
 }
 
+// the fixed part of the objset stats, filled in alongside the property list. the same fields as
+// dmu_objset_stats_t, in friendlier types
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObjsetStats {
+    pub num_clones: u64,
+    pub creation_txg: u64,
+    pub guid: u64,
+    pub typ: ObjsetType,
+    pub is_snapshot: bool,
+    pub inconsistent: bool,
+    pub redacted: bool,
+    pub origin: CString, // empty if not a clone
+}
+
+impl From<&DMUObjectStats> for ObjsetStats {
+    fn from(s: &DMUObjectStats) -> Self {
+        ObjsetStats {
+            num_clones: s.num_clones,
+            creation_txg: s.creation_txg,
+            guid: s.guid,
+            typ: s.typ.into(),
+            is_snapshot: s.is_snapshot != 0,
+            inconsistent: s.inconsistent != 0,
+            redacted: s.redacted != 0,
+            origin: CStr::from_bytes_until_nul(&s.origin)
+                .unwrap_or_default()
+                .into(),
+        }
+    }
+}
+
 // struct drr_begin
 #[repr(C)]
 #[derive(Derivative, Debug)]
@@ -58,39 +94,40 @@ pub(crate) struct DMUReplayRecordBegin {
     toname: [u8; MAXNAMELEN],
 }
 
-// zinject_record_t
+// zinject_record_t. which fields mean anything depends on cmd, the kind of injection; use
+// zfs::inject::ZInjectBuilder to get a consistent one
 #[repr(C)]
-#[derive(Derivative, Debug)]
+#[derive(Derivative, Debug, Clone, PartialEq, Eq)]
 #[derivative(Default)]
-pub(crate) struct ZInjectRecord {
-    objset: u64,
-    object: u64,
-    start: u64,
-    end: u64,
-    guid: u64,
-    level: u32,
-    error: u32,
-    typ: u64,
-    freq: u32,
-    failfast: u32,
+pub struct ZInjectRecord {
+    pub objset: u64,
+    pub object: u64,
+    pub start: u64,
+    pub end: u64,
+    pub guid: u64,
+    pub level: u32,
+    pub error: u32,
+    pub typ: u64,
+    pub freq: u32,
+    pub failfast: u32,
     #[derivative(Default(value = "[0; MAXNAMELEN]"))]
-    func: [u8; MAXNAMELEN],
-    iotype: u32,
-    duration: i32,
-    timer: u64,
-    nlanes: u64,
-    cmd: u64,
-    dvas: u64,
+    pub func: [u8; MAXNAMELEN],
+    pub iotype: u32,
+    pub duration: i32,
+    pub timer: u64,
+    pub nlanes: u64,
+    pub cmd: u32,
+    pub dvas: u32,
 }
 
 // zfs_share_t
 #[repr(C)]
 #[derive(Default, Debug)]
 pub(crate) struct ZFSShare {
-    exportdata: u64,
-    sharedata: u64,
-    sharetype: u64,
-    sharemax: u64,
+    pub(crate) exportdata: u64, // char *, the path to share
+    pub(crate) sharedata: u64,  // char *, the share options
+    pub(crate) sharetype: u64,  // zfs_share_op_t
+    pub(crate) sharemax: u64,   // size of sharedata
 }
 
 // zfs_stat_t
@@ -109,45 +146,46 @@ pub(crate) struct ZFSStat {
 #[repr(C)]
 #[derive(Derivative, Debug)]
 #[derivative(Default)]
-pub(crate) struct ZFSCommand {
+pub struct ZFSCommand {
     // nvlist-based
     #[derivative(Default(value = "[0; MAXPATHLEN]"))]
-    pub name: [u8; MAXPATHLEN],
+    pub(crate) name: [u8; MAXPATHLEN],
     #[derivative(Default(value = "null()"))]
-    nvlist_src: *const u8,
-    nvlist_src_size: u64,
-    #[derivative(Default(value = "null()"))]
-    pub nvlist_dst: *const u8,
-    pub nvlist_dst_size: u64,
+    pub(crate) nvlist_src: *const u8,
+    pub(crate) nvlist_src_size: u64,
+    #[derivative(Default(value = "null_mut()"))]
+    pub(crate) nvlist_dst: *mut u8,
+    pub(crate) nvlist_dst_size: u64,
+    nvlist_dst_filled: u32, // boolean_t
     pad2: i32,
 
     // legacy
     #[derivative(Default(value = "null()"))]
     history: *const u8,
     #[derivative(Default(value = "[0; MAXPATHLEN*2]"))]
-    value: [u8; MAXPATHLEN * 2],
+    pub(crate) value: [u8; MAXPATHLEN * 2],
     #[derivative(Default(value = "[0; MAXNAMELEN]"))]
-    string: [u8; MAXNAMELEN],
-    guid: u64,
+    pub(crate) string: [u8; MAXNAMELEN],
+    pub(crate) guid: u64,
     #[derivative(Default(value = "null()"))]
-    nvlist_conf: *const u8,
-    nvlist_conf_size: u64,
-    pub cookie: u64,
-    objset_type: u64,
-    perm_action: u64,
+    pub(crate) nvlist_conf: *const u8,
+    pub(crate) nvlist_conf_size: u64,
+    pub(crate) cookie: u64,
+    pub(crate) objset_type: u64,
+    pub(crate) perm_action: u64,
     history_len: u64,
     history_offset: u64,
-    obj: u64,
+    pub(crate) obj: u64,
     iflags: u64,
-    share: ZFSShare,
+    pub(crate) share: ZFSShare,
     objset_stats: DMUObjectStats,
     begin_record: DMUReplayRecordBegin,
-    inject_record: ZInjectRecord,
+    pub(crate) inject_record: ZInjectRecord,
     defer_destroy: u32,
-    flags: i32,
+    pub(crate) flags: i32,
     action_handle: u64,
-    cleanup_fd: c_int,
-    simple: u8,
+    pub(crate) cleanup_fd: c_int,
+    pub(crate) simple: u8,
     #[derivative(Default(value = "[0; 3]"))]
     pad: [u8; 3],
     sendobj: u64,
@@ -157,38 +195,365 @@ pub(crate) struct ZFSCommand {
     zoneid: u64,
 }
 
+// if any of these are wrong, the kernel is reading and writing a different struct to us, so
+// refuse to build. sizes are for LP64, from include/sys/zfs_ioctl.h:
+//
+//   name                                MAXPATHLEN
+//   nvlist src/dst ptr+size             4 * 8       = 32
+//   nvlist_dst_filled, pad2             4 + 4       = 8
+//   history                             8
+//   value                               MAXPATHLEN * 2
+//   string                              MAXNAMELEN  = 256
+//   guid .. iflags                      10 * 8      = 80
+//   zfs_share_t                         4 * 8       = 32
+//   dmu_objset_stats_t                  3 * 8 + 4 + 3 + 256, padded to 8 = 288
+//   drr_begin                           3 * 8 + 4 + 4 + 2 * 8 + 256 = 304
+//   zinject_record_t                    5 * 8 + 2 * 4 + 8 + 2 * 4 + 256 + 2 * 4 + 2 * 8 + 2 * 4 = 352
+//   defer_destroy, flags                4 + 4       = 8
+//   action_handle                       8
+//   cleanup_fd, simple, pad             4 + 1 + 3   = 8
+//   sendobj, fromobj, createtxg         3 * 8       = 24
+//   zfs_stat_t                          5 * 8       = 40
+//   zoneid                              8
+//
+// that's 1456 + MAXPATHLEN * 3, with no padding between members
+#[cfg(target_pointer_width = "64")]
+mod layout {
+    use super::*;
+    use std::mem::size_of;
+
+    const _: () = assert!(size_of::<ZFSShare>() == 32);
+    const _: () = assert!(size_of::<DMUObjectStats>() == 288);
+    const _: () = assert!(size_of::<DMUReplayRecordBegin>() == 304);
+    const _: () = assert!(size_of::<ZInjectRecord>() == 352);
+    const _: () = assert!(size_of::<ZFSStat>() == 40);
+
+    // Linux: MAXPATHLEN 4096
+    #[cfg(target_os = "linux")]
+    const _: () = assert!(size_of::<ZFSCommand>() == 13744);
+
+    // FreeBSD: MAXPATHLEN 1024
+    #[cfg(target_os = "freebsd")]
+    const _: () = assert!(size_of::<ZFSCommand>() == 4528);
+}
+
+// the parts of the command a transport might need to look at or fill in. the real kernel does
+// all of this itself; these are for stand-ins
+impl ZFSCommand {
+    // the object name the command applies to
+    pub fn name(&self) -> &CStr {
+        CStr::from_bytes_until_nul(&self.name).unwrap_or_default()
+    }
+
+    pub fn set_name(&mut self, name: &CStr) {
+        let name = name.to_bytes_with_nul();
+        self.name = [0; MAXPATHLEN];
+        self.name[..name.len()].copy_from_slice(name);
+    }
+
+    // a second name or path, for the legacy ioctls that take or return one
+    pub fn value(&self) -> &CStr {
+        CStr::from_bytes_until_nul(&self.value).unwrap_or_default()
+    }
+
+    pub fn set_value(&mut self, value: &CStr) {
+        let value = value.to_bytes_with_nul();
+        self.value = [0; MAXPATHLEN * 2];
+        self.value[..value.len()].copy_from_slice(value);
+    }
+
+    // a short name, for the few legacy ioctls that take a third
+    pub fn string(&self) -> &CStr {
+        CStr::from_bytes_until_nul(&self.string).unwrap_or_default()
+    }
+
+    pub fn set_string(&mut self, string: &CStr) {
+        let string = string.to_bytes_with_nul();
+        self.string = [0; MAXNAMELEN];
+        self.string[..string.len()].copy_from_slice(string);
+    }
+
+    pub fn cookie(&self) -> u64 {
+        self.cookie
+    }
+
+    pub fn set_cookie(&mut self, cookie: u64) {
+        self.cookie = cookie;
+    }
+
+    pub fn guid(&self) -> u64 {
+        self.guid
+    }
+
+    pub fn set_guid(&mut self, guid: u64) {
+        self.guid = guid;
+    }
+
+    pub fn obj(&self) -> u64 {
+        self.obj
+    }
+
+    pub fn set_obj(&mut self, obj: u64) {
+        self.obj = obj;
+    }
+
+    // for the userspace ioctls, which property (zfs_userquota_prop_t)
+    pub fn objset_type(&self) -> u64 {
+        self.objset_type
+    }
+
+    pub fn perm_action(&self) -> u64 {
+        self.perm_action
+    }
+
+    pub fn flags(&self) -> i32 {
+        self.flags
+    }
+
+    // the packed argument list passed in by the caller, if any
+    pub fn src(&self) -> &[u8] {
+        if self.nvlist_src.is_null() {
+            return &[];
+        }
+        unsafe { std::slice::from_raw_parts(self.nvlist_src, self.nvlist_src_size as usize) }
+    }
+
+    // the packed config list passed in by the caller, if any
+    pub fn conf(&self) -> &[u8] {
+        if self.nvlist_conf.is_null() {
+            return &[];
+        }
+        unsafe { std::slice::from_raw_parts(self.nvlist_conf, self.nvlist_conf_size as usize) }
+    }
+
+    // a separate open of the control device; whatever the command ties to it is undone when
+    // it's closed
+    pub fn cleanup_fd(&self) -> c_int {
+        self.cleanup_fd
+    }
+
+    // for the share ioctl, what to do (zfs_share_op_t)
+    pub fn sharetype(&self) -> u64 {
+        self.share.sharetype
+    }
+
+    // and the path and options strings it points at
+    pub fn share_export(&self) -> &CStr {
+        self.share_str(self.share.exportdata)
+    }
+
+    pub fn share_options(&self) -> &CStr {
+        self.share_str(self.share.sharedata)
+    }
+
+    fn share_str(&self, addr: u64) -> &CStr {
+        if addr == 0 {
+            return Default::default();
+        }
+        unsafe { CStr::from_ptr(addr as *const c_char) }
+    }
+
+    pub fn inject_record(&self) -> &ZInjectRecord {
+        &self.inject_record
+    }
+
+    pub fn set_inject_record(&mut self, record: &ZInjectRecord) {
+        self.inject_record = record.clone();
+    }
+
+    // for the list commands, only the name and objset_stats are wanted, not the property list
+    pub fn simple(&self) -> bool {
+        self.simple != 0
+    }
+
+    pub fn objset_stats(&self) -> ObjsetStats {
+        (&self.objset_stats).into()
+    }
+
+    pub fn set_objset_stats(&mut self, stats: &ObjsetStats) {
+        let origin = stats.origin.to_bytes();
+        let olen = origin.len().min(ZFS_MAX_DATASET_NAME_LEN - 1);
+        self.objset_stats = DMUObjectStats {
+            num_clones: stats.num_clones,
+            creation_txg: stats.creation_txg,
+            guid: stats.guid,
+            typ: match stats.typ {
+                ObjsetType::None => 0,
+                ObjsetType::Meta => 1,
+                ObjsetType::Zfs => 2,
+                ObjsetType::Zvol => 3,
+                ObjsetType::Other => 4,
+                ObjsetType::Any | ObjsetType::Unknown => 5,
+            },
+            is_snapshot: stats.is_snapshot.into(),
+            inconsistent: stats.inconsistent.into(),
+            redacted: stats.redacted.into(),
+            ..Default::default()
+        };
+        self.objset_stats.origin[..olen].copy_from_slice(&origin[..olen]);
+    }
+
+    // the current contents of the result buffer
+    pub fn dst(&self) -> &[u8] {
+        if self.nvlist_dst.is_null() {
+            return &[];
+        }
+        unsafe { std::slice::from_raw_parts(self.nvlist_dst, self.nvlist_dst_size as usize) }
+    }
+
+    // copy a packed nvlist into the caller's result buffer. like the kernel, if it won't fit,
+    // set the required size and return ENOMEM
+    pub fn write_dst(&mut self, nvbuf: &[u8]) -> Result<(), IOError> {
+        if self.nvlist_dst.is_null() || nvbuf.len() as u64 > self.nvlist_dst_size {
+            self.nvlist_dst_size = nvbuf.len() as u64;
+            return Err(IOError::from(IOErrorKind::OutOfMemory));
+        }
+        unsafe {
+            std::ptr::copy_nonoverlapping(nvbuf.as_ptr(), self.nvlist_dst, nvbuf.len());
+        }
+        self.nvlist_dst_size = nvbuf.len() as u64;
+        Ok(())
+    }
+}
+
 extern "C" {
     fn ioctl(fd: c_int, request: c_ulong, ...) -> c_int;
+    fn pipe2(fds: *mut c_int, flags: c_int) -> c_int;
+}
+
+// for make_pipe(), so the pipe isn't inherited by anything the program runs
+// /usr/include/asm-generic/fcntl.h
+#[cfg(target_os = "linux")]
+const O_CLOEXEC: c_int = 0o2000000;
+// /usr/include/sys/fcntl.h
+#[cfg(target_os = "freebsd")]
+const O_CLOEXEC: c_int = 0x00100000;
+
+// a pipe, as (read end, write end), for ioctls that write their output to a file descriptor
+pub(crate) fn make_pipe() -> Result<(File, File), IOError> {
+    let mut fds: [c_int; 2] = [-1; 2];
+    if unsafe { pipe2(fds.as_mut_ptr(), O_CLOEXEC) } < 0 {
+        return Err(IOError::last_os_error());
+    }
+    Ok(unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) })
+}
+
+// the datasets in the mount table, that is, the source of every zfs mount
+#[cfg(target_os = "linux")]
+pub(crate) fn zfs_mounts() -> Result<Vec<CString>, IOError> {
+    // fields are separated by spaces, and any spaces (or other awkward characters) within a
+    // field are written as \ooo octal escapes
+    fn unescape(field: &[u8]) -> Vec<u8> {
+        let mut out = vec![];
+        let mut i = 0;
+        while i < field.len() {
+            let oct = field.get(i + 1..i + 4).and_then(|o| {
+                std::str::from_utf8(o)
+                    .ok()
+                    .and_then(|o| u8::from_str_radix(o, 8).ok())
+            });
+            match (field[i], oct) {
+                (b'\\', Some(c)) => {
+                    out.push(c);
+                    i += 4;
+                }
+                (c, _) => {
+                    out.push(c);
+                    i += 1;
+                }
+            }
+        }
+        out
+    }
+
+    let mounts = std::fs::read("/proc/self/mounts")?;
+    Ok(mounts
+        .split(|&c| c == b'\n')
+        .filter_map(|line| {
+            let mut fields = line.split(|&c| c == b' ');
+            let (source, _, fstype) = (fields.next()?, fields.next()?, fields.next()?);
+            (fstype == b"zfs")
+                .then(|| CString::new(unescape(source)).ok())
+                .flatten()
+        })
+        .collect())
+}
+
+// XXX getfsstat()
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn zfs_mounts() -> Result<Vec<CString>, IOError> {
+    Err(IOError::from(IOErrorKind::Unsupported))
 }
 
+// an ioctl request number, tied to the type of the one argument it takes. this is the only place
+// the raw variadic ioctl() is called, so the argument can't be the wrong type or missing
+struct Ioctl<T> {
+    req: c_ulong,
+    _arg: PhantomData<fn(&mut T)>,
+}
+
+impl<T> Ioctl<T> {
+    const fn new(req: c_ulong) -> Ioctl<T> {
+        Ioctl {
+            req,
+            _arg: PhantomData,
+        }
+    }
+
+    fn ioctl(&self, fd: &impl AsRawFd, arg: &mut T) -> Result<c_uint, IOError> {
+        let r = unsafe { ioctl(fd.as_raw_fd(), self.req, arg as *mut T) };
+        r.try_into().map_err(|_| IOError::last_os_error())
+    }
+}
+
+// Linux: the command struct is passed directly, with request 'Z' << 8 + number
 #[cfg(not(target_os = "freebsd"))]
 pub(crate) fn zfs_ioctl(
     fd: &mut impl AsRawFd,
     req: c_ulong,
     zc: &mut ZFSCommand,
 ) -> Result<c_uint, IOError> {
-    let r = unsafe { ioctl(fd.as_raw_fd(), 0x5a00 + req, zc as *mut _, null::<c_void>()) };
-    r.try_into().map_err(|_| IOError::last_os_error())
+    Ioctl::<ZFSCommand>::new(0x5a00 + req).ioctl(fd, zc)
+}
+
+// zfs_iocparm_t
+#[cfg(target_os = "freebsd")]
+#[repr(C)]
+struct IocParm {
+    version: u32,
+    cmd: u64,
+    cmd_size: u64,
 }
 
+// ZFS_IOCVER_OZFS
+#[cfg(target_os = "freebsd")]
+const ZFS_IOCVER_OZFS: u32 = 15;
+
+// _IOWR(group, num, T)
+#[cfg(target_os = "freebsd")]
+const fn iowr<T>(group: u8, num: c_ulong) -> c_ulong {
+    const IOC_INOUT: c_ulong = 0xc0000000;
+    const IOCPARM_MASK: c_ulong = 0x1fff;
+    IOC_INOUT
+        | (((std::mem::size_of::<T>() as c_ulong) & IOCPARM_MASK) << 16)
+        | ((group as c_ulong) << 8)
+        | num
+}
+
+// FreeBSD: the command struct is wrapped in a zfs_iocparm_t, with request
+// _IOWR('Z', number, zfs_iocparm_t)
 #[cfg(target_os = "freebsd")]
 pub(crate) fn zfs_ioctl(
     fd: &mut impl AsRawFd,
     req: c_ulong,
     zc: &mut ZFSCommand,
 ) -> Result<c_uint, IOError> {
-    #[repr(C)]
-    struct iocparm(u32, u64, u64);
-    let mut iocp = iocparm(
-        15 as u32,
-        zc as *mut ZFSCommand as u64,
-        std::mem::size_of::<ZFSCommand>() as u64,
-    );
-    // _IOWR('Z', req, sizeof(iocparm))
-    let ncmd: c_ulong =
-        0xc0000000 + ((std::mem::size_of::<iocparm>() as c_ulong) << 16) + 0x5a00 + req;
-    let r = unsafe { ioctl(fd.as_raw_fd(), ncmd, &mut iocp as *mut _, null::<c_void>()) };
-    r.try_into().map_err(|_| IOError::last_os_error())
+    let mut iocp = IocParm {
+        version: ZFS_IOCVER_OZFS,
+        cmd: zc as *mut ZFSCommand as u64,
+        cmd_size: std::mem::size_of::<ZFSCommand>() as u64,
+    };
+    Ioctl::<IocParm>::new(iowr::<IocParm>(b'Z', req)).ioctl(fd, &mut iocp)
 }
 
 macro_rules! ioc {
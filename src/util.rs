@@ -8,11 +8,13 @@ use std::cell::OnceCell;
 use std::cmp::Ordering;
 use std::ffi::{CStr, CString};
 use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
 
 // this badly-named thing takes a CString and does a lossy conversion to String on demand, which is
 // what you want almost always
 #[derive(Clone, Debug)]
-pub(crate) struct AutoString(CString, OnceCell<String>);
+pub struct AutoString(CString, OnceCell<String>);
 
 impl AutoString {
     pub fn as_c_str(&self) -> &CStr {
@@ -38,6 +40,12 @@ impl PartialEq for AutoString {
 
 impl Eq for AutoString {}
 
+impl Hash for AutoString {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.hash(state)
+    }
+}
+
 impl Ord for AutoString {
     fn cmp(&self, other: &Self) -> Ordering {
         self.0.cmp(&other.0)
@@ -62,6 +70,14 @@ impl fmt::Display for AutoString {
     }
 }
 
+impl Deref for AutoString {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
 impl AsRef<CStr> for AutoString {
     fn as_ref(&self) -> &CStr {
         self.as_c_str()
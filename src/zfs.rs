@@ -4,70 +4,271 @@
 
 // Copyright (c) 2023, Rob Norris <robn@despairlabs.com>
 
-use crate::ioc;
-use crate::nvenums::VdevType;
-use crate::nvpair::PairList;
+pub mod error;
+pub mod import;
+pub mod inject;
+
+use self::error::WithName;
+use self::import::ImportablePool;
+use self::inject::Fault;
+use crate::ioc::{self, DiffEntry, ShareData, ShareOp, VdevStateCmd, ZInjectRecord};
+use crate::nvenums::{
+    self, AllocBias, CanMount, FeatureState, ObjsetType, PropSource, UserQuotaType, VdevState,
+    VdevType,
+};
+use crate::nvpair::{PairKey, PairList, PairValue};
 use crate::nvtypes;
+use crate::sys;
 use crate::util::AutoString;
-use std::cell::RefCell;
-use std::collections::VecDeque;
+use std::borrow::Cow;
+use std::cell::{Ref, RefCell};
+use std::collections::{HashMap, VecDeque};
 use std::error::Error;
 use std::ffi::{CStr, CString};
+use std::fmt;
+use std::fs::File;
 use std::io::Error as IOError;
 use std::io::ErrorKind as IOErrorKind;
+use std::io::Read;
+use std::os::fd::{AsFd, BorrowedFd, OwnedFd};
+use std::os::unix::ffi::OsStringExt;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 struct Handle {
     ioc: RefCell<ioc::Handle>,
 }
 
 impl Handle {
-    fn open() -> Result<Handle, Box<dyn Error>> {
-        Ok(Handle {
-            ioc: RefCell::new(ioc::Handle::open()?),
-        })
-    }
-
     fn get_config(&self) -> Result<PairList, Box<dyn Error>> {
         self.ioc.borrow_mut().pool_configs()
     }
 
     fn get_pool(&self, name: impl AsRef<CStr>) -> Result<PairList, Box<dyn Error>> {
-        self.ioc.borrow_mut().pool_stats(name.as_ref())
+        let name = name.as_ref();
+        self.ioc
+            .borrow_mut()
+            .pool_stats(name)
+            .with_name("open", "pool", name)
     }
 
-    fn get_vdev(
+    fn pool_set_props(
         &self,
         name: impl AsRef<CStr>,
+        props: &PairList,
+    ) -> Result<(), Box<dyn Error>> {
+        let name = name.as_ref();
+        self.ioc.borrow_mut().pool_set_props(name, props).with_name(
+            "set property for",
+            "pool",
+            name,
+        )
+    }
+
+    fn pool_upgrade(&self, name: impl AsRef<CStr>, version: u64) -> Result<(), Box<dyn Error>> {
+        let name = name.as_ref();
+        self.ioc
+            .borrow_mut()
+            .pool_upgrade(name, version)
+            .with_name("upgrade", "pool", name)
+    }
+
+    fn pool_sync(&self, name: impl AsRef<CStr>, force: bool) -> Result<(), Box<dyn Error>> {
+        let name = name.as_ref();
+        self.ioc
+            .borrow_mut()
+            .pool_sync(name, force)
+            .with_name("sync", "pool", name)
+    }
+
+    fn pool_tryimport(&self, config: &PairList) -> Result<PairList, Box<dyn Error>> {
+        let name = config.get_c_string("name").unwrap_or_default();
+        self.ioc
+            .borrow_mut()
+            .pool_tryimport(config)
+            .with_name("import", "pool", &name)
+    }
+
+    fn pool_reguid(&self, name: impl AsRef<CStr>) -> Result<(), Box<dyn Error>> {
+        let name = name.as_ref();
+        self.ioc
+            .borrow_mut()
+            .pool_reguid(name)
+            .with_name("reguid", "pool", name)
+    }
+
+    fn vdev_attach(
+        &self,
+        pool: impl AsRef<CStr>,
         guid: u64,
-    ) -> Result<Option<PairList>, Box<dyn Error>> {
-        let plist = self.get_pool(name)?;
-        let top = plist
-            .get_list("vdev_tree")
-            .ok_or_else(|| IOError::from(IOErrorKind::NotFound))?; // XXX should be impossible, maybe
-                                                                   // just panic?
+        new: &PairList,
+        replacing: bool,
+    ) -> Result<(), Box<dyn Error>> {
+        // the kernel wants a whole tree, with the new leaf as the only child of the root
+        let mut root = PairList::new();
+        root.add("type", PairValue::String(CString::new("root").unwrap()));
+        root.add("children", PairValue::ListArray(vec![new.clone()]));
+        let pool = pool.as_ref();
+        let op = if replacing { "replace in" } else { "attach to" };
+        self.ioc
+            .borrow_mut()
+            .vdev_attach(pool, guid, &root, replacing)
+            .with_name(op, "pool", pool)
+    }
 
-        let mut vds: VecDeque<&PairList> = VecDeque::new();
-        vds.push_back(top);
+    fn vdev_add(&self, pool: impl AsRef<CStr>, config: &PairList) -> Result<(), Box<dyn Error>> {
+        let pool = pool.as_ref();
+        self.ioc
+            .borrow_mut()
+            .vdev_add(pool, config, true)
+            .with_name("add to", "pool", pool)
+    }
 
-        while let Some(vd) = vds.pop_front() {
-            if let Some(vguid) = vd.get_u64("guid") {
-                if vguid == guid {
-                    return Ok(Some(vd.clone()));
-                }
+    fn vdev_remove(&self, pool: impl AsRef<CStr>, guid: u64) -> Result<(), Box<dyn Error>> {
+        let pool = pool.as_ref();
+        self.ioc
+            .borrow_mut()
+            .vdev_remove(pool, guid)
+            .with_name("remove from", "pool", pool)
+    }
 
-                vd.get_list_slice("children")
-                    .into_iter()
-                    .flatten()
-                    .for_each(|cvd| vds.push_back(cvd));
-            }
-        }
+    fn vdev_set_state(
+        &self,
+        pool: impl AsRef<CStr>,
+        guid: u64,
+        state: VdevStateCmd,
+    ) -> Result<VdevState, Box<dyn Error>> {
+        let pool = pool.as_ref();
+        self.ioc
+            .borrow_mut()
+            .vdev_set_state(pool, guid, state)
+            .with_name("change vdev state in", "pool", pool)
+    }
 
-        Ok(None)
+    fn vdev_detach(&self, pool: impl AsRef<CStr>, guid: u64) -> Result<(), Box<dyn Error>> {
+        let pool = pool.as_ref();
+        self.ioc
+            .borrow_mut()
+            .vdev_detach(pool, guid)
+            .with_name("detach from", "pool", pool)
+    }
+
+    fn inject_fault(
+        &self,
+        pool: impl AsRef<CStr>,
+        record: &ZInjectRecord,
+        flags: u64,
+    ) -> Result<u64, Box<dyn Error>> {
+        let pool = pool.as_ref();
+        self.ioc
+            .borrow_mut()
+            .inject_fault(pool, record, flags)
+            .with_name("inject fault into", "pool", pool)
+    }
+
+    fn clear_fault(&self, id: u64) -> Result<(), Box<dyn Error>> {
+        self.ioc.borrow_mut().clear_fault(id).with_name(
+            "clear",
+            "fault",
+            &CString::new(id.to_string())?,
+        )
+    }
+
+    fn get_fault_list(&self) -> Result<Vec<Fault>, Box<dyn Error>> {
+        let mut list = vec![];
+        let mut id = 0;
+        while let Some((next, pool, record)) = self.ioc.borrow_mut().inject_list_next(id)? {
+            id = next;
+            list.push(Fault {
+                id,
+                pool: (&pool).into(),
+                record,
+            });
+        }
+        Ok(list)
     }
 
     fn get_dataset(&self, name: impl AsRef<CStr>) -> Result<PairList, Box<dyn Error>> {
-        self.ioc.borrow_mut().objset_stats(name.as_ref())
+        let name = name.as_ref();
+        self.ioc
+            .borrow_mut()
+            .objset_stats(name)
+            .with_name("open", "dataset", name)
+    }
+
+    fn get_dataset_stats(
+        &self,
+        name: impl AsRef<CStr>,
+    ) -> Result<(PairList, ioc::ObjsetStats), Box<dyn Error>> {
+        let name = name.as_ref();
+        self.ioc
+            .borrow_mut()
+            .objset_stats_full(name)
+            .with_name("open", "dataset", name)
+    }
+
+    fn get_dataset_zplprops(&self, name: impl AsRef<CStr>) -> Result<PairList, Box<dyn Error>> {
+        let name = name.as_ref();
+        self.ioc
+            .borrow_mut()
+            .objset_zplprops(name)
+            .with_name("get properties of", "dataset", name)
+    }
+
+    fn get_dataset_recvd_props(&self, name: impl AsRef<CStr>) -> Result<PairList, Box<dyn Error>> {
+        let name = name.as_ref();
+        self.ioc.borrow_mut().objset_recvd_props(name).with_name(
+            "get received properties of",
+            "dataset",
+            name,
+        )
+    }
+
+    fn get_dataset_fsacl(&self, name: impl AsRef<CStr>) -> Result<PairList, Box<dyn Error>> {
+        let name = name.as_ref();
+        self.ioc
+            .borrow_mut()
+            .get_fsacl(name)
+            .with_name("get permissions for", "dataset", name)
+    }
+
+    fn get_bookmark_props(&self, name: impl AsRef<CStr>) -> Result<PairList, Box<dyn Error>> {
+        let name = name.as_ref();
+        self.ioc
+            .borrow_mut()
+            .get_bookmark_props(name)
+            .with_name("open", "bookmark", name)
+    }
+
+    fn destroy_bookmark(&self, name: impl AsRef<CStr>) -> Result<(), Box<dyn Error>> {
+        let name = name.as_ref();
+        let pool = ioc::pool_of(std::iter::once(name))?;
+        self.ioc
+            .borrow_mut()
+            .destroy_bookmarks(&pool, &[name])
+            .with_name("destroy", "bookmark", name)
+    }
+
+    fn set_dataset_props(
+        &self,
+        name: impl AsRef<CStr>,
+        props: &PairList,
+    ) -> Result<(), Box<dyn Error>> {
+        let name = name.as_ref();
+        self.ioc
+            .borrow_mut()
+            .set_props(name, props)
+            .with_name("set property for", "dataset", name)
+    }
+
+    fn get_dataset_prop(
+        &self,
+        name: impl AsRef<CStr>,
+        prop: impl PairKey,
+    ) -> Result<Option<PairList>, Box<dyn Error>> {
+        let dslist = self.get_dataset(name)?;
+        Ok(dslist.get_list(prop).cloned())
     }
 
     fn get_dataset_list(&self) -> Result<Vec<CString>, Box<dyn Error>> {
@@ -75,21 +276,253 @@ impl Handle {
 
         for pool in self.get_config()?.keys() {
             let _ = self.get_dataset(pool)?;
-            list.push(pool.into());
-
-            let mut stack: Vec<(CString, u64)> = vec![(pool.into(), 0)];
-            while let Some((name, cookie)) = stack.pop() {
-                match self.ioc.borrow_mut().dataset_list_next(&name, cookie) {
-                    Ok(is) => {
-                        list.push(is.name.clone());
-                        stack.push((name, is.cookie));
-                        stack.push((is.name, 0));
-                    }
-                    Err(e) => {
-                        let ioe = e.downcast::<IOError>()?;
-                        ioe.raw_os_error().filter(|n| *n == 3).ok_or(ioe)?; // ESRCH
-                    }
+            list.extend(self.get_filtered_list(pool, &DATASETS_ONLY)?);
+        }
+
+        Ok(list)
+    }
+
+    // every dataset from top down (top included) that the filter wants, and their snapshots if
+    // it wants those, each dataset followed by its snapshots and then its children. only names
+    // and types are asked for, not properties, so unwanted ones cost little. volumes can't have
+    // children, so they aren't listed for any
+    fn get_filtered_list(
+        &self,
+        top: impl AsRef<CStr>,
+        filter: &DatasetFilter,
+    ) -> Result<Vec<CString>, Box<dyn Error>> {
+        let mut list: Vec<CString> = vec![];
+
+        let mut stack: Vec<(CString, ObjsetType, u64)> =
+            vec![(top.as_ref().into(), ObjsetType::Zfs, 0)];
+        while let Some((name, typ, cookie)) = stack.pop() {
+            if cookie == 0 {
+                if filter.wants(typ) {
+                    list.push(name.clone());
+                }
+                if filter.snap {
+                    list.extend(self.get_snapshot_list_simple(&name)?);
+                }
+                if typ == ObjsetType::Zvol {
+                    continue;
+                }
+            }
+            match self
+                .ioc
+                .borrow_mut()
+                .dataset_list_next_simple(&name, cookie)
+            {
+                Ok(is) => {
+                    stack.push((name, typ, is.cookie));
+                    stack.push((is.name, is.stats.typ, 0));
+                }
+                Err(e) if error::raw_os_error(&*e) == Some(3) => {} // ESRCH
+                Err(e) => return Err(e).with_name("list children of", "dataset", &name),
+            }
+        }
+        Ok(list)
+    }
+
+    // every dataset below this one, each before its own children
+    fn get_descendant_list(&self, top: impl AsRef<CStr>) -> Result<Vec<CString>, Box<dyn Error>> {
+        let mut list = self.get_filtered_list(top, &DATASETS_ONLY)?;
+        list.remove(0); // top itself
+        Ok(list)
+    }
+
+    fn get_userspace_one(
+        &self,
+        dataset: impl AsRef<CStr>,
+        prop: UserQuotaType,
+        rid: u64,
+    ) -> Result<u64, Box<dyn Error>> {
+        let dataset = dataset.as_ref();
+        self.ioc
+            .borrow_mut()
+            .userspace_one(dataset, prop, Default::default(), rid)
+            .with_name("get space used in", "dataset", dataset)
+    }
+
+    fn hold(
+        &self,
+        snap: impl AsRef<CStr>,
+        tag: &CStr,
+        cleanup_fd: Option<BorrowedFd>,
+    ) -> Result<(), Box<dyn Error>> {
+        let snap = snap.as_ref();
+        self.ioc
+            .borrow_mut()
+            .hold(&[(snap, tag)], cleanup_fd)
+            .with_name("hold", "snapshot", snap)
+    }
+
+    fn cleanup_fd(&self) -> Result<OwnedFd, Box<dyn Error>> {
+        Ok(self.ioc.borrow().cleanup_fd()?)
+    }
+
+    fn release(&self, snap: impl AsRef<CStr>, tag: &CStr) -> Result<(), Box<dyn Error>> {
+        let snap = snap.as_ref();
+        self.ioc
+            .borrow_mut()
+            .release(&[(snap, tag)])
+            .with_name("release", "snapshot", snap)
+    }
+
+    // a temporary snapshot and the fd it lasts as long as. the name is the full one
+    fn tmp_snapshot(
+        &self,
+        dataset: impl AsRef<CStr>,
+        prefix: &CStr,
+    ) -> Result<(CString, OwnedFd), Box<dyn Error>> {
+        let dataset = dataset.as_ref();
+        let mut ioc = self.ioc.borrow_mut();
+        let cleanup = ioc.cleanup_fd()?;
+        let short = ioc
+            .tmp_snapshot(dataset, prefix, &cleanup)
+            .with_name("snapshot", "dataset", dataset)?;
+        let mut snap = dataset.to_bytes().to_vec();
+        snap.push(b'@');
+        snap.extend_from_slice(short.to_bytes());
+        Ok((CString::new(snap)?, cleanup))
+    }
+
+    fn redact(
+        &self,
+        snap: impl AsRef<CStr>,
+        bookmark: &CStr,
+        redact_snaps: &[&CStr],
+    ) -> Result<(), Box<dyn Error>> {
+        let snap = snap.as_ref();
+        self.ioc
+            .borrow_mut()
+            .redact(snap, bookmark, redact_snaps)
+            .with_name("redact", "snapshot", snap)
+    }
+
+    fn remap_dataset(&self, name: impl AsRef<CStr>) -> Result<(), Box<dyn Error>> {
+        let name = name.as_ref();
+        self.ioc
+            .borrow_mut()
+            .remap(name)
+            .with_name("remap", "dataset", name)
+    }
+
+    fn share_dataset(
+        &self,
+        name: impl AsRef<CStr>,
+        share: &ShareData,
+        op: ShareOp,
+    ) -> Result<(), Box<dyn Error>> {
+        let name = name.as_ref();
+        let what = match op {
+            ShareOp::ShareNfs | ShareOp::ShareSmb => "share",
+            ShareOp::UnshareNfs | ShareOp::UnshareSmb => "unshare",
+        };
+        self.ioc
+            .borrow_mut()
+            .share(name, share, op)
+            .with_name(what, "dataset", name)
+    }
+
+    fn create_snapshots(&self, snaps: &[CString]) -> Result<(), Box<dyn Error>> {
+        let names: Vec<&CStr> = snaps.iter().map(CString::as_c_str).collect();
+        let first = names.first().copied().unwrap_or_default();
+        self.ioc
+            .borrow_mut()
+            .snapshot(&names, None)
+            .with_name("create", "snapshot", first)
+    }
+
+    fn diff(
+        &self,
+        snap: impl AsRef<CStr>,
+        from: impl AsRef<CStr>,
+    ) -> Result<Vec<DiffEntry>, Box<dyn Error>> {
+        // the kernel writes the whole stream before returning, so read it on another thread
+        // while it does
+        let (r, w) = sys::make_pipe()?;
+        let reader = std::thread::spawn(move || ioc::read_diff(r));
+        let snap = snap.as_ref();
+        let res = self
+            .ioc
+            .borrow_mut()
+            .diff(snap, from.as_ref(), &w)
+            .with_name("diff", "snapshot", snap);
+        drop(w);
+        let entries = reader.join().expect("diff reader panicked")?;
+        res?;
+        Ok(entries)
+    }
+
+    fn recv(
+        &self,
+        snap: impl AsRef<CStr>,
+        begin: [u8; ioc::BEGIN_RECORD_SIZE],
+        mut reader: impl Read + Send + 'static,
+        force: bool,
+        resumable: bool,
+    ) -> Result<PairList, Box<dyn Error>> {
+        // the kernel reads the stream from a pipe, so feed it from another thread. the begin
+        // record has already been taken off the front, and is passed alongside
+        let (r, mut w) = sys::make_pipe()?;
+        let writer = std::thread::spawn(move || -> std::io::Result<()> {
+            std::io::copy(&mut reader, &mut w)?;
+            Ok(())
+        });
+        let snap = snap.as_ref();
+        let res = self
+            .ioc
+            .borrow_mut()
+            .recv(snap, Some(&begin), &r, None, None, force, resumable)
+            .with_name("receive", "dataset", snap);
+        drop(r);
+        // the kernel stops reading at the end record, or when it fails. either way the writer
+        // gets a broken pipe, which only matters if nothing else went wrong
+        match writer.join().expect("recv writer panicked") {
+            Err(e) if e.kind() != IOErrorKind::BrokenPipe => Err(e.into()),
+            _ => res,
+        }
+    }
+
+    fn get_snapshot_list(&self, dataset: impl AsRef<CStr>) -> Result<Vec<CString>, Box<dyn Error>> {
+        let dataset = dataset.as_ref();
+        let mut list: Vec<CString> = vec![];
+
+        let mut cookie = 0;
+        loop {
+            match self.ioc.borrow_mut().snapshot_list_next(dataset, cookie) {
+                Ok(is) => {
+                    list.push(is.name);
+                    cookie = is.cookie;
+                }
+                Err(e) if error::raw_os_error(&*e) == Some(3) => break, // ESRCH
+                Err(e) => return Err(e).with_name("list snapshots of", "dataset", dataset),
+            }
+        }
+
+        Ok(list)
+    }
+
+    fn get_snapshot_list_simple(
+        &self,
+        dataset: impl AsRef<CStr>,
+    ) -> Result<Vec<CString>, Box<dyn Error>> {
+        let dataset = dataset.as_ref();
+        let mut list: Vec<CString> = vec![];
+
+        let mut cookie = 0;
+        loop {
+            match self
+                .ioc
+                .borrow_mut()
+                .snapshot_list_next_simple(dataset, cookie)
+            {
+                Ok(is) => {
+                    list.push(is.name);
+                    cookie = is.cookie;
                 }
+                Err(e) if error::raw_os_error(&*e) == Some(3) => break, // ESRCH
+                Err(e) => return Err(e).with_name("list snapshots of", "dataset", dataset),
             }
         }
 
@@ -97,15 +530,49 @@ impl Handle {
     }
 }
 
+impl From<ioc::Handle> for Handle {
+    fn from(ioc: ioc::Handle) -> Self {
+        Handle {
+            ioc: RefCell::new(ioc),
+        }
+    }
+}
+
 pub struct Root(Rc<Handle>);
 
 pub fn open() -> Result<Root, Box<dyn Error>> {
     Root::open()
 }
 
+// like open(), for a control device somewhere other than /dev/zfs (eg bind-mounted into a
+// container)
+pub fn open_dev<P: AsRef<Path>>(path: P) -> Result<Root, Box<dyn Error>> {
+    Root::open_dev(path)
+}
+
+// pools that could be imported from the devices in these directories (eg /dev/disk/by-id)
+pub fn importable_pools(dirs: &[&Path]) -> Result<Vec<ImportablePool>, Box<dyn Error>> {
+    open()?.importable_pools(dirs)
+}
+
+// build on an already-open (possibly non-kernel) ioctl handle
+impl From<ioc::Handle> for Root {
+    fn from(ioc: ioc::Handle) -> Self {
+        Root(Rc::new(Handle::from(ioc)))
+    }
+}
+
 impl Root {
     fn open() -> Result<Root, Box<dyn Error>> {
-        Ok(Root(Rc::new(Handle::open()?)))
+        Root::open_dev("/dev/zfs")
+    }
+
+    // an ioc::Handle is big (it carries its own result buffer), and unoptimised builds copy it
+    // for every Result it passes back through. so open the device first, and make the handle
+    // only once it's sure to work, right where it's going to live
+    pub fn open_dev<P: AsRef<Path>>(path: P) -> Result<Root, Box<dyn Error>> {
+        let dev = File::open(path)?;
+        Ok(Root::from(ioc::Handle::with_transport(Box::new(dev))))
     }
 
     pub fn pools(&self) -> Result<Vec<Pool>, Box<dyn Error>> {
@@ -116,127 +583,1812 @@ impl Root {
             .map(|p| Pool::new(self.0.clone(), p.into()))
             .collect())
     }
+
+    // the named pool, if there is one
+    pub fn pool(&self, name: &str) -> Result<Option<Pool>, Box<dyn Error>> {
+        Ok(self
+            .0
+            .get_config()?
+            .keys()
+            .find(|p| p.to_bytes() == name.as_bytes())
+            .map(|p| Pool::new(self.0.clone(), p.into())))
+    }
+
+    // every fault currently being injected, in any pool
+    pub fn faults(&self) -> Result<Vec<Fault>, Box<dyn Error>> {
+        self.0.get_fault_list()
+    }
+
+    // stop injecting a fault, by the id from Pool::inject_fault() or faults()
+    pub fn clear_fault(&self, id: u64) -> Result<(), Box<dyn Error>> {
+        self.0.clear_fault(id)
+    }
+
+    // pools that aren't imported, but have labels on devices in these directories. each is
+    // tried with the kernel, which fills in its health; one the kernel won't try is still
+    // listed, with no health. if the imported pools can't be listed, none are left out
+    pub fn importable_pools(&self, dirs: &[&Path]) -> Result<Vec<ImportablePool>, Box<dyn Error>> {
+        let imported: Vec<u64> = self
+            .0
+            .get_config()
+            .map(|c| {
+                c.pairs()
+                    .filter_map(|p| p.as_list().and_then(|l| l.get_u64("pool_guid")))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let mut pools = import::scan(dirs)?;
+        pools.retain(|p| !imported.contains(&p.guid));
+        for pool in pools.iter_mut() {
+            pool.health = self.0.pool_tryimport(&pool.config).ok().and_then(|c| {
+                c.get_list("vdev_tree")
+                    .and_then(|vt| vt.get_u64_slice("vdev_stats"))
+                    .map(|vs| VdevState::from(nvtypes::VdevStats::from(vs).state))
+            });
+        }
+        Ok(pools)
+    }
+
+    // like pools(), but each Pool is made only when it's asked for, so looking for one pool can
+    // stop as soon as it's found. if the pool list can't be fetched, that error is the only
+    // thing yielded
+    pub fn pools_iter(&self) -> impl Iterator<Item = Result<Pool, Box<dyn Error>>> {
+        let handle = self.0.clone();
+        let (config, err) = match self.0.get_config() {
+            Ok(config) => (config, None),
+            Err(e) => (PairList::new(), Some(e)),
+        };
+        err.into_iter().map(Err).chain(
+            config
+                .into_iter()
+                .map(move |p| Ok(Pool::new(handle.clone(), p.key().into()))),
+        )
+    }
 }
 
+// the pool's config and stats are fetched on first use and then kept, so everything read from a
+// Pool (and the vdevs got from it) is a snapshot from that moment. call refresh() to see new
+// counters or config changes
+#[derive(Clone)]
 pub struct Pool {
     handle: Rc<Handle>,
     name: AutoString,
+    stats: RefCell<Option<PairList>>,
+}
+
+impl PartialEq for Pool {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
 }
 
+impl Eq for Pool {}
+
 impl Pool {
     fn new(handle: Rc<Handle>, name: AutoString) -> Pool {
-        Pool { handle, name }
+        Pool {
+            handle,
+            name,
+            stats: RefCell::new(None),
+        }
     }
 
-    pub fn name(&self) -> String {
-        self.name.to_string()
+    pub fn name(&self) -> &AutoString {
+        &self.name
     }
 
-    pub fn root_vdev(&self) -> Result<Vdev, Box<dyn Error>> {
-        let pl = self.handle.get_pool(&self.name)?;
-        let vl = pl
-            .get_list("vdev_tree")
-            .ok_or_else(|| IOError::from(IOErrorKind::NotFound))?;
-        Vdev::new(self.handle.clone(), self.name.clone(), vl)
+    // drop the cached config and stats; the next call will fetch them fresh
+    pub fn refresh(&self) {
+        self.stats.replace(None);
     }
 
-    pub fn datasets(&self) -> Result<Vec<Dataset>, Box<dyn Error>> {
+    fn get_stats(&self) -> Result<Ref<'_, PairList>, Box<dyn Error>> {
+        if self.stats.borrow().is_none() {
+            let pl = self.handle.get_pool(&self.name)?;
+            self.stats.replace(Some(pl));
+        }
+        Ok(Ref::map(self.stats.borrow(), |s| s.as_ref().unwrap()))
+    }
+
+    pub fn guid(&self) -> Result<u64, Box<dyn Error>> {
         Ok(self
-            .handle
-            .get_dataset_list()?
-            .iter()
-            .filter(|ds| {
-                ds.to_bytes().starts_with(self.name.as_bytes())
-                    && (ds.to_bytes().len() == self.name.as_bytes().len()
-                        || ds.to_bytes()[self.name.as_bytes().len()] == b'/')
-            })
-            .map(|ds| Dataset::new(self.handle.clone(), ds.into()))
-            .collect())
+            .get_stats()?
+            .get_u64("pool_guid")
+            .ok_or_else(|| IOError::from(IOErrorKind::NotFound))?)
     }
-}
 
-pub struct Vdev {
-    handle: Rc<Handle>,
-    pool: AutoString,
-    guid: u64,
-    typ: VdevType,
-}
+    // the number of top-level vdevs, including holes left by removed ones
+    pub fn vdev_children(&self) -> Result<u64, Box<dyn Error>> {
+        Ok(self
+            .get_stats()?
+            .get_u64("vdev_children")
+            .ok_or_else(|| IOError::from(IOErrorKind::NotFound))?)
+    }
 
-impl Vdev {
-    fn new(handle: Rc<Handle>, pool: AutoString, vl: &PairList) -> Result<Vdev, Box<dyn Error>> {
-        let guid = vl
-            .get_u64("guid")
-            .ok_or_else(|| IOError::from(IOErrorKind::NotFound))?;
-        let typ = vl
-            .get_c_string("type")
-            .ok_or_else(|| IOError::from(IOErrorKind::NotFound))?;
-        Ok(Vdev {
-            handle,
-            pool,
-            guid,
-            typ: (&typ).into(),
-        })
+    // start injecting a fault (see inject::ZInjectBuilder), returning its id. with flush_arc,
+    // cached data is dropped first, so reads go to disk and can hit the fault
+    pub fn inject_fault(
+        &self,
+        record: &ZInjectRecord,
+        flush_arc: bool,
+    ) -> Result<u64, Box<dyn Error>> {
+        const ZINJECT_FLUSH_ARC: u64 = 0x2;
+        let flags = if flush_arc { ZINJECT_FLUSH_ARC } else { 0 };
+        self.handle.inject_fault(&self.name, record, flags)
     }
 
-    pub fn guid(&self) -> u64 {
-        self.guid
+    // wait until everything written to the pool so far is on disk
+    pub fn sync(&self) -> Result<(), Box<dyn Error>> {
+        self.handle.pool_sync(&self.name, false)
     }
 
-    pub fn typ(&self) -> VdevType {
-        self.typ
+    // change the pool guid. the cached stats are dropped, so guid() will show the new one
+    pub fn reguid(&self) -> Result<(), Box<dyn Error>> {
+        self.handle.pool_reguid(&self.name)?;
+        self.refresh();
+        Ok(())
     }
 
-    pub fn children(&self) -> Result<Vec<Vdev>, Box<dyn Error>> {
-        Ok(self
-            .handle
-            .get_vdev(&self.pool, self.guid)?
-            .and_then(|l| l.get_list_slice("children").map(|s| s.to_vec()))
-            .unwrap_or(vec![])
+    // every feature we know of, and any others the pool has, by guid (eg
+    // "com.delphix:hole_birth"; zpool shows the part after the colon)
+    pub fn features(&self) -> Result<Vec<(String, FeatureState)>, Box<dyn Error>> {
+        let pl = self.get_stats()?;
+        let empty = PairList::new();
+        let fs = pl.get_list("feature_stats").unwrap_or(&empty);
+
+        let known = nvenums::FEATURES
             .iter()
-            .map(|vl| Vdev::new(self.handle.clone(), self.pool.clone(), vl))
-            .flatten()
-            .collect())
+            .map(|&f| (f.to_string(), FeatureState::from(fs.get_u64(f))));
+        let other = fs
+            .pairs()
+            .filter(|p| !nvenums::FEATURES.contains(&p.key().to_string_lossy().as_ref()))
+            .map(|p| {
+                (
+                    p.key().to_string_lossy().to_string(),
+                    FeatureState::from(p.to_u64()),
+                )
+            });
+
+        Ok(known.chain(other).collect())
     }
 
-    pub fn stats(&self) -> Result<nvtypes::VdevStats, Box<dyn Error>> {
-        Ok(self
-            .handle
-            .get_vdev(&self.pool, self.guid)?
-            .and_then(|l| {
-                l.get_u64_slice("vdev_stats")
-                    .map(|s| nvtypes::VdevStats::from(s))
+    // bring the pool up to date, like zpool upgrade: move it to the feature flags version if it
+    // isn't already, then enable every feature we know of that isn't enabled. they're enabled
+    // one at a time, as an older kernel refuses the whole list (EINVAL or ENOTSUP) if any of
+    // them is new to it; those are passed over. if there's nothing to do, nothing is done
+    pub fn upgrade(&self) -> Result<(), Box<dyn Error>> {
+        const SPA_VERSION_FEATURES: u64 = 5000;
+
+        let version = self
+            .get_stats()?
+            .get_u64("version")
+            .ok_or_else(|| IOError::from(IOErrorKind::NotFound))?;
+        if version < SPA_VERSION_FEATURES {
+            self.handle.pool_upgrade(&self.name, SPA_VERSION_FEATURES)?;
+            self.refresh();
+        }
+
+        let disabled: Vec<_> = self
+            .features()?
+            .into_iter()
+            .filter(|(f, s)| {
+                *s == FeatureState::Disabled && nvenums::FEATURES.contains(&f.as_str())
             })
-            .unwrap_or_default())
+            .collect();
+        for (f, _) in &disabled {
+            let name = f.split_once(':').map_or(f.as_str(), |(_, n)| n);
+            let props = PairList::from_pairs([(format!("feature@{}", name), PairValue::UInt64(0))]);
+            match self.handle.pool_set_props(&self.name, &props) {
+                Ok(()) => {}
+                // EINVAL, ENOTSUP
+                Err(e) if matches!(error::raw_os_error(&*e), Some(22) | Some(95)) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        if !disabled.is_empty() {
+            self.refresh();
+        }
+
+        Ok(())
     }
-}
 
-pub struct Dataset {
-    handle: Rc<Handle>,
-    name: AutoString,
-}
+    pub fn root_vdev(&self) -> Result<Vdev, Box<dyn Error>> {
+        let pl = self.get_stats()?;
+        let vl = pl
+            .get_list("vdev_tree")
+            .ok_or_else(|| IOError::from(IOErrorKind::NotFound))?;
+        Vdev::new(
+            self.handle.clone(),
+            self.name.clone(),
+            vl,
+            VdevSection::Main,
+        )
+    }
 
-impl Dataset {
-    fn new(handle: Rc<Handle>, name: AutoString) -> Dataset {
-        Dataset { handle, name }
+    // the top-level vdevs that are separate intent logs. they're among the root vdev's
+    // children too, marked by Vdev::is_log()
+    pub fn logs(&self) -> Result<Vec<Vdev>, Box<dyn Error>> {
+        Ok(self
+            .root_vdev()?
+            .children()?
+            .into_iter()
+            .filter(Vdev::is_log)
+            .collect())
     }
 
-    pub fn name(&self) -> String {
-        self.name.to_string()
+    // hot spares. these aren't part of the root vdev's children, but kept alongside them
+    pub fn spares(&self) -> Result<Vec<Vdev>, Box<dyn Error>> {
+        self.aux_vdevs("spares", VdevSection::Spare)
     }
 
-    fn get_prop(&self, prop: &str) -> Result<Option<PairList>, Box<dyn Error>> {
-        let dslist = self.handle.get_dataset(&self.name)?;
-        Ok(dslist.get_list(prop).cloned())
+    // l2arc cache devices. like spares, these are kept apart from the root vdev's children
+    pub fn cache_devices(&self) -> Result<Vec<Vdev>, Box<dyn Error>> {
+        self.aux_vdevs("l2cache", VdevSection::Cache)
     }
 
-    pub fn get_prop_u64(&self, prop: &str) -> Result<Option<u64>, Box<dyn Error>> {
-        Ok(self.get_prop(prop)?.and_then(|l| l.get_u64("value")))
+    fn aux_vdevs(&self, key: &str, section: VdevSection) -> Result<Vec<Vdev>, Box<dyn Error>> {
+        let pl = self.get_stats()?;
+        let vl = pl
+            .get_list("vdev_tree")
+            .ok_or_else(|| IOError::from(IOErrorKind::NotFound))?;
+        vl.get_list_slice(key)
+            .unwrap_or_default()
+            .iter()
+            .map(|vl| Vdev::new(self.handle.clone(), self.name.clone(), vl, section))
+            .collect()
     }
 
-    pub fn get_prop_string(&self, prop: &str) -> Result<Option<String>, Box<dyn Error>> {
-        Ok(self
-            .get_prop(prop)?
-            .and_then(|l| l.get_c_string("value"))
-            .map(|cs| cs.to_string_lossy().to_string()))
+    // the whole vdev tree, with every vdev's stats, all from the one stats fetch. unlike Vdev,
+    // these are plain values, owned outright
+    pub fn vdev_tree(&self) -> Result<VdevNode, Box<dyn Error>> {
+        let pl = self.get_stats()?;
+        let top = pl
+            .get_list("vdev_tree")
+            .ok_or_else(|| IOError::from(IOErrorKind::NotFound))?;
+        let mut root = VdevNode::new(top, false)?;
+        root.name = self.name.to_string();
+        Ok(root)
+    }
+
+    // everything zpool status shows, all from the one stats fetch: the vdev tree with each
+    // vdev's stats, the spares and cache devices, the last or current scan, and the error log
+    // count. its Display is laid out like zpool status too
+    pub fn status(&self) -> Result<PoolStatus, Box<dyn Error>> {
+        let tree = self.vdev_tree()?;
+        let pl = self.get_stats()?;
+        let vt = pl
+            .get_list("vdev_tree")
+            .ok_or_else(|| IOError::from(IOErrorKind::NotFound))?;
+        let aux = |key| -> Result<Vec<VdevNode>, Box<dyn Error>> {
+            vt.get_list_slice(key)
+                .unwrap_or_default()
+                .iter()
+                .map(|vl| VdevNode::new(vl, false))
+                .collect()
+        };
+        Ok(PoolStatus {
+            name: self.name.to_string(),
+            state: VdevState::from(tree.stats.state),
+            spares: aux("spares")?,
+            cache: aux("l2cache")?,
+            scan: vt.get_u64_slice("scan_stats").map(nvtypes::ScanStats::from),
+            error_count: pl.get_u64("error_count").unwrap_or_default(),
+            tree,
+        })
+    }
+
+    // the vdev with this guid, anywhere in the tree or among the spares and cache devices, or
+    // None if there isn't one. a spare in use is in the tree too, and is found there first
+    pub fn vdev(&self, guid: u64) -> Result<Option<Vdev>, Box<dyn Error>> {
+        let pl = self.get_stats()?;
+        let top = pl
+            .get_list("vdev_tree")
+            .ok_or_else(|| IOError::from(IOErrorKind::NotFound))?;
+
+        let mut vds: VecDeque<(&PairList, VdevSection)> = VecDeque::new();
+        vds.push_back((top, VdevSection::Main));
+        for (key, section) in [
+            ("spares", VdevSection::Spare),
+            ("l2cache", VdevSection::Cache),
+        ] {
+            top.get_list_slice(key)
+                .into_iter()
+                .flatten()
+                .for_each(|vd| vds.push_back((vd, section)));
+        }
+
+        while let Some((vd, section)) = vds.pop_front() {
+            let section = section.of(vd);
+            if vd.get_u64("guid") == Some(guid) {
+                return Ok(Some(Vdev::new(
+                    self.handle.clone(),
+                    self.name.clone(),
+                    vd,
+                    section,
+                )?));
+            }
+            vd.get_list_slice("children")
+                .into_iter()
+                .flatten()
+                .for_each(|cvd| vds.push_back((cvd, section)));
+        }
+
+        Ok(None)
+    }
+
+    // stats for every vdev in the pool, keyed by guid, from a single stats call and walk of the
+    // vdev tree. parents come before their children, starting with the root. for polling, this
+    // is much cheaper than calling stats() on each vdev in turn
+    pub fn all_vdev_stats(&self) -> Result<Vec<(u64, nvtypes::VdevStats)>, Box<dyn Error>> {
+        let pl = self.get_stats()?;
+        let top = pl
+            .get_list("vdev_tree")
+            .ok_or_else(|| IOError::from(IOErrorKind::NotFound))?;
+
+        let mut stats = vec![];
+
+        let mut vds: VecDeque<&PairList> = VecDeque::new();
+        vds.push_back(top);
+
+        while let Some(vd) = vds.pop_front() {
+            if let Some(guid) = vd.get_u64("guid") {
+                let vs = vd
+                    .get_u64_slice("vdev_stats")
+                    .map(nvtypes::VdevStats::from)
+                    .unwrap_or_default();
+                stats.push((guid, vs));
+            }
+            vd.get_list_slice("children")
+                .into_iter()
+                .flatten()
+                .for_each(|cvd| vds.push_back(cvd));
+        }
+
+        Ok(stats)
+    }
+
+    // who the pool is and where it was last imported, from the top of its config
+    pub fn config_info(&self) -> Result<PoolConfigInfo, Box<dyn Error>> {
+        let pl = self.get_stats()?;
+        let need = |key| {
+            pl.get_u64(key)
+                .ok_or_else(|| IOError::from(IOErrorKind::NotFound))
+        };
+        Ok(PoolConfigInfo {
+            name: pl
+                .get_str("name")
+                .map(Cow::into_owned)
+                .ok_or_else(|| IOError::from(IOErrorKind::NotFound))?,
+            guid: need("pool_guid")?,
+            version: need("version")?,
+            txg: need("txg")?,
+            hostid: pl.get_u64("hostid"),
+            hostname: pl.get_str("hostname").map(Cow::into_owned),
+            comment: pl.get_str("comment").map(Cow::into_owned),
+        })
+    }
+
+    // a summary of the pool's condition, all from the one stats fetch: its state, the error
+    // counts over every vdev, whether a scrub is running, and how many permanent errors it has
+    pub fn health(&self) -> Result<PoolHealth, Box<dyn Error>> {
+        let vstats = self.all_vdev_stats()?;
+        let pl = self.get_stats()?;
+        let scan = pl
+            .get_list("vdev_tree")
+            .and_then(|vt| vt.get_u64_slice("scan_stats"))
+            .map(nvtypes::ScanStats::from)
+            .unwrap_or_default();
+
+        let sum = |f: fn(&nvtypes::VdevStats) -> u64| vstats.iter().map(|(_, vs)| f(vs)).sum();
+
+        Ok(PoolHealth {
+            state: VdevState::from(vstats.first().map_or(0, |(_, vs)| vs.state)),
+            read_errors: sum(|vs| vs.read_errors),
+            write_errors: sum(|vs| vs.write_errors),
+            checksum_errors: sum(|vs| vs.checksum_errors),
+            scrubbing: scan.func == POOL_SCAN_SCRUB && scan.state == DSS_SCANNING,
+            error_count: pl.get_u64("error_count").unwrap_or_default(),
+        })
+    }
+
+    // add vdevs to the pool. config is a root vdev with the new vdevs as its "children" (or
+    // "l2cache" or "spares"), the same shape as the pool's own vdev_tree. vdevs whose ashift
+    // doesn't match the pool are refused
+    pub fn add_vdev(&self, config: &PairList) -> Result<(), Box<dyn Error>> {
+        self.handle.vdev_add(&self.name, config)?;
+        self.refresh();
+        Ok(())
+    }
+
+    // true if the named dataset would be in this pool: the pool's own root dataset, or
+    // anything under it
+    fn owns(&self, dsname: &[u8]) -> bool {
+        let pname = self.name.as_bytes();
+        dsname.starts_with(pname) && (dsname.len() == pname.len() || dsname[pname.len()] == b'/')
+    }
+
+    pub fn datasets(&self) -> Result<Vec<Dataset>, Box<dyn Error>> {
+        Ok(self
+            .handle
+            .get_dataset_list()?
+            .iter()
+            .filter(|ds| self.owns(ds.to_bytes()))
+            .map(|ds| Dataset::new(self.handle.clone(), ds.into()))
+            .collect())
+    }
+
+    // the datasets in the pool of the types the filter wants, and their snapshots if it wants
+    // those, each dataset followed by its snapshots and then its children. unlike datasets(),
+    // nothing is fetched for the ones left out, which on a big pool is most of the work
+    pub fn datasets_filtered(&self, filter: DatasetFilter) -> Result<Vec<Objset>, Box<dyn Error>> {
+        Ok(self
+            .handle
+            .get_filtered_list(&self.name, &filter)?
+            .iter()
+            .map(|name| {
+                if name.to_bytes().contains(&b'@') {
+                    Objset::Snapshot(Snapshot::new(self.handle.clone(), name.into()))
+                } else {
+                    Objset::Dataset(Dataset::new(self.handle.clone(), name.into()))
+                }
+            })
+            .collect())
+    }
+
+    // every snapshot of every dataset in the pool, dataset by dataset. there can be a lot of
+    // them; all_snapshots_iter() gets them as they're needed instead. to put them in the order
+    // they were taken, sort by their "createtxg" property
+    pub fn all_snapshots(&self) -> Result<Vec<Snapshot>, Box<dyn Error>> {
+        self.all_snapshots_iter().collect()
+    }
+
+    // like all_snapshots(), but each dataset's snapshots are only listed once the ones before
+    // are used up. if the dataset list can't be fetched, that error is the only thing yielded
+    pub fn all_snapshots_iter(&self) -> impl Iterator<Item = Result<Snapshot, Box<dyn Error>>> {
+        let handle = self.handle.clone();
+        let (datasets, err) = match self.handle.get_dataset_list() {
+            Ok(list) => (
+                list.into_iter()
+                    .filter(|ds| self.owns(ds.to_bytes()))
+                    .collect(),
+                None,
+            ),
+            Err(e) => (vec![], Some(e)),
+        };
+        err.into_iter()
+            .map(Err)
+            .chain(datasets.into_iter().flat_map(move |ds| {
+                let handle = handle.clone();
+                let (snaps, err) = match handle.get_snapshot_list(ds) {
+                    Ok(snaps) => (snaps, None),
+                    Err(e) => (vec![], Some(e)),
+                };
+                err.into_iter().map(Err).chain(
+                    snaps
+                        .into_iter()
+                        .map(move |snap| Ok(Snapshot::new(handle.clone(), (&snap).into()))),
+                )
+            }))
+    }
+
+    // whether the pool is still there (eg not exported or destroyed since it was found). this
+    // always asks, and leaves the cached stats alone. only ENOENT means it's gone; any other
+    // error (eg EPERM) is returned, since it says nothing either way
+    pub fn exists(&self) -> Result<bool, Box<dyn Error>> {
+        match self.handle.get_pool(&self.name) {
+            Ok(_) => Ok(true),
+            Err(e) if error::raw_os_error(&*e) == Some(2) => Ok(false), // ENOENT
+            Err(e) => Err(e),
+        }
+    }
+
+    // the named dataset, if it exists. names outside this pool are never found, and nor are
+    // snapshots or bookmarks, which have their own types
+    pub fn dataset(&self, name: &str) -> Result<Option<Dataset>, Box<dyn Error>> {
+        if !self.owns(name.as_bytes()) || name.contains(['@', '#']) {
+            return Ok(None);
+        }
+        let cname = CString::new(name)?;
+        match self.handle.get_dataset(&cname) {
+            Ok(_) => Ok(Some(Dataset::new(self.handle.clone(), (&cname).into()))),
+            Err(e) if error::raw_os_error(&*e) == Some(2) => Ok(None), // ENOENT
+            Err(e) => Err(e),
+        }
+    }
+}
+
+// the state of the pool as a whole, as a monitor would want it. see Pool::health(). the error
+// counts are totals over every vdev in the tree, and an error at a leaf is usually counted by
+// its parents too, so treat them as "non-zero means look closer" rather than a number of bad
+// blocks
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PoolHealth {
+    pub state: VdevState, // of the root vdev, which is what zpool shows as the pool's health
+    pub read_errors: u64,
+    pub write_errors: u64,
+    pub checksum_errors: u64,
+    pub scrubbing: bool,  // includes a paused scrub; resilvers don't count
+    pub error_count: u64, // permanent errors, that is, entries in the pool's error log
+}
+
+// pool_scan_func_t
+const POOL_SCAN_SCRUB: u64 = 1;
+const POOL_SCAN_RESILVER: u64 = 2;
+
+// dsl_scan_state_t
+const DSS_SCANNING: u64 = 1;
+const DSS_FINISHED: u64 = 2;
+const DSS_CANCELED: u64 = 3;
+
+// vdev_aux_t: a spare that's standing in for another vdev
+const VDEV_AUX_SPARED: u64 = 10;
+
+// what zpool status shows, from Pool::status(). the vdevs are as in Pool::vdev_tree(), with the
+// spares and cache devices (which aren't in the tree) alongside. scan is None if the pool has
+// never been scrubbed or resilvered
+#[derive(Debug)]
+pub struct PoolStatus {
+    pub name: String,
+    pub state: VdevState,
+    pub tree: VdevNode,
+    pub spares: Vec<VdevNode>,
+    pub cache: Vec<VdevNode>,
+    pub scan: Option<nvtypes::ScanStats>,
+    pub error_count: u64,
+}
+
+// zpool_state_to_name(), for the states a vdev can actually be in
+fn state_name(state: VdevState) -> &'static str {
+    match state {
+        VdevState::Closed | VdevState::Offline => "OFFLINE",
+        VdevState::Removed => "REMOVED",
+        VdevState::CantOpen => "UNAVAIL",
+        VdevState::Faulted => "FAULTED",
+        VdevState::Degraded => "DEGRADED",
+        VdevState::Healthy => "ONLINE",
+        VdevState::Unknown => "UNKNOWN",
+    }
+}
+
+// zfs_nicenum(): at most five characters, in powers of 1024, with as many decimal places as
+// will fit. bytes get a B when they're too small for any other suffix
+fn nicenum(n: u64, bytes: bool) -> String {
+    const UNITS: &[&str] = &["", "K", "M", "G", "T", "P", "E"];
+    let mut i = 0;
+    while i + 1 < UNITS.len() && n >= 1 << (10 * (i + 1)) {
+        i += 1;
+    }
+    if i == 0 {
+        return format!("{}{}", n, if bytes { "B" } else { "" });
+    }
+    if n & ((1 << (10 * i)) - 1) == 0 {
+        return format!("{}{}", n >> (10 * i), UNITS[i]);
+    }
+    let v = n as f64 / (1u64 << (10 * i)) as f64;
+    (0..=2)
+        .rev()
+        .map(|p| format!("{:.*}{}", p, v, UNITS[i]))
+        .find(|s| s.len() <= 5)
+        .unwrap_or_else(|| format!("{:.0}{}", v, UNITS[i]))
+}
+
+impl PoolStatus {
+    fn fmt_scan(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Some(scan) = self.scan.as_ref().filter(|s| s.func != 0) else {
+            return writeln!(f, "  scan: none requested");
+        };
+        let what = if scan.func == POOL_SCAN_RESILVER {
+            "resilver"
+        } else {
+            "scrub"
+        };
+        let secs = scan.end_time.saturating_sub(scan.start_time);
+        let took = format!("{:02}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60);
+        match scan.state {
+            DSS_FINISHED if scan.func == POOL_SCAN_RESILVER => writeln!(
+                f,
+                "  scan: resilvered {} in {} with {} errors",
+                nicenum(scan.processed, true),
+                took,
+                scan.errors
+            ),
+            DSS_FINISHED => writeln!(
+                f,
+                "  scan: scrub repaired {} in {} with {} errors",
+                nicenum(scan.processed, true),
+                took,
+                scan.errors
+            ),
+            DSS_CANCELED => writeln!(f, "  scan: {} canceled", what),
+            DSS_SCANNING => writeln!(
+                f,
+                "  scan: {} in progress, {} / {} scanned, {} issued",
+                what,
+                nicenum(scan.examined, true),
+                nicenum(scan.to_examine, true),
+                nicenum(scan.issued, true)
+            ),
+            _ => writeln!(f, "  scan: none requested"),
+        }
+    }
+
+    // one vdev and everything under it
+    fn fmt_vdev(
+        f: &mut fmt::Formatter<'_>,
+        vd: &VdevNode,
+        depth: usize,
+        width: usize,
+    ) -> fmt::Result {
+        PoolStatus::fmt_vdev_line(f, vd, depth, width)?;
+        for cvd in &vd.children {
+            PoolStatus::fmt_vdev(f, cvd, depth + 1, width)?;
+        }
+        Ok(())
+    }
+
+    fn fmt_vdev_line(
+        f: &mut fmt::Formatter<'_>,
+        vd: &VdevNode,
+        depth: usize,
+        width: usize,
+    ) -> fmt::Result {
+        let vs = &vd.stats;
+        writeln!(
+            f,
+            "\t{:indent$}{:<w$}  {:<8} {:>5} {:>5} {:>5}",
+            "",
+            vd.name,
+            state_name(VdevState::from(vs.state)),
+            nicenum(vs.read_errors, false),
+            nicenum(vs.write_errors, false),
+            nicenum(vs.checksum_errors, false),
+            indent = depth * 2,
+            w = width - depth * 2,
+        )
+    }
+}
+
+// the widest name in the tree, counting the indent it'll have
+fn name_width(vd: &VdevNode, depth: usize) -> usize {
+    vd.children
+        .iter()
+        .map(|cvd| name_width(cvd, depth + 1))
+        .fold(vd.name.len() + depth * 2, usize::max)
+}
+
+impl fmt::Display for PoolStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "  pool: {}", self.name)?;
+        writeln!(f, " state: {}", state_name(self.state))?;
+        self.fmt_scan(f)?;
+        writeln!(f, "config:")?;
+        writeln!(f)?;
+
+        let width = self
+            .spares
+            .iter()
+            .chain(self.cache.iter())
+            .map(|vd| name_width(vd, 1))
+            .fold(name_width(&self.tree, 0).max(4), usize::max);
+
+        writeln!(
+            f,
+            "\t{:<width$}  {:<8} {:>5} {:>5} {:>5}",
+            "NAME", "STATE", "READ", "WRITE", "CKSUM"
+        )?;
+        PoolStatus::fmt_vdev_line(f, &self.tree, 0, width)?;
+        for vd in self
+            .tree
+            .children
+            .iter()
+            .filter(|vd| vd.is_listed() && vd.class().is_none())
+        {
+            PoolStatus::fmt_vdev(f, vd, 1, width)?;
+        }
+
+        // then each allocation class, in the order zpool status has them
+        for (class, header) in [
+            (AllocBias::Dedup, "dedup"),
+            (AllocBias::Special, "special"),
+            (AllocBias::Log, "logs"),
+        ] {
+            let vdevs: Vec<_> = self
+                .tree
+                .children
+                .iter()
+                .filter(|vd| vd.is_listed() && vd.class() == Some(class))
+                .collect();
+            if !vdevs.is_empty() {
+                writeln!(f, "\t{}", header)?;
+                for vd in vdevs {
+                    PoolStatus::fmt_vdev(f, vd, 1, width)?;
+                }
+            }
+        }
+        if !self.cache.is_empty() {
+            writeln!(f, "\tcache")?;
+            for vd in &self.cache {
+                PoolStatus::fmt_vdev(f, vd, 1, width)?;
+            }
+        }
+        if !self.spares.is_empty() {
+            writeln!(f, "\tspares")?;
+            for vd in &self.spares {
+                let state = match VdevState::from(vd.stats.state) {
+                    VdevState::Healthy if vd.stats.aux == VDEV_AUX_SPARED => "INUSE",
+                    VdevState::Healthy => "AVAIL",
+                    state => state_name(state),
+                };
+                writeln!(f, "\t  {:<w$}  {}", vd.name, state, w = width - 2)?;
+            }
+        }
+
+        writeln!(f)?;
+        match self.error_count {
+            0 => writeln!(f, "errors: No known data errors"),
+            n => writeln!(f, "errors: {} data errors, use '-v' for a list", n),
+        }
+    }
+}
+
+// the identifying part of a pool's config, as zpool import lists it. hostid and hostname are of
+// the system that last imported the pool; if they're not this one's, and the pool's not
+// exported, it may still be in use over there
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PoolConfigInfo {
+    pub name: String,
+    pub guid: u64,
+    pub version: u64, // SPA_VERSION; 5000 means feature flags
+    pub txg: u64,     // when the config was last written
+    pub hostid: Option<u64>,
+    pub hostname: Option<String>,
+    pub comment: Option<String>, // set with the comment property
+}
+
+// a dataset property's value, as the kernel has it. index properties (compression, checksum,
+// etc) and booleans are numbers here, not the names zfs get shows
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PropValue {
+    Number(u64),
+    String(String),
+}
+
+// name, value, source
+type PropEntry = (String, PropValue, PropSource);
+
+// space accounting for a dataset, in bytes. used is the other four added up. anything the
+// kernel didn't report (eg the breakdown, for a snapshot) is 0
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SpaceUsage {
+    pub available: u64,
+    pub used: u64,
+    pub snapshots: u64, // usedbysnapshots: freed if every snapshot were destroyed
+    pub dataset: u64,   // usedbydataset: referenced by the dataset itself
+    pub children: u64,  // usedbychildren: everything under it
+    pub refreservation: u64, // usedbyrefreservation: held back by refreservation, not yet used
+}
+
+// IO done on a pool between two samples, as from IoStatSampler
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct IoDelta {
+    pub read_bytes: u64,
+    pub write_bytes: u64,
+    pub read_ops: u64,
+    pub write_ops: u64,
+}
+
+impl IoDelta {
+    // the difference between two sets of cumulative stats. the counters start again from zero
+    // when the pool is imported, so any that went backwards count as zero
+    pub fn between(old: &nvtypes::VdevStats, new: &nvtypes::VdevStats) -> IoDelta {
+        IoDelta {
+            read_bytes: new.read_bytes().saturating_sub(old.read_bytes()),
+            write_bytes: new.write_bytes().saturating_sub(old.write_bytes()),
+            read_ops: new.read_ops().saturating_sub(old.read_ops()),
+            write_ops: new.write_ops().saturating_sub(old.write_ops()),
+        }
+    }
+}
+
+// pool IO per interval, like zpool iostat. each sample() refreshes the pool and returns what's
+// been done since the one before. there's nothing to compare the first one to, so like the
+// first line of zpool iostat, it's everything since the pool was imported
+pub struct IoStatSampler {
+    pool: Pool,
+    last: nvtypes::VdevStats,
+}
+
+impl IoStatSampler {
+    pub fn new(pool: &Pool) -> IoStatSampler {
+        IoStatSampler {
+            pool: pool.clone(),
+            last: Default::default(),
+        }
+    }
+
+    pub fn pool(&self) -> &Pool {
+        &self.pool
+    }
+
+    pub fn sample(&mut self) -> Result<IoDelta, Box<dyn Error>> {
+        self.pool.refresh();
+        let vs = self.pool.root_vdev()?.stats()?;
+        let delta = IoDelta::between(&self.last, &vs);
+        self.last = vs;
+        Ok(delta)
+    }
+}
+
+// one vdev in the tree from Pool::vdev_tree(). path is the device (or file) for leaves, and
+// None for everything else. name is what zpool status calls it: the pool name for the root,
+// the path (less any /dev/) for leaves, and eg mirror-0 or raidz2-1 for everything between.
+// is_log is set for a log vdev and everything under it. spares and cache devices aren't in
+// the tree; see Pool::spares() and Pool::cache_devices()
+#[derive(Debug)]
+pub struct VdevNode {
+    pub guid: u64,
+    pub name: String,
+    pub typ: VdevType,
+    pub path: Option<String>,
+    pub is_log: bool,
+    pub alloc_bias: Option<AllocBias>, // top-level vdevs in an allocation class only
+    pub stats: nvtypes::VdevStats,
+    pub children: Vec<VdevNode>,
+}
+
+impl VdevNode {
+    // for a top-level vdev, the section zpool status lists it under: None for the main tree,
+    // or its allocation class
+    fn class(&self) -> Option<AllocBias> {
+        match self.is_log {
+            true => Some(AllocBias::Log),
+            false => self.alloc_bias,
+        }
+    }
+
+    // holes and indirect vdevs (what's left after a removal) hold a place in the tree, but
+    // zpool status doesn't show them
+    fn is_listed(&self) -> bool {
+        !matches!(self.typ, VdevType::Hole | VdevType::Indirect)
+    }
+
+    // in_log is true under a log vdev, which everything below it is part of, like Vdev::is_log()
+    fn new(vl: &PairList, in_log: bool) -> Result<VdevNode, Box<dyn Error>> {
+        let guid = vl
+            .get_u64("guid")
+            .ok_or_else(|| IOError::from(IOErrorKind::NotFound))?;
+        let typ = vl
+            .get_c_string("type")
+            .ok_or_else(|| IOError::from(IOErrorKind::NotFound))?;
+        let is_log = in_log || vl.get_u64("is_log").unwrap_or_default() != 0;
+        let path = vl.get_str("path").map(Cow::into_owned);
+        let name = match (&path, vl.get_u64("nparity")) {
+            (Some(path), _) => path.strip_prefix("/dev/").unwrap_or(path).to_string(),
+            (None, Some(np)) if typ.to_bytes() == b"raidz" => {
+                format!("raidz{}-{}", np, vl.get_u64("id").unwrap_or_default())
+            }
+            (None, _) => format!(
+                "{}-{}",
+                typ.to_string_lossy(),
+                vl.get_u64("id").unwrap_or_default()
+            ),
+        };
+        Ok(VdevNode {
+            guid,
+            name,
+            typ: (&typ).into(),
+            path,
+            is_log,
+            alloc_bias: vl.get_c_string("alloc_bias").map(|s| (&s).into()),
+            stats: vl
+                .get_u64_slice("vdev_stats")
+                .map(nvtypes::VdevStats::from)
+                .unwrap_or_default(),
+            children: vl
+                .get_list_slice("children")
+                .unwrap_or_default()
+                .iter()
+                .map(|cvl| VdevNode::new(cvl, is_log))
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+// which part of the pool config a vdev was found in. spares and cache devices are kept apart
+// from the tree. logs are in it, as top-level vdevs marked is_log, and everything under one is
+// part of that log too
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum VdevSection {
+    Main,
+    Log,
+    Spare,
+    Cache,
+}
+
+impl VdevSection {
+    // the section for vl, found in this one
+    fn of(self, vl: &PairList) -> VdevSection {
+        match self {
+            VdevSection::Main if vl.get_u64("is_log").unwrap_or_default() != 0 => VdevSection::Log,
+            _ => self,
+        }
+    }
+}
+
+// a vdev keeps its own part of the config tree, as it was when it was got from the pool. to
+// see newer stats, refresh the pool and get the vdev again
+pub struct Vdev {
+    handle: Rc<Handle>,
+    pool: AutoString,
+    guid: u64,
+    typ: VdevType,
+    section: VdevSection,
+    list: PairList,
+}
+
+impl Vdev {
+    // section is where vl was found: for a child, its parent's section
+    fn new(
+        handle: Rc<Handle>,
+        pool: AutoString,
+        vl: &PairList,
+        section: VdevSection,
+    ) -> Result<Vdev, Box<dyn Error>> {
+        let guid = vl
+            .get_u64("guid")
+            .ok_or_else(|| IOError::from(IOErrorKind::NotFound))?;
+        let typ = vl
+            .get_c_string("type")
+            .ok_or_else(|| IOError::from(IOErrorKind::NotFound))?;
+        Ok(Vdev {
+            handle,
+            pool,
+            guid,
+            typ: (&typ).into(),
+            section: section.of(vl),
+            list: vl.clone(),
+        })
+    }
+
+    pub fn guid(&self) -> u64 {
+        self.guid
+    }
+
+    pub fn typ(&self) -> VdevType {
+        self.typ
+    }
+
+    // parity disks for raidz and draid vdevs; None for everything else
+    pub fn nparity(&self) -> Option<u64> {
+        self.list.get_u64("nparity")
+    }
+
+    // log2 of the sector size the vdev was set up with. only top-level vdevs have one; leaves
+    // use their parent's
+    pub fn ashift(&self) -> Option<u64> {
+        self.list.get_u64("ashift")
+    }
+
+    // true for a top-level vdev that's a separate intent log, and for everything under one
+    pub fn is_log(&self) -> bool {
+        self.section == VdevSection::Log
+    }
+
+    // true for an l2arc cache device, from Pool::cache_devices()
+    pub fn is_cache(&self) -> bool {
+        self.section == VdevSection::Cache
+    }
+
+    // true for a hot spare: one from Pool::spares(), or one in the tree that's standing in for
+    // a failed device
+    pub fn is_spare(&self) -> bool {
+        self.section == VdevSection::Spare || self.list.get_u64("is_spare").unwrap_or_default() != 0
+    }
+
+    // the allocation class a top-level vdev was added for (eg zpool add tank special ...).
+    // None for ordinary vdevs, and for pools too old to record it
+    pub fn alloc_bias(&self) -> Option<AllocBias> {
+        self.list.get_c_string("alloc_bias").map(|s| (&s).into())
+    }
+
+    // a child without a guid or type is an error, rather than being left out of the tree
+    pub fn children(&self) -> Result<Vec<Vdev>, Box<dyn Error>> {
+        self.list
+            .get_list_slice("children")
+            .unwrap_or_default()
+            .iter()
+            .map(|vl| Vdev::new(self.handle.clone(), self.pool.clone(), vl, self.section))
+            .collect()
+    }
+
+    // attach a new leaf vdev (a config list with at least "type" and "path") alongside this
+    // one, making a mirror, or widening an existing one. returns once the resilver is started.
+    // refresh the pool to see the new vdev
+    pub fn attach(&self, new: &PairList) -> Result<(), Box<dyn Error>> {
+        self.handle.vdev_attach(&self.pool, self.guid, new, false)
+    }
+
+    // replace this vdev with a new leaf. this vdev is detached when the resilver completes
+    pub fn replace(&self, new: &PairList) -> Result<(), Box<dyn Error>> {
+        self.handle.vdev_attach(&self.pool, self.guid, new, true)
+    }
+
+    // remove this vdev from the pool. for top-level vdevs, the data is copied elsewhere in the
+    // background; watch the pool's removal stats and this vdev's scan_removing stat
+    pub fn remove(&self) -> Result<(), Box<dyn Error>> {
+        self.handle.vdev_remove(&self.pool, self.guid)
+    }
+
+    // bring the vdev back online, returning the state it's now in
+    pub fn online(&self) -> Result<VdevState, Box<dyn Error>> {
+        self.handle.vdev_set_state(
+            &self.pool,
+            self.guid,
+            VdevStateCmd::Online { expand: false },
+        )
+    }
+
+    // take the vdev offline, eg for maintenance. it stays offline across export and reboot
+    pub fn offline(&self) -> Result<VdevState, Box<dyn Error>> {
+        self.handle.vdev_set_state(
+            &self.pool,
+            self.guid,
+            VdevStateCmd::Offline { temporary: false },
+        )
+    }
+
+    // force the vdev into the faulted state, so ZFS stops using it
+    pub fn fault(&self) -> Result<VdevState, Box<dyn Error>> {
+        self.handle
+            .vdev_set_state(&self.pool, self.guid, VdevStateCmd::Faulted)
+    }
+
+    // detach this vdev from its mirror
+    pub fn detach(&self) -> Result<(), Box<dyn Error>> {
+        self.handle.vdev_detach(&self.pool, self.guid)
+    }
+
+    pub fn stats(&self) -> Result<nvtypes::VdevStats, Box<dyn Error>> {
+        Ok(self
+            .list
+            .get_u64_slice("vdev_stats")
+            .map(nvtypes::VdevStats::from)
+            .unwrap_or_default())
+    }
+
+    pub fn stats_ex(&self) -> Result<nvtypes::VdevStatsEx, Box<dyn Error>> {
+        Ok(self
+            .list
+            .get_list("vdev_stats_ex")
+            .map(nvtypes::VdevStatsEx::from)
+            .unwrap_or_default())
+    }
+}
+
+// which kinds of dataset Pool::datasets_filtered() lists. the default is all of them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DatasetFilter {
+    pub fs: bool,
+    pub vol: bool,
+    pub snap: bool,
+}
+
+impl Default for DatasetFilter {
+    fn default() -> Self {
+        DatasetFilter {
+            fs: true,
+            vol: true,
+            snap: true,
+        }
+    }
+}
+
+// filesystems and volumes, no snapshots; what the plain dataset lists are made of
+const DATASETS_ONLY: DatasetFilter = DatasetFilter {
+    fs: true,
+    vol: true,
+    snap: false,
+};
+
+impl DatasetFilter {
+    fn wants(&self, typ: ObjsetType) -> bool {
+        match typ {
+            ObjsetType::Zfs => self.fs,
+            ObjsetType::Zvol => self.vol,
+            _ => false,
+        }
+    }
+}
+
+// a dataset or a snapshot, from Pool::datasets_filtered()
+#[derive(Clone)]
+pub enum Objset {
+    Dataset(Dataset),
+    Snapshot(Snapshot),
+}
+
+impl Objset {
+    pub fn name(&self) -> &AutoString {
+        match self {
+            Objset::Dataset(ds) => ds.name(),
+            Objset::Snapshot(snap) => snap.name(),
+        }
+    }
+}
+
+// the protocols a dataset can be shared over, with Dataset::share()
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShareProtocol {
+    Nfs,
+    Smb,
+}
+
+// like Pool, the dataset's stats and properties are fetched on first use and then kept, so
+// everything read from it is as it was at that moment. call refresh() to see changes
+#[derive(Clone)]
+pub struct Dataset {
+    handle: Rc<Handle>,
+    name: AutoString,
+    stats: RefCell<Option<(PairList, ioc::ObjsetStats)>>,
+}
+
+impl PartialEq for Dataset {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
+}
+
+impl Eq for Dataset {}
+
+impl Dataset {
+    fn new(handle: Rc<Handle>, name: AutoString) -> Dataset {
+        Dataset {
+            handle,
+            name,
+            stats: RefCell::new(None),
+        }
+    }
+
+    pub fn name(&self) -> &AutoString {
+        &self.name
+    }
+
+    // the dataset this one is in, or None for the pool's root dataset. this is only the name;
+    // nothing is asked of the kernel until it's used
+    pub fn parent(&self) -> Option<Dataset> {
+        let name = self.name.as_bytes();
+        let slash = name.iter().rposition(|&c| c == b'/')?;
+        let parent = CString::new(&name[..slash]).unwrap();
+        Some(Dataset::new(self.handle.clone(), (&parent).into()))
+    }
+
+    // the pool this dataset is in
+    pub fn pool(&self) -> Pool {
+        let name = self.name.as_bytes();
+        let slash = name.iter().position(|&c| c == b'/').unwrap_or(name.len());
+        let pool = CString::new(&name[..slash]).unwrap();
+        Pool::new(self.handle.clone(), (&pool).into())
+    }
+
+    // drop the cached stats and properties; the next call will fetch them fresh
+    pub fn refresh(&self) {
+        self.stats.replace(None);
+    }
+
+    fn get_stats(&self) -> Result<Ref<'_, (PairList, ioc::ObjsetStats)>, Box<dyn Error>> {
+        if self.stats.borrow().is_none() {
+            let stats = self.handle.get_dataset_stats(&self.name)?;
+            self.stats.replace(Some(stats));
+        }
+        Ok(Ref::map(self.stats.borrow(), |s| s.as_ref().unwrap()))
+    }
+
+    pub fn guid(&self) -> Result<u64, Box<dyn Error>> {
+        Ok(self.get_stats()?.1.guid)
+    }
+
+    pub fn creation_txg(&self) -> Result<u64, Box<dyn Error>> {
+        Ok(self.get_stats()?.1.creation_txg)
+    }
+
+    // drop this dataset's references to indirect mappings left by a vdev removal, like zfs
+    // remap. see ioc::Handle::remap(); current kernels do nothing
+    pub fn remap(&self) -> Result<(), Box<dyn Error>> {
+        self.handle.remap_dataset(&self.name)
+    }
+
+    // publish this dataset's mountpoint as an NFS or SMB share, with the options from its
+    // sharenfs or sharesmb property, or withdraw it, through the kernel. only kernels that
+    // still do sharing themselves take this; OpenZFS 2.0 and later say ENOSYS, and shares
+    // there come from setting sharenfs or sharesmb instead. a dataset with nowhere to mount
+    // (a volume, or mountpoint=none or legacy) can't be shared
+    pub fn share(&self, proto: ShareProtocol) -> Result<(), Box<dyn Error>> {
+        let op = match proto {
+            ShareProtocol::Nfs => ShareOp::ShareNfs,
+            ShareProtocol::Smb => ShareOp::ShareSmb,
+        };
+        self.handle
+            .share_dataset(&self.name, &self.share_data(proto)?, op)
+    }
+
+    pub fn unshare(&self, proto: ShareProtocol) -> Result<(), Box<dyn Error>> {
+        let op = match proto {
+            ShareProtocol::Nfs => ShareOp::UnshareNfs,
+            ShareProtocol::Smb => ShareOp::UnshareSmb,
+        };
+        self.handle
+            .share_dataset(&self.name, &self.share_data(proto)?, op)
+    }
+
+    fn share_data(&self, proto: ShareProtocol) -> Result<ShareData, Box<dyn Error>> {
+        let export = self
+            .mountpoint()?
+            .ok_or_else(|| IOError::from(IOErrorKind::NotFound))?;
+        let prop = match proto {
+            ShareProtocol::Nfs => "sharenfs",
+            ShareProtocol::Smb => "sharesmb",
+        };
+        Ok(ShareData {
+            export: CString::new(export.into_os_string().into_vec())?,
+            options: CString::new(self.get_prop_string(prop)?.unwrap_or_default())?,
+        })
+    }
+
+    // the bytes charged to one user or group here, as zfs userspace and zfs groupspace show
+    // them. 0 if they have nothing here
+    pub fn user_used(&self, uid: u32) -> Result<u64, Box<dyn Error>> {
+        self.handle
+            .get_userspace_one(&self.name, UserQuotaType::UserUsed, uid.into())
+    }
+
+    pub fn group_used(&self, gid: u32) -> Result<u64, Box<dyn Error>> {
+        self.handle
+            .get_userspace_one(&self.name, UserQuotaType::GroupUsed, gid.into())
+    }
+
+    // when the dataset was created, from the creation property (seconds since the epoch)
+    pub fn creation_time(&self) -> Result<SystemTime, Box<dyn Error>> {
+        let secs = self
+            .get_prop_u64("creation")?
+            .ok_or_else(|| IOError::from(IOErrorKind::NotFound))?;
+        Ok(UNIX_EPOCH + Duration::from_secs(secs))
+    }
+
+    pub fn is_snapshot(&self) -> Result<bool, Box<dyn Error>> {
+        Ok(self.get_stats()?.1.is_snapshot)
+    }
+
+    // if this is a clone, the snapshot it was cloned from
+    pub fn origin(&self) -> Result<Option<Snapshot>, Box<dyn Error>> {
+        let stats = self.get_stats()?;
+        let origin = &stats.1.origin;
+        Ok((!origin.is_empty()).then(|| Snapshot::new(self.handle.clone(), origin.into())))
+    }
+
+    // filesystem or volume
+    pub fn kind(&self) -> Result<ObjsetType, Box<dyn Error>> {
+        Ok(self
+            .get_prop_u64("type")?
+            .map_or(ObjsetType::Unknown, ObjsetType::from))
+    }
+
+    // where the space is going, like zfs list -o space
+    pub fn space(&self) -> Result<SpaceUsage, Box<dyn Error>> {
+        let stats = self.get_stats()?;
+        let prop = |name| {
+            stats
+                .0
+                .get_list(name)
+                .and_then(|l| l.get_u64("value"))
+                .unwrap_or_default()
+        };
+        Ok(SpaceUsage {
+            available: prop("available"),
+            used: prop("used"),
+            snapshots: prop("usedbysnapshots"),
+            dataset: prop("usedbydataset"),
+            children: prop("usedbychildren"),
+            refreservation: prop("usedbyrefreservation"),
+        })
+    }
+
+    // true if the filesystem is mounted anywhere right now, according to the mount table
+    pub fn is_mounted(&self) -> Result<bool, Box<dyn Error>> {
+        Ok(sys::zfs_mounts()?
+            .iter()
+            .any(|m| m.as_c_str() == self.name.as_c_str()))
+    }
+
+    // where the filesystem mounts, working out inherited and default mountpoints the same way
+    // zfs does. None for volumes, and for mountpoint=none or legacy (mounted by some other
+    // means). doesn't include the pool's altroot, if it has one
+    pub fn mountpoint(&self) -> Result<Option<PathBuf>, Box<dyn Error>> {
+        if self.kind()? != ObjsetType::Zfs {
+            return Ok(None);
+        }
+
+        // the property's value is the mountpoint of the dataset it's set on, so find out which
+        // that is, and add the rest of our name onto it. if it's not set anywhere, it's as if
+        // the pool's root dataset had "/"
+        let name = self.name.as_str();
+        let (value, root) = match self.get_prop("mountpoint")? {
+            None => ("/".to_string(), ""),
+            Some(l) => {
+                let value = l.get_str("value").map(Cow::into_owned).unwrap_or_default();
+                let root = match PropSource::new(
+                    l.get_c_string("source").as_deref(),
+                    self.name.as_c_str(),
+                ) {
+                    PropSource::Inherited(from) => name
+                        .get(..from.len())
+                        .filter(|r| *r == from)
+                        .unwrap_or(name),
+                    PropSource::Default => "",
+                    _ => name,
+                };
+                (value, root)
+            }
+        };
+
+        if matches!(value.as_str(), "none" | "legacy" | "-" | "") {
+            return Ok(None);
+        }
+
+        let mut path = PathBuf::from(value);
+        let rest = name[root.len()..].trim_start_matches('/');
+        if !rest.is_empty() {
+            path.push(rest);
+        }
+        Ok(Some(path))
+    }
+
+    // whether the dataset is still there. like Pool::exists(), this always asks, and only
+    // ENOENT means it's gone
+    pub fn exists(&self) -> Result<bool, Box<dyn Error>> {
+        match self.handle.get_dataset(&self.name) {
+            Ok(_) => Ok(true),
+            Err(e) if error::raw_os_error(&*e) == Some(2) => Ok(false), // ENOENT
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn snapshots(&self) -> Result<Vec<Snapshot>, Box<dyn Error>> {
+        Ok(self
+            .handle
+            .get_snapshot_list(&self.name)?
+            .iter()
+            .map(|snap| Snapshot::new(self.handle.clone(), snap.into()))
+            .collect())
+    }
+
+    // a snapshot of this dataset that's only kept while the TempSnapshot is, for reading a
+    // consistent copy without leaving anything behind. it's named prefix and a unique suffix.
+    // the kernel destroys it when the guard is dropped, or if the process dies first
+    pub fn temp_snapshot(&self, prefix: &str) -> Result<TempSnapshot, Box<dyn Error>> {
+        let (name, cleanup) = self
+            .handle
+            .tmp_snapshot(&self.name, &CString::new(prefix)?)?;
+        Ok(TempSnapshot {
+            snap: Snapshot::new(self.handle.clone(), (&name).into()),
+            _cleanup: cleanup,
+        })
+    }
+
+    // the bookmark dataset#name, if there is one
+    pub fn bookmark(&self, name: &str) -> Result<Option<Bookmark>, Box<dyn Error>> {
+        let mut bmname = self.name.as_bytes().to_vec();
+        bmname.push(b'#');
+        bmname.extend_from_slice(name.as_bytes());
+        let bmname = CString::new(bmname)?;
+        match self.handle.get_bookmark_props(&bmname) {
+            Ok(_) => Ok(Some(Bookmark::new(self.handle.clone(), (&bmname).into()))),
+            Err(e) if error::raw_os_error(&*e) == Some(2) => Ok(None), // ENOENT
+            Err(e) => Err(e),
+        }
+    }
+
+    // take a snapshot of this dataset, called snapname (the part after the @). with recursive,
+    // every dataset below this one gets a snapshot of the same name too, all at the same
+    // moment: if any of them can't be taken (eg one already has a snapshot by that name), none
+    // are, and the error is an ioc::PartialFailure naming it
+    pub fn snapshot(
+        &self,
+        snapname: &str,
+        recursive: bool,
+    ) -> Result<Vec<Snapshot>, Box<dyn Error>> {
+        let mut datasets = vec![self.name.as_c_str().to_owned()];
+        if recursive {
+            datasets.extend(self.handle.get_descendant_list(&self.name)?);
+        }
+        let snaps = datasets
+            .iter()
+            .map(|ds| {
+                let mut snap = ds.as_bytes().to_vec();
+                snap.push(b'@');
+                snap.extend_from_slice(snapname.as_bytes());
+                CString::new(snap)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        self.handle.create_snapshots(&snaps)?;
+        Ok(snaps
+            .iter()
+            .map(|snap| Snapshot::new(self.handle.clone(), snap.into()))
+            .collect())
+    }
+
+    // receive a send stream into this filesystem, as a new snapshot with the same short name
+    // as the one it was sent from. the stream is usually incremental from the latest snapshot
+    // here; with force, any changes since that snapshot are rolled back first. with resumable,
+    // a receive that's cut short can be picked up again from receive_resume_token()
+    pub fn receive_from(
+        &self,
+        mut reader: impl Read + Send + 'static,
+        force: bool,
+        resumable: bool,
+    ) -> Result<Snapshot, Box<dyn Error>> {
+        let mut begin = [0; ioc::BEGIN_RECORD_SIZE];
+        reader.read_exact(&mut begin)?;
+        let toname = ioc::stream_toname(&begin)?;
+        let short = toname
+            .to_bytes()
+            .splitn(2, |&c| c == b'@')
+            .nth(1)
+            .ok_or_else(|| IOError::from(IOErrorKind::InvalidData))?;
+        let snap = CString::new([self.name.as_bytes(), b"@", short].concat())?;
+
+        self.handle.recv(&snap, begin, reader, force, resumable)?;
+        self.refresh();
+        Ok(Snapshot::new(self.handle.clone(), (&snap).into()))
+    }
+
+    // the token to resume an interrupted resumable receive from (with zfs send -t), if there
+    // is one
+    pub fn receive_resume_token(&self) -> Result<Option<String>, Box<dyn Error>> {
+        self.get_prop_string("receive_resume_token")
+    }
+
+    fn get_prop(&self, prop: impl PairKey) -> Result<Option<PairList>, Box<dyn Error>> {
+        Ok(self.get_stats()?.0.get_list(prop).cloned())
+    }
+
+    // props are named by any PairKey: a &str, or a &CStr (or its to_bytes()), so C names can
+    // be used as they are
+    pub fn get_prop_u64(&self, prop: impl PairKey) -> Result<Option<u64>, Box<dyn Error>> {
+        Ok(self.get_prop(prop)?.and_then(|l| l.get_u64("value")))
+    }
+
+    pub fn get_prop_string(&self, prop: impl PairKey) -> Result<Option<String>, Box<dyn Error>> {
+        Ok(self
+            .get_prop(prop)?
+            .and_then(|l| l.get_str("value").map(Cow::into_owned)))
+    }
+
+    // set properties, as zfs set does. values are as the kernel keeps them: numbers for index
+    // and boolean properties, strings for the rest. if only some could be set, the error is an
+    // ioc::PartialFailure. either way, the cached stats are dropped, so the next read sees
+    // whatever changed
+    pub fn set_props(&self, props: &PairList) -> Result<(), Box<dyn Error>> {
+        let res = self.handle.set_dataset_props(&self.name, props);
+        self.refresh();
+        res
+    }
+
+    pub fn set_prop_u64(&self, prop: impl Into<Vec<u8>>, value: u64) -> Result<(), Box<dyn Error>> {
+        let mut props = PairList::new();
+        props.add(prop, PairValue::UInt64(value));
+        self.set_props(&props)
+    }
+
+    pub fn set_prop_string(
+        &self,
+        prop: impl Into<Vec<u8>>,
+        value: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut props = PairList::new();
+        props.add(prop, PairValue::String(CString::new(value)?));
+        self.set_props(&props)
+    }
+
+    // the on/off properties, as bools. the kernel keeps them as 0 and 1, and leaves them out
+    // when they've never been set anywhere, which means the default
+    pub fn readonly(&self) -> Result<bool, Box<dyn Error>> {
+        Ok(self.get_prop_u64("readonly")?.is_some_and(|n| n != 0))
+    }
+
+    pub fn set_readonly(&self, on: bool) -> Result<(), Box<dyn Error>> {
+        self.set_prop_u64("readonly", on.into())
+    }
+
+    pub fn atime(&self) -> Result<bool, Box<dyn Error>> {
+        Ok(self.get_prop_u64("atime")? != Some(0))
+    }
+
+    pub fn set_atime(&self, on: bool) -> Result<(), Box<dyn Error>> {
+        self.set_prop_u64("atime", on.into())
+    }
+
+    pub fn canmount(&self) -> Result<CanMount, Box<dyn Error>> {
+        Ok(self
+            .get_prop_u64("canmount")?
+            .map_or(CanMount::On, CanMount::from))
+    }
+
+    pub fn set_canmount(&self, canmount: CanMount) -> Result<(), Box<dyn Error>> {
+        self.set_prop_u64("canmount", canmount.into())
+    }
+
+    // filesystem layer properties, set at creation time. unlike the regular properties, these
+    // are plain name=value pairs
+    pub fn zpl_props(&self) -> Result<PairList, Box<dyn Error>> {
+        self.handle.get_dataset_zplprops(&self.name)
+    }
+
+    // properties as received by zfs recv, in the same form as the regular property list
+    pub fn received_props(&self) -> Result<PairList, Box<dyn Error>> {
+        self.handle.get_dataset_recvd_props(&self.name)
+    }
+
+    // delegated permissions, from this dataset and its ancestors, keyed by the dataset they're
+    // set on. each is a list of "who" keys to lists of permission names. the keys are a type
+    // (u/g/e for user, group, everyone; c for create-time; s for a named set; capitals for sets
+    // rather than single permissions), a scope (l local, d descendents, - neither), then "$"
+    // and the uid, gid or set name
+    pub fn permissions(&self) -> Result<PairList, Box<dyn Error>> {
+        self.handle.get_dataset_fsacl(&self.name)
+    }
+
+    pub fn get_prop_source(
+        &self,
+        prop: impl PairKey,
+    ) -> Result<Option<PropSource>, Box<dyn Error>> {
+        Ok(self
+            .get_prop(prop)?
+            .map(|l| PropSource::new(l.get_c_string("source").as_deref(), self.name.as_c_str())))
+    }
+
+    // every property, with its value and where it came from, like zfs get all. this includes
+    // the statistics (used, available, etc) and user properties. values that aren't a single
+    // number or string (eg redact_snaps), and the kernel's own $-prefixed entries, are skipped
+    pub fn all_props(&self) -> Result<Vec<PropEntry>, Box<dyn Error>> {
+        let stats = self.get_stats()?;
+        Ok(stats
+            .0
+            .pairs()
+            .filter(|p| !p.key().to_bytes().starts_with(b"$"))
+            .filter_map(|p| {
+                let l = match p.value() {
+                    PairValue::List(l) => l,
+                    _ => return None,
+                };
+                let value = match l.get("value")?.value() {
+                    PairValue::UInt64(n) => PropValue::Number(*n),
+                    PairValue::String(s) => PropValue::String(s.to_string_lossy().into()),
+                    _ => return None,
+                };
+                let source =
+                    PropSource::new(l.get_c_string("source").as_deref(), self.name.as_c_str());
+                Some((p.key().to_string_lossy().into(), value, source))
+            })
+            .collect())
+    }
+
+    // the user properties (those with a colon in the name, like com.example:backup), set here
+    // or inherited, and their values. these are always strings; anything else is skipped
+    pub fn user_props(&self) -> Result<HashMap<String, String>, Box<dyn Error>> {
+        let stats = self.get_stats()?;
+        Ok(stats
+            .0
+            .pairs()
+            .filter(|p| p.key().to_bytes().contains(&b':'))
+            .filter_map(|p| {
+                let value = p.as_list()?.get_str("value")?.into_owned();
+                Some((p.key().to_string_lossy().into_owned(), value))
+            })
+            .collect())
+    }
+}
+
+#[derive(Clone)]
+pub struct Snapshot {
+    handle: Rc<Handle>,
+    name: AutoString,
+}
+
+impl PartialEq for Snapshot {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
+}
+
+impl Eq for Snapshot {}
+
+impl Snapshot {
+    fn new(handle: Rc<Handle>, name: AutoString) -> Snapshot {
+        Snapshot { handle, name }
+    }
+
+    // full snapshot name, dataset@snap
+    pub fn name(&self) -> &AutoString {
+        &self.name
+    }
+
+    // the dataset this is a snapshot of
+    pub fn dataset(&self) -> Dataset {
+        let name = self.name.as_bytes();
+        let at = name.iter().position(|&c| c == b'@').unwrap_or(name.len());
+        let dsname = CString::new(&name[..at]).unwrap();
+        Dataset::new(self.handle.clone(), (&dsname).into())
+    }
+
+    fn get_prop(&self, prop: impl PairKey) -> Result<Option<PairList>, Box<dyn Error>> {
+        self.handle.get_dataset_prop(&self.name, prop)
+    }
+
+    // when the snapshot was taken, like Dataset::creation_time()
+    pub fn creation_time(&self) -> Result<SystemTime, Box<dyn Error>> {
+        let secs = self
+            .get_prop_u64("creation")?
+            .ok_or_else(|| IOError::from(IOErrorKind::NotFound))?;
+        Ok(UNIX_EPOCH + Duration::from_secs(secs))
+    }
+
+    pub fn get_prop_u64(&self, prop: impl PairKey) -> Result<Option<u64>, Box<dyn Error>> {
+        Ok(self.get_prop(prop)?.and_then(|l| l.get_u64("value")))
+    }
+
+    pub fn get_prop_string(&self, prop: impl PairKey) -> Result<Option<String>, Box<dyn Error>> {
+        Ok(self
+            .get_prop(prop)?
+            .and_then(|l| l.get_str("value").map(Cow::into_owned)))
+    }
+
+    // put a hold on this snapshot, with a tag to tell it from anyone else's, like zfs hold.
+    // until every hold is released, the snapshot can't be destroyed
+    pub fn hold(&self, tag: &str) -> Result<(), Box<dyn Error>> {
+        self.handle.hold(&self.name, &CString::new(tag)?, None)
+    }
+
+    pub fn release(&self, tag: &str) -> Result<(), Box<dyn Error>> {
+        self.handle.release(&self.name, &CString::new(tag)?)
+    }
+
+    // hold the snapshot for as long as the guard is kept (eg through a long send), releasing it
+    // when the guard is dropped. the hold is tied to a cleanup fd the guard keeps, so it goes
+    // even if the guard never gets dropped (an abort, or the process being killed)
+    pub fn hold_guard(&self, tag: &str) -> Result<HoldGuard, Box<dyn Error>> {
+        let tag = CString::new(tag)?;
+        let cleanup = self.handle.cleanup_fd()?;
+        self.handle.hold(&self.name, &tag, Some(cleanup.as_fd()))?;
+        Ok(HoldGuard {
+            snap: self.clone(),
+            tag: Some(tag),
+            _cleanup: cleanup,
+        })
+    }
+
+    // make the redaction bookmark dataset#name, like zfs redact: the blocks of this snapshot
+    // that are also in any of with (clones of it, with whatever's to be kept back removed) are
+    // left out of a send from the bookmark
+    pub fn redact(&self, name: &str, with: &[&Snapshot]) -> Result<(), Box<dyn Error>> {
+        let bookmark = CString::new(name)?;
+        let with: Vec<&CStr> = with.iter().map(|s| s.name.as_c_str()).collect();
+        self.handle.redact(&self.name, &bookmark, &with)
+    }
+
+    // the ranges of objects that changed since an older snapshot of the same dataset. these
+    // are raw object ids, not paths
+    pub fn diff_from(&self, older: &Snapshot) -> Result<Vec<DiffEntry>, Box<dyn Error>> {
+        self.handle.diff(&self.name, &older.name)
+    }
+}
+
+// a temporary snapshot, from Dataset::temp_snapshot(). it holds the fd the snapshot was tied
+// to; dropping it closes the fd, and the kernel releases and destroys the snapshot
+pub struct TempSnapshot {
+    snap: Snapshot,
+    _cleanup: OwnedFd,
+}
+
+impl TempSnapshot {
+    pub fn snapshot(&self) -> &Snapshot {
+        &self.snap
+    }
+}
+
+// a bookmark: a snapshot's place in its dataset's history (its guid and txg), kept after the
+// snapshot is destroyed, to send incrementally from. its properties are fetched each time
+#[derive(Clone)]
+pub struct Bookmark {
+    handle: Rc<Handle>,
+    name: AutoString,
+}
+
+impl PartialEq for Bookmark {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
+}
+
+impl Eq for Bookmark {}
+
+impl Bookmark {
+    fn new(handle: Rc<Handle>, name: AutoString) -> Bookmark {
+        Bookmark { handle, name }
+    }
+
+    // full bookmark name, dataset#bookmark
+    pub fn name(&self) -> &AutoString {
+        &self.name
+    }
+
+    // the dataset this is a bookmark in
+    pub fn dataset(&self) -> Dataset {
+        let name = self.name.as_bytes();
+        let hash = name.iter().position(|&c| c == b'#').unwrap_or(name.len());
+        let dsname = CString::new(&name[..hash]).unwrap();
+        Dataset::new(self.handle.clone(), (&dsname).into())
+    }
+
+    // every property, as the kernel gives them
+    pub fn props(&self) -> Result<PairList, Box<dyn Error>> {
+        self.handle.get_bookmark_props(&self.name)
+    }
+
+    fn get_prop_u64(&self, prop: &str) -> Result<u64, Box<dyn Error>> {
+        Ok(self
+            .props()?
+            .get_list(prop)
+            .and_then(|l| l.get_u64("value"))
+            .ok_or_else(|| IOError::from(IOErrorKind::NotFound))?)
+    }
+
+    // the guid of the snapshot it was made from
+    pub fn guid(&self) -> Result<u64, Box<dyn Error>> {
+        self.get_prop_u64("guid")
+    }
+
+    pub fn creation_txg(&self) -> Result<u64, Box<dyn Error>> {
+        self.get_prop_u64("createtxg")
+    }
+
+    // when the snapshot it was made from was taken
+    pub fn creation_time(&self) -> Result<SystemTime, Box<dyn Error>> {
+        Ok(UNIX_EPOCH + Duration::from_secs(self.get_prop_u64("creation")?))
+    }
+
+    // destroy the bookmark, like zfs destroy dataset#bookmark. one that's already gone is not
+    // an error
+    pub fn destroy(self) -> Result<(), Box<dyn Error>> {
+        self.handle.destroy_bookmark(&self.name)
+    }
+}
+
+// a hold on a snapshot, from Snapshot::hold_guard(), released when this is dropped. a failed
+// release on drop can only be ignored, so call release() to see it instead. if the process
+// dies without dropping it, the kernel releases the hold when it closes the cleanup fd
+pub struct HoldGuard {
+    snap: Snapshot,
+    tag: Option<CString>, // None once released
+    _cleanup: OwnedFd,
+}
+
+impl HoldGuard {
+    pub fn snapshot(&self) -> &Snapshot {
+        &self.snap
+    }
+
+    pub fn release(mut self) -> Result<(), Box<dyn Error>> {
+        match self.tag.take() {
+            Some(tag) => self.snap.handle.release(&self.snap.name, &tag),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for HoldGuard {
+    fn drop(&mut self) {
+        if let Some(tag) = self.tag.take() {
+            let _ = self.snap.handle.release(&self.snap.name, &tag);
+        }
     }
 }
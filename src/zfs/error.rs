@@ -0,0 +1,101 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+// Copyright (c) 2023, Rob Norris <robn@despairlabs.com>
+
+// errors from the kernel, with what was being done and to what, so they can be shown the way
+// zfs and zpool show them: cannot open 'tank/foo': dataset does not exist
+
+use std::error::Error;
+use std::ffi::CStr;
+use std::fmt;
+use std::io::{Error as IOError, ErrorKind as IOErrorKind};
+
+const EPERM: i32 = 1;
+const ENOENT: i32 = 2;
+const EACCES: i32 = 13;
+const EBUSY: i32 = 16;
+const EEXIST: i32 = 17;
+const ENOSPC: i32 = 28;
+
+#[derive(Debug)]
+pub struct VeneerError {
+    op: &'static str,
+    what: &'static str,
+    name: String,
+    err: IOError,
+}
+
+impl VeneerError {
+    // the thing the operation was on: a pool, dataset or snapshot name
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn kind(&self) -> IOErrorKind {
+        self.err.kind()
+    }
+
+    pub fn raw_os_error(&self) -> Option<i32> {
+        self.err.raw_os_error()
+    }
+}
+
+impl fmt::Display for VeneerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "cannot {} '{}': ", self.op, self.name)?;
+        match self.err.raw_os_error() {
+            Some(ENOENT) => write!(f, "{} does not exist", self.what),
+            Some(EPERM) | Some(EACCES) => write!(f, "permission denied"),
+            Some(EEXIST) => write!(f, "{} already exists", self.what),
+            Some(EBUSY) => write!(f, "{} is busy", self.what),
+            Some(ENOSPC) => write!(f, "out of space"),
+            _ => write!(f, "{}", self.err),
+        }
+    }
+}
+
+impl Error for VeneerError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.err)
+    }
+}
+
+// the errno behind an error from the zfs layer, whether it's been named yet or not
+pub(crate) fn raw_os_error(e: &(dyn Error + 'static)) -> Option<i32> {
+    e.downcast_ref::<VeneerError>()
+        .map(VeneerError::raw_os_error)
+        .or_else(|| e.downcast_ref::<IOError>().map(IOError::raw_os_error))
+        .flatten()
+}
+
+// name the target of a failed operation. only io errors are wrapped; anything else (eg a
+// result that wouldn't parse) is about the reply, not the target, and goes back as it was
+pub(crate) trait WithName<T> {
+    fn with_name(
+        self,
+        op: &'static str,
+        what: &'static str,
+        name: &CStr,
+    ) -> Result<T, Box<dyn Error>>;
+}
+
+impl<T> WithName<T> for Result<T, Box<dyn Error>> {
+    fn with_name(
+        self,
+        op: &'static str,
+        what: &'static str,
+        name: &CStr,
+    ) -> Result<T, Box<dyn Error>> {
+        self.map_err(|e| match e.downcast::<IOError>() {
+            Ok(err) => Box::new(VeneerError {
+                op,
+                what,
+                name: name.to_string_lossy().into_owned(),
+                err: *err,
+            }) as Box<dyn Error>,
+            Err(e) => e,
+        })
+    }
+}
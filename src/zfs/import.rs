@@ -0,0 +1,236 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+// Copyright (c) 2023, Rob Norris <robn@despairlabs.com>
+
+// finding pools to import, like zpool import with no arguments. every device (or file) in the
+// given directories is checked for ZFS labels; those that have them are grouped by pool, and
+// each pool's config is put back together from the newest label for each top-level vdev. see
+// Root::importable_pools(), which also asks the kernel what it makes of each one
+
+use crate::nvenums::{PoolState, VdevState};
+use crate::nvpair::{self, PairList, PairValue};
+use std::collections::{BTreeMap, BTreeSet};
+use std::ffi::CString;
+use std::fs::{self, File};
+use std::io::{Read, Result as IOResult, Seek, SeekFrom};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::FileTypeExt;
+use std::path::{Path, PathBuf};
+
+// vdev_label_t: 8K of padding, 8K boot envelope, then the config nvlist (112K, less the
+// zio_eck_t checksum trailer at its end), then the uberblock ring. there are four copies, two
+// at the front of the device and two at the end
+pub const LABEL_SIZE: u64 = 256 << 10;
+pub const LABEL_NVLIST_OFFSET: u64 = 16 << 10;
+pub const LABEL_NVLIST_SIZE: usize = (112 << 10) - 40;
+
+// the top-level keys of a label that describe the pool, and so go in the assembled config
+const POOL_KEYS: &[&str] = &[
+    "version",
+    "name",
+    "state",
+    "txg",
+    "pool_guid",
+    "hostid",
+    "hostname",
+    "comment",
+    "vdev_children",
+    "hole_array",
+    "features_for_read",
+];
+
+// a pool found on devices, and not imported. health is what the kernel thinks of it after a
+// trial import (eg ONLINE, DEGRADED, UNAVAIL), or None if it wouldn't say. config is what to
+// import it from
+#[derive(Debug, Clone)]
+pub struct ImportablePool {
+    pub name: String,
+    pub guid: u64,
+    pub state: PoolState, // from the labels: exported, destroyed, or still active somewhere
+    pub txg: u64,
+    pub hostid: Option<u64>,
+    pub hostname: Option<String>,
+    pub health: Option<VdevState>,
+    pub devices: Vec<PathBuf>, // every device that has one of the pool's labels
+    pub vdev_children: u64,    // top-level vdevs the pool should have
+    pub missing: Vec<u64>,     // ids of the ones no device was found for
+    pub config: PairList,
+}
+
+// the label offsets on a device of this size. the end ones are placed as if the device were
+// a whole number of labels long
+fn label_offsets(size: u64) -> [u64; 4] {
+    let psize = size & !(LABEL_SIZE - 1);
+    [0, LABEL_SIZE, psize - 2 * LABEL_SIZE, psize - LABEL_SIZE]
+}
+
+// the newest of the device's labels that belongs to a pool, if any. labels that don't parse
+// (a device that's not ZFS, or a damaged copy) are passed over
+pub fn read_label(f: &mut File) -> IOResult<Option<PairList>> {
+    let size = f.seek(SeekFrom::End(0))?;
+    if size < 4 * LABEL_SIZE {
+        return Ok(None);
+    }
+    let mut best: Option<PairList> = None;
+    let mut buf = vec![0; LABEL_NVLIST_SIZE];
+    for off in label_offsets(size) {
+        f.seek(SeekFrom::Start(off + LABEL_NVLIST_OFFSET))?;
+        f.read_exact(&mut buf)?;
+        let Ok(label) = nvpair::parse_slice(&buf) else {
+            continue;
+        };
+        if label.get_u64("pool_guid").is_none() || label.get_list("vdev_tree").is_none() {
+            continue; // a spare or cache device, or not ours at all
+        }
+        let txg = |l: &PairList| l.get_u64("txg").unwrap_or_default();
+        if best.as_ref().is_none_or(|b| txg(&label) > txg(b)) {
+            best = Some(label);
+        }
+    }
+    Ok(best)
+}
+
+// every device or file in the directories with a pool label on it. symlinks are followed, as
+// directories like /dev/disk/by-id have nothing else; a device reached by several of them is
+// only counted once, at the first path (by name) that leads to it. entries that can't be
+// opened or read (eg no permission, a dangling link, or a failing disk) are skipped, as zpool
+// import does; a directory that can't be read is an error
+fn find_labels(dirs: &[&Path]) -> IOResult<Vec<(PathBuf, PairList)>> {
+    let mut found = vec![];
+    for dir in dirs {
+        for ent in fs::read_dir(dir)? {
+            let path = ent?.path();
+            let Ok(md) = fs::metadata(&path) else {
+                continue;
+            };
+            let ft = md.file_type();
+            if !ft.is_file() && !ft.is_block_device() {
+                continue;
+            }
+            let Ok(mut f) = File::open(&path) else {
+                continue;
+            };
+            if let Ok(Some(label)) = read_label(&mut f) {
+                found.push((path, label));
+            }
+        }
+    }
+    found.sort_by(|a, b| a.0.cmp(&b.0));
+    let mut seen = BTreeSet::new();
+    found.retain(|(_, l)| seen.insert(l.get_u64("guid")));
+    Ok(found)
+}
+
+// a leaf's path, as recorded in the label, is wherever the device was when the pool was last
+// imported. point each one at where it was found this time
+fn fix_paths(vd: &PairList, paths: &[(u64, &Path)]) -> PairList {
+    let mut vd = vd.clone();
+    if let Some(children) = vd.get_list_slice("children") {
+        let children = children.iter().map(|c| fix_paths(c, paths)).collect();
+        vd.add("children", PairValue::ListArray(children));
+    } else if let Some((_, path)) = vd
+        .get_u64("guid")
+        .and_then(|g| paths.iter().find(|(pg, _)| *pg == g))
+    {
+        let path = CString::new(path.as_os_str().as_bytes()).unwrap_or_default();
+        vd.add("path", PairValue::String(path));
+    }
+    vd
+}
+
+// one pool's labels, into a config with every top-level vdev, from whichever label for it is
+// newest. the ids in the newest label's hole_array are the places of removed vdevs (eg a log
+// device), and go in as holes, as libzfs does. any other top-level vdev that nothing was found
+// for goes in as "missing"
+fn assemble(labels: &[(PathBuf, PairList)]) -> ImportablePool {
+    let txg = |l: &PairList| l.get_u64("txg").unwrap_or_default();
+    let (_, newest) = labels.iter().max_by_key(|(_, l)| txg(l)).unwrap();
+
+    let mut tops: BTreeMap<u64, &PairList> = BTreeMap::new();
+    let mut top_txgs: BTreeMap<u64, u64> = BTreeMap::new();
+    for (_, l) in labels {
+        let vt = l.get_list("vdev_tree").unwrap();
+        let id = vt.get_u64("id").unwrap_or_default();
+        if top_txgs.get(&id).is_none_or(|t| txg(l) > *t) {
+            tops.insert(id, vt);
+            top_txgs.insert(id, txg(l));
+        }
+    }
+
+    let vdev_children = newest
+        .get_u64("vdev_children")
+        .unwrap_or_else(|| tops.keys().last().map_or(0, |id| id + 1));
+    let paths: Vec<_> = labels
+        .iter()
+        .filter_map(|(p, l)| l.get_u64("guid").map(|g| (g, p.as_path())))
+        .collect();
+
+    let holes = newest.get_u64_slice("hole_array").unwrap_or_default();
+    let mut missing = vec![];
+    let children = (0..vdev_children)
+        .map(|id| match tops.get(&id) {
+            Some(vt) => fix_paths(vt, &paths),
+            None if holes.contains(&id) => {
+                let mut vt = PairList::new();
+                vt.add("type", PairValue::String(CString::new("hole").unwrap()));
+                vt.add("id", PairValue::UInt64(id));
+                vt.add("guid", PairValue::UInt64(0));
+                vt
+            }
+            None => {
+                missing.push(id);
+                let mut vt = PairList::new();
+                vt.add("type", PairValue::String(CString::new("missing").unwrap()));
+                vt.add("id", PairValue::UInt64(id));
+                vt.add("guid", PairValue::UInt64(0));
+                vt
+            }
+        })
+        .collect();
+
+    let guid = newest.get_u64("pool_guid").unwrap_or_default();
+    let mut root = PairList::new();
+    root.add("type", PairValue::String(CString::new("root").unwrap()));
+    root.add("id", PairValue::UInt64(0));
+    root.add("guid", PairValue::UInt64(guid));
+    root.add("children", PairValue::ListArray(children));
+
+    let mut config = PairList::new();
+    newest
+        .pairs()
+        .filter(|p| POOL_KEYS.iter().any(|k| p.key().to_bytes() == k.as_bytes()))
+        .for_each(|p| config.add(p.key().to_bytes(), p.value().clone()));
+    config.add("vdev_tree", PairValue::List(root));
+
+    ImportablePool {
+        name: newest
+            .get_str("name")
+            .map(|s| s.into_owned())
+            .unwrap_or_default(),
+        guid,
+        state: PoolState::from(newest.get_u64("state").unwrap_or(u64::MAX)),
+        txg: txg(newest),
+        hostid: newest.get_u64("hostid"),
+        hostname: newest.get_str("hostname").map(|s| s.into_owned()),
+        health: None,
+        devices: labels.iter().map(|(p, _)| p.clone()).collect(),
+        vdev_children,
+        missing,
+        config,
+    }
+}
+
+// every pool with labels on devices in the directories, by name, assembled but not yet shown
+// to the kernel
+pub(crate) fn scan(dirs: &[&Path]) -> IOResult<Vec<ImportablePool>> {
+    let mut by_pool: BTreeMap<u64, Vec<(PathBuf, PairList)>> = BTreeMap::new();
+    for (path, label) in find_labels(dirs)? {
+        let guid = label.get_u64("pool_guid").unwrap_or_default();
+        by_pool.entry(guid).or_default().push((path, label));
+    }
+    let mut pools: Vec<_> = by_pool.values().map(|l| assemble(l)).collect();
+    pools.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(pools)
+}
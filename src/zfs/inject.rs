@@ -0,0 +1,189 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+// Copyright (c) 2023, Rob Norris <robn@despairlabs.com>
+
+// fault injection, like zinject. describe the fault with a ZInjectBuilder, then start it with
+// Pool::inject_fault(). it stays until cleared with Root::clear_fault()
+
+use crate::ioc::ZInjectRecord;
+use crate::util::AutoString;
+use std::io::{Error as IOError, ErrorKind as IOErrorKind};
+
+// zinject_type_t
+const ZINJECT_DATA_FAULT: u32 = 1;
+const ZINJECT_DEVICE_FAULT: u32 = 2;
+
+// zi_freq is a fraction of UINT32_MAX; 0 means every time. values of 100 or less are taken as
+// whole percents, for old zinject, so the smallest fraction is ZI_PERCENTAGE_MIN (0.0001%)
+const ZI_PERCENTAGE_MAX: f64 = u32::MAX as f64;
+const ZI_PERCENTAGE_MIN: u32 = 4294;
+
+const EIO: i32 = 5;
+const ENXIO: i32 = 6;
+
+// the kinds of IO a device fault applies to (zio_type_t)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IoType {
+    Read,
+    Write,
+    Free,
+    Claim,
+    Flush,
+    Trim,
+    All, // ZIO_TYPES
+}
+
+impl From<IoType> for u32 {
+    fn from(t: IoType) -> Self {
+        match t {
+            IoType::Read => 1,
+            IoType::Write => 2,
+            IoType::Free => 3,
+            IoType::Claim => 4,
+            IoType::Flush => 5,
+            IoType::Trim => 6,
+            IoType::All => 7,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Target {
+    Device(u64),
+    Object {
+        objset: u64,
+        object: u64,
+        level: u32,
+    },
+}
+
+// an active fault, as listed by Root::faults()
+#[derive(Debug, Clone)]
+pub struct Fault {
+    pub id: u64,
+    pub pool: AutoString,
+    pub record: ZInjectRecord,
+}
+
+// a fault goes either on a whole device (by vdev guid) or on the blocks of one object (by
+// objset and object id, as in the error log). the rest is optional: the errno to fail with
+// (defaults to ENXIO for devices, EIO for objects), how often to fail (defaults to always),
+// and which IO (devices only) or which blocks (objects only) to fail
+#[derive(Debug, Default)]
+pub struct ZInjectBuilder {
+    target: Option<Target>,
+    targets: usize,
+    error: Option<i32>,
+    freq: Option<f64>,
+    iotype: Option<IoType>,
+    range: Option<(u64, u64)>,
+}
+
+impl ZInjectBuilder {
+    pub fn new() -> ZInjectBuilder {
+        Default::default()
+    }
+
+    pub fn device(mut self, guid: u64) -> Self {
+        self.target = Some(Target::Device(guid));
+        self.targets += 1;
+        self
+    }
+
+    // level 0 is the data blocks; higher levels are the indirect blocks above them
+    pub fn object(mut self, objset: u64, object: u64, level: u32) -> Self {
+        self.target = Some(Target::Object {
+            objset,
+            object,
+            level,
+        });
+        self.targets += 1;
+        self
+    }
+
+    pub fn error(mut self, errno: i32) -> Self {
+        self.error = Some(errno);
+        self
+    }
+
+    // percentage of matching IOs to fail, down to 0.0001
+    pub fn frequency(mut self, pct: f64) -> Self {
+        self.freq = Some(pct);
+        self
+    }
+
+    pub fn io_type(mut self, iotype: IoType) -> Self {
+        self.iotype = Some(iotype);
+        self
+    }
+
+    // block ids within the object to fail, inclusive. without this, it's all of them
+    pub fn range(mut self, start: u64, end: u64) -> Self {
+        self.range = Some((start, end));
+        self
+    }
+
+    pub fn build(self) -> Result<ZInjectRecord, IOError> {
+        let invalid = |msg| Err(IOError::new(IOErrorKind::InvalidInput, msg));
+
+        let target = match (self.target, self.targets) {
+            (None, _) => return invalid("no device or object to inject into"),
+            (Some(_), n) if n > 1 => return invalid("only one device or object per record"),
+            (Some(t), _) => t,
+        };
+
+        let freq = match self.freq {
+            None => 0,
+            Some(f) if f > 0.0 && f <= 100.0 => {
+                ((f / 100.0 * ZI_PERCENTAGE_MAX) as u32).max(ZI_PERCENTAGE_MIN)
+            }
+            Some(_) => return invalid("frequency must be a percentage, more than 0"),
+        };
+
+        let mut rec = ZInjectRecord {
+            freq,
+            ..Default::default()
+        };
+
+        let error = match target {
+            Target::Device(guid) => {
+                if self.range.is_some() {
+                    return invalid("block ranges only apply to object faults");
+                }
+                rec.cmd = ZINJECT_DEVICE_FAULT;
+                rec.guid = guid;
+                rec.iotype = self.iotype.unwrap_or(IoType::All).into();
+                self.error.unwrap_or(ENXIO)
+            }
+            Target::Object {
+                objset,
+                object,
+                level,
+            } => {
+                if self.iotype.is_some() {
+                    return invalid("io types only apply to device faults");
+                }
+                let (start, end) = self.range.unwrap_or((0, u64::MAX));
+                if start > end {
+                    return invalid("block range ends before it starts");
+                }
+                rec.cmd = ZINJECT_DATA_FAULT;
+                rec.objset = objset;
+                rec.object = object;
+                rec.level = level;
+                rec.start = start;
+                rec.end = end;
+                self.error.unwrap_or(EIO)
+            }
+        };
+
+        if error <= 0 {
+            return invalid("error must be a positive errno");
+        }
+        rec.error = error as u32;
+
+        Ok(rec)
+    }
+}
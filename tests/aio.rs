@@ -0,0 +1,101 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+// Copyright (c) 2023, Rob Norris <robn@despairlabs.com>
+
+#![cfg(feature = "tokio")]
+
+mod common;
+
+use common::{recorded_handle, replay_handle, send_stream};
+use common::{ZFS_IOC_POOL_SYNC, ZFS_IOC_RECV_NEW, ZFS_IOC_WAIT};
+use std::ffi::CString;
+use std::fs::File;
+use std::io::Error as IOError;
+use tokio::runtime::{Builder, Runtime};
+use veneer::aio::AsyncHandle;
+use veneer::nvenums::WaitActivity;
+use veneer::nvpair::PairValue;
+
+fn runtime() -> Runtime {
+    Builder::new_current_thread().build().unwrap()
+}
+
+#[test]
+fn pool_configs() {
+    let h = AsyncHandle::from(replay_handle());
+    let configs = runtime().block_on(h.pool_configs()).unwrap();
+    assert_eq!(configs.keys().count(), 1);
+    assert!(configs.get_list("tank").is_some());
+}
+
+#[test]
+fn pool_sync() {
+    let (h, ops) = recorded_handle();
+    let h = AsyncHandle::from(h);
+    let tank = CString::new("tank").unwrap();
+
+    // spawned, so it has to be Send + 'static, and no longer tied to the handle
+    let task = h.pool_sync(tank, true);
+    drop(h);
+    runtime()
+        .block_on(async { tokio::spawn(task).await })
+        .unwrap()
+        .unwrap();
+
+    let ops = ops.lock().unwrap();
+    assert_eq!(ops.len(), 1);
+    assert_eq!(ops[0].req, ZFS_IOC_POOL_SYNC);
+}
+
+#[test]
+fn wait() {
+    let (h, ops) = recorded_handle();
+    let h = AsyncHandle::from(h);
+    let tank = CString::new("tank").unwrap();
+    let waited = runtime()
+        .block_on(h.wait(tank, WaitActivity::Scrub, None))
+        .unwrap();
+    assert!(waited);
+
+    let ops = ops.lock().unwrap();
+    assert_eq!(ops[0].req, ZFS_IOC_WAIT);
+    let args = ops[0].src.as_ref().unwrap();
+    assert!(matches!(
+        args.get("wait_activity").unwrap().value(),
+        PairValue::Int32(6)
+    ));
+    assert!(args.get("wait_tag").is_none());
+}
+
+#[test]
+fn recv() {
+    let (h, ops) = recorded_handle();
+    let h = AsyncHandle::from(h);
+    let path = std::env::temp_dir().join(format!("veneer-aio-recv-{}", std::process::id()));
+    std::fs::write(&path, send_stream("other/fs@c", 1000)).unwrap();
+    let stream = File::open(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let snap = CString::new("tank/fs@c").unwrap();
+    let out = runtime()
+        .block_on(h.recv(snap, stream.into(), None, None, false, false))
+        .unwrap();
+    assert_eq!(out.get_u64("read_bytes"), Some(1000));
+    assert_eq!(ops.lock().unwrap()[0].req, ZFS_IOC_RECV_NEW);
+}
+
+#[test]
+fn errors() {
+    let h = AsyncHandle::from(replay_handle());
+    let nope = CString::new("nope").unwrap();
+    let e = runtime().block_on(h.pool_stats(nope)).unwrap_err();
+    assert_eq!(e.downcast::<IOError>().unwrap().raw_os_error(), Some(2)); // ENOENT
+
+    // anything, through the general form
+    let n = runtime()
+        .block_on(h.run(|h| Ok(h.pool_configs()?.keys().count())))
+        .unwrap();
+    assert_eq!(n, 1);
+}
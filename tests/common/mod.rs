@@ -0,0 +1,616 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+// Copyright (c) 2023, Rob Norris <robn@despairlabs.com>
+
+#![allow(dead_code)]
+
+use std::ffi::CString;
+use std::fs::File;
+use std::io::Write;
+use std::io::{Error as IOError, Result as IOResult};
+use std::mem::ManuallyDrop;
+use std::os::fd::{FromRawFd, OwnedFd};
+use std::os::raw::{c_uint, c_ulong};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicI32, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use veneer::ioc::{self, ObjsetStats, Transport, ZFSCommand, ZInjectRecord};
+use veneer::nvenums::ObjsetType;
+use veneer::nvpair;
+use veneer::nvpair::{Pair, PairList, PairValue};
+use veneer::zfs;
+
+pub fn fixture_path(name: &str) -> PathBuf {
+    [env!("CARGO_MANIFEST_DIR"), "tests", "fixtures", name]
+        .iter()
+        .collect()
+}
+
+// load a packed nvlist from tests/fixtures. regenerate these with the nvlist-dump example
+pub fn fixture(name: &str) -> Vec<u8> {
+    let path = fixture_path(name);
+    std::fs::read(&path).unwrap_or_else(|e| panic!("{}: {}", path.display(), e))
+}
+
+pub fn get_str(l: &PairList, key: &str) -> Option<String> {
+    l.get_str(key).map(|s| s.into_owned())
+}
+
+// a send stream from the named snapshot: a begin record, then some filler standing in for the
+// rest of the records
+pub fn send_stream(toname: &str, len: usize) -> Vec<u8> {
+    let mut stream = vec![0u8; ioc::BEGIN_RECORD_SIZE];
+    stream[8..16].copy_from_slice(&0x2f5bacbacu64.to_ne_bytes()); // DMU_BACKUP_MAGIC
+    stream[56..56 + toname.len()].copy_from_slice(toname.as_bytes());
+    stream.extend((0..len).map(|n| n as u8));
+    stream
+}
+
+// request numbers, from sys.rs
+const ZFS_IOC_POOL_CONFIGS: c_ulong = 0x04;
+const ZFS_IOC_POOL_STATS: c_ulong = 0x05;
+const ZFS_IOC_POOL_TRYIMPORT: c_ulong = 0x06;
+pub const ZFS_IOC_POOL_UPGRADE: c_ulong = 0x09;
+pub const ZFS_IOC_POOL_SET_PROPS: c_ulong = 0x26;
+pub const ZFS_IOC_VDEV_ADD: c_ulong = 0x0b;
+pub const ZFS_IOC_VDEV_REMOVE: c_ulong = 0x0c;
+pub const ZFS_IOC_VDEV_SET_STATE: c_ulong = 0x0d;
+pub const ZFS_IOC_VDEV_ATTACH: c_ulong = 0x0e;
+pub const ZFS_IOC_VDEV_DETACH: c_ulong = 0x0f;
+const ZFS_IOC_OBJSET_STATS: c_ulong = 0x12;
+const ZFS_IOC_DATASET_LIST_NEXT: c_ulong = 0x14;
+const ZFS_IOC_SNAPSHOT_LIST_NEXT: c_ulong = 0x15;
+pub const ZFS_IOC_SET_PROP: c_ulong = 0x16;
+pub const ZFS_IOC_INJECT_FAULT: c_ulong = 0x1d;
+pub const ZFS_IOC_CLEAR_FAULT: c_ulong = 0x1e;
+const ZFS_IOC_INJECT_LIST_NEXT: c_ulong = 0x1f;
+const ZFS_IOC_DSOBJ_TO_DSNAME: c_ulong = 0x24;
+pub const ZFS_IOC_SNAPSHOT: c_ulong = 0x23;
+pub const ZFS_IOC_SET_FSACL: c_ulong = 0x28;
+pub const ZFS_IOC_TMP_SNAPSHOT: c_ulong = 0x37;
+pub const ZFS_IOC_SHARE: c_ulong = 0x2a;
+pub const ZFS_IOC_SMB_ACL: c_ulong = 0x2c;
+pub const ZFS_IOC_HOLD: c_ulong = 0x30;
+pub const ZFS_IOC_RELEASE: c_ulong = 0x31;
+const ZFS_IOC_USERSPACE_ONE: c_ulong = 0x2d;
+const ZFS_IOC_GET_FSACL: c_ulong = 0x29;
+const ZFS_IOC_NEXT_OBJ: c_ulong = 0x35;
+const ZFS_IOC_DIFF: c_ulong = 0x36;
+const ZFS_IOC_SEND_PROGRESS: c_ulong = 0x3e;
+pub const ZFS_IOC_RECV_NEW: c_ulong = 0x46;
+pub const ZFS_IOC_REMAP: c_ulong = 0x4c;
+pub const ZFS_IOC_REDACT: c_ulong = 0x51;
+const ZFS_IOC_GET_BOOKMARK_PROPS: c_ulong = 0x52;
+pub const ZFS_IOC_DESTROY_BOOKMARKS: c_ulong = 0x45;
+const ZFS_IOC_POOL_REGUID: c_ulong = 0x3c;
+pub const ZFS_IOC_POOL_SYNC: c_ulong = 0x47;
+pub const ZFS_IOC_WAIT: c_ulong = 0x53;
+
+const EPERM: i32 = 1;
+const ENOENT: i32 = 2;
+const ESRCH: i32 = 3;
+const EEXIST: i32 = 17;
+const EINVAL: i32 = 22;
+const ENOSYS: i32 = 38;
+const ENOTSUP: i32 = 95;
+
+// a command that changes something, as the kernel would have seen it
+#[derive(Debug, Clone)]
+pub struct Op {
+    pub req: c_ulong,
+    pub name: String,
+    pub guid: u64,
+    pub cookie: u64,
+    pub obj: u64,
+    pub perm_action: u64,
+    pub sharetype: u64,
+    pub share: (String, String), // export path, options
+    pub value: String,
+    pub string: String,
+    pub flags: i32,
+    pub inject: ZInjectRecord,
+    pub src: Option<PairList>,
+    pub conf: Option<PairList>,
+}
+
+impl Op {
+    fn new(req: c_ulong, cmd: &ZFSCommand) -> Op {
+        Op {
+            req,
+            name: cmd.name().to_string_lossy().to_string(),
+            guid: cmd.guid(),
+            cookie: cmd.cookie(),
+            obj: cmd.obj(),
+            perm_action: cmd.perm_action(),
+            sharetype: cmd.sharetype(),
+            share: (
+                cmd.share_export().to_string_lossy().into(),
+                cmd.share_options().to_string_lossy().into(),
+            ),
+            value: cmd.value().to_string_lossy().into(),
+            string: cmd.string().to_string_lossy().into(),
+            flags: cmd.flags(),
+            inject: cmd.inject_record().clone(),
+            src: (!cmd.src().is_empty()).then(|| nvpair::parse(cmd.src()).unwrap()),
+            conf: (!cmd.conf().is_empty()).then(|| nvpair::parse(cmd.conf()).unwrap()),
+        }
+    }
+}
+
+// a transport that answers from the fixture files, as if talking to a system with a single pool
+// "tank" containing one child filesystem "tank/fs". counts the calls made, and records the
+// changes asked for, across clones. if fail is set, every call fails with that errno instead.
+// with aux, tank also has a log, a cache device and a spare
+#[derive(Debug, Default)]
+pub struct Replay {
+    pub calls: Arc<AtomicUsize>,
+    reguids: Arc<AtomicU64>,
+    pub ops: Arc<Mutex<Vec<Op>>>,
+    pub fail: Arc<AtomicI32>,
+    pub aux: bool,
+}
+
+// overwrite the first u64 value with the given name in a packed nvlist. names start 8-aligned,
+// and the value follows the name, padded to 8
+fn patch_u64(buf: &mut [u8], key: &str, value: u64) {
+    let name = [key.as_bytes(), b"\0"].concat();
+    let at = buf
+        .windows(name.len())
+        .position(|w| w == name)
+        .expect("key not in fixture");
+    let at = at + ((name.len() + 7) & !7);
+    buf[at..at + 8].copy_from_slice(&value.to_le_bytes());
+}
+
+impl Replay {
+    // pool lists, with the guid moved along by however many times it's been changed
+    fn pool_fixture(&self, name: &str) -> Vec<u8> {
+        let mut buf = fixture(name);
+        let n = self.reguids.load(Ordering::Relaxed);
+        patch_u64(&mut buf, "pool_guid", 0x1f2e3d4c5b6a7988 + n);
+        buf
+    }
+
+    fn objset_fixture(name: &str) -> Option<Vec<u8>> {
+        match name {
+            "tank" => Some(fixture("objset_stats-tank.nvlist")),
+            "tank/fs" | "tank/fs@a" | "tank/fs@b" => Some(fixture("objset_stats-tank_fs.nvlist")),
+            _ => None,
+        }
+    }
+
+    // the fixed stats the kernel would fill in alongside the property list. take what we can
+    // from the list itself, so the two agree
+    fn objset_stats(nvbuf: &[u8]) -> ObjsetStats {
+        let l = nvpair::parse(nvbuf).unwrap();
+        let prop = |name| l.get_list(name).and_then(|p| p.get_u64("value")).unwrap();
+        ObjsetStats {
+            num_clones: 0,
+            creation_txg: prop("createtxg"),
+            guid: prop("guid"),
+            typ: ObjsetType::from(prop("type")),
+            is_snapshot: false,
+            inconsistent: false,
+            redacted: false,
+            origin: CString::default(),
+        }
+    }
+}
+
+impl Transport for Replay {
+    fn ioctl(&mut self, req: c_ulong, cmd: &mut ZFSCommand) -> IOResult<c_uint> {
+        self.calls.fetch_add(1, Ordering::Relaxed);
+        let fail = self.fail.load(Ordering::Relaxed);
+        if fail != 0 {
+            return Err(IOError::from_raw_os_error(fail));
+        }
+        let name = cmd.name().to_string_lossy().to_string();
+        let nvbuf = match req {
+            ZFS_IOC_POOL_CONFIGS => self.pool_fixture("pool_configs.nvlist"),
+            ZFS_IOC_POOL_STATS if name == "tank" && self.aux => {
+                self.pool_fixture("pool_stats-tank_aux.nvlist")
+            }
+            ZFS_IOC_POOL_STATS if name == "tank" => self.pool_fixture("pool_stats-tank.nvlist"),
+            ZFS_IOC_POOL_TRYIMPORT => {
+                // the config back, with a state on the root: healthy, or can't open if any
+                // top-level vdev is missing
+                let mut conf = nvpair::parse(cmd.conf()).unwrap();
+                let mut vt = conf.get_list("vdev_tree").unwrap().clone();
+                let missing = vt
+                    .get_list_slice("children")
+                    .unwrap_or_default()
+                    .iter()
+                    .any(|c| c.get_str("type").unwrap() == "missing");
+                let state = if missing { 4 } else { 7 }; // VDEV_STATE_CANT_OPEN, _HEALTHY
+                vt.add("vdev_stats", PairValue::UInt64Array(vec![0, state, 0]));
+                conf.add("vdev_tree", PairValue::List(vt));
+                nvpair::pack(&conf).unwrap()
+            }
+            ZFS_IOC_DSOBJ_TO_DSNAME if name == "tank" && cmd.obj() == 54 => {
+                cmd.set_value(&CString::new("tank").unwrap());
+                return Ok(0);
+            }
+            ZFS_IOC_NEXT_OBJ if name == "tank/fs" => {
+                // a handful of objects, with some holes where others were freed
+                let next = [1, 2, 3, 7, 34].into_iter().find(|n| *n > cmd.obj());
+                cmd.set_obj(next.ok_or(IOError::from_raw_os_error(ESRCH))?);
+                return Ok(0);
+            }
+            ZFS_IOC_INJECT_FAULT if name == "tank" => {
+                self.ops.lock().unwrap().push(Op::new(req, cmd));
+                cmd.set_guid(1);
+                return Ok(0);
+            }
+            ZFS_IOC_CLEAR_FAULT if cmd.guid() == 1 => {
+                self.ops.lock().unwrap().push(Op::new(req, cmd));
+                return Ok(0);
+            }
+            ZFS_IOC_INJECT_LIST_NEXT if cmd.guid() == 0 => {
+                // one fault, on the first disk
+                cmd.set_guid(1);
+                cmd.set_name(&CString::new("tank").unwrap());
+                cmd.set_inject_record(&ZInjectRecord {
+                    cmd: 2, // ZINJECT_DEVICE_FAULT
+                    guid: 0x1c2b3a4d5e6f7081,
+                    error: 6, // ENXIO
+                    iotype: 7,
+                    ..Default::default()
+                });
+                return Ok(0);
+            }
+            ZFS_IOC_POOL_REGUID if name == "tank" => {
+                self.reguids.fetch_add(1, Ordering::Relaxed);
+                return Ok(0);
+            }
+            // a kernel from before block cloning
+            ZFS_IOC_POOL_SET_PROPS
+                if name == "tank"
+                    && nvpair::parse(cmd.src())
+                        .unwrap()
+                        .get("feature@block_cloning")
+                        .is_some() =>
+            {
+                return Err(IOError::from_raw_os_error(EINVAL));
+            }
+            ZFS_IOC_POOL_UPGRADE
+            | ZFS_IOC_POOL_SET_PROPS
+            | ZFS_IOC_VDEV_ADD
+            | ZFS_IOC_VDEV_REMOVE
+            | ZFS_IOC_VDEV_SET_STATE
+            | ZFS_IOC_VDEV_ATTACH
+            | ZFS_IOC_VDEV_DETACH
+            | ZFS_IOC_POOL_SYNC
+                if name == "tank" =>
+            {
+                self.ops.lock().unwrap().push(Op::new(req, cmd));
+                return Ok(0);
+            }
+            ZFS_IOC_SET_PROP if name == "tank/fs" => {
+                // properties named bad* are refused, and said so in the errlist, the way the
+                // kernel reports a property it won't set. the rest are taken
+                self.ops.lock().unwrap().push(Op::new(req, cmd));
+                let errs: PairList = nvpair::parse(cmd.src())
+                    .unwrap()
+                    .keys()
+                    .filter(|k| k.to_bytes().starts_with(b"bad"))
+                    .map(|k| Pair::new(k.to_bytes(), PairValue::Int32(EPERM)))
+                    .collect();
+                cmd.write_dst(&nvpair::pack(&errs).unwrap())?;
+                if errs.pairs().next().is_some() {
+                    return Err(IOError::from_raw_os_error(EPERM));
+                }
+                return Ok(0);
+            }
+            ZFS_IOC_SNAPSHOT if name == "tank" => {
+                // all or nothing: tank/fs already has @a and @b, and there are no other
+                // datasets to take snapshots of. the ones that can't be taken go in the errlist
+                self.ops.lock().unwrap().push(Op::new(req, cmd));
+                let args = nvpair::parse(cmd.src()).unwrap();
+                let errs: PairList = args
+                    .get_list("snaps")
+                    .unwrap()
+                    .keys()
+                    .filter_map(|k| {
+                        let snap = k.to_str().unwrap();
+                        let (ds, _) = snap.split_once('@').unwrap();
+                        let err = match ds {
+                            "tank/fs" if Replay::objset_fixture(snap).is_some() => EEXIST,
+                            "tank" | "tank/fs" => return None,
+                            _ => ENOENT,
+                        };
+                        Some(Pair::new(snap, PairValue::Int32(err)))
+                    })
+                    .collect();
+                if let Some(p) = errs.pairs().next() {
+                    let PairValue::Int32(err) = p.value() else {
+                        unreachable!()
+                    };
+                    cmd.write_dst(&nvpair::pack(&errs).unwrap())?;
+                    return Err(IOError::from_raw_os_error(*err));
+                }
+                return Ok(0);
+            }
+            ZFS_IOC_SET_FSACL | ZFS_IOC_REMAP if name == "tank/fs" => {
+                self.ops.lock().unwrap().push(Op::new(req, cmd));
+                return Ok(0);
+            }
+            ZFS_IOC_TMP_SNAPSHOT if name == "tank/fs" && cmd.cleanup_fd() > 0 => {
+                self.ops.lock().unwrap().push(Op::new(req, cmd));
+                let mut snap = cmd.value().to_bytes().to_vec();
+                snap.extend_from_slice(b"-0000000000c0ffee");
+                cmd.set_value(&CString::new(snap).unwrap());
+                return Ok(0);
+            }
+            ZFS_IOC_HOLD | ZFS_IOC_RELEASE if name == "tank" => {
+                // only tank/fs's snapshots are there to be held or released
+                self.ops.lock().unwrap().push(Op::new(req, cmd));
+                let args = nvpair::parse(cmd.src()).unwrap();
+                let snaps = match req {
+                    ZFS_IOC_HOLD => args.get_list("holds").unwrap().clone(),
+                    _ => args,
+                };
+                let errs: PairList = snaps
+                    .keys()
+                    .filter(|k| Replay::objset_fixture(&k.to_string_lossy()).is_none())
+                    .map(|k| Pair::new(k.to_bytes(), PairValue::Int32(ENOENT)))
+                    .collect();
+                if errs.pairs().next().is_some() {
+                    cmd.write_dst(&nvpair::pack(&errs).unwrap())?;
+                    return Err(IOError::from_raw_os_error(ENOENT));
+                }
+                return Ok(0);
+            }
+            ZFS_IOC_DESTROY_BOOKMARKS if name == "tank" => {
+                // bookmarks that aren't there are skipped, but their datasets must be
+                self.ops.lock().unwrap().push(Op::new(req, cmd));
+                let errs: PairList = nvpair::parse(cmd.src())
+                    .unwrap()
+                    .keys()
+                    .filter(|k| {
+                        let ds = k.to_bytes().split(|&c| c == b'#').next().unwrap();
+                        Replay::objset_fixture(&String::from_utf8_lossy(ds)).is_none()
+                    })
+                    .map(|k| Pair::new(k.to_bytes(), PairValue::Int32(ENOENT)))
+                    .collect();
+                if errs.pairs().next().is_some() {
+                    cmd.write_dst(&nvpair::pack(&errs).unwrap())?;
+                    return Err(IOError::from_raw_os_error(ENOENT));
+                }
+                return Ok(0);
+            }
+            ZFS_IOC_REDACT if name == "tank/fs@a" => {
+                self.ops.lock().unwrap().push(Op::new(req, cmd));
+                return Ok(0);
+            }
+            // tank/fs is shared as an old kernel would; tank as a current one, which leaves
+            // sharing to userspace
+            ZFS_IOC_SHARE if name == "tank/fs" => {
+                self.ops.lock().unwrap().push(Op::new(req, cmd));
+                return Ok(0);
+            }
+            ZFS_IOC_SHARE => return Err(IOError::from_raw_os_error(ENOSYS)),
+            // tank/fs has SMB ACLs, as on illumos; nothing else does
+            ZFS_IOC_SMB_ACL if name == "tank/fs" => {
+                self.ops.lock().unwrap().push(Op::new(req, cmd));
+                return Ok(0);
+            }
+            ZFS_IOC_SMB_ACL => return Err(IOError::from_raw_os_error(ENOTSUP)),
+            ZFS_IOC_USERSPACE_ONE if name == "tank/fs" && cmd.value().is_empty() => {
+                // uid 1000 and gid 100 own everything; anyone else has nothing
+                let used = match (cmd.objset_type(), cmd.guid()) {
+                    (0, 1000) => 3 << 20, // ZFS_PROP_USERUSED
+                    (2, 100) => 5 << 20,  // ZFS_PROP_GROUPUSED
+                    _ => 0,
+                };
+                cmd.set_cookie(used);
+                return Ok(0);
+            }
+            ZFS_IOC_GET_BOOKMARK_PROPS if name == "tank/fs#book" => {
+                let prop =
+                    |n| PairValue::List(PairList::from_pairs([("value", PairValue::UInt64(n))]));
+                let props = PairList::from_pairs([
+                    ("guid", prop(0x0b0c0b0c0b0c0b0c)),
+                    ("createtxg", prop(1234)),
+                    ("creation", prop(1_700_000_000)),
+                ]);
+                nvpair::pack(&props).unwrap()
+            }
+            ZFS_IOC_GET_FSACL if name == "tank/fs" => fixture("fsacl-tank_fs.nvlist"),
+            ZFS_IOC_OBJSET_STATS => {
+                Replay::objset_fixture(&name).ok_or(IOError::from_raw_os_error(ENOENT))?
+            }
+            ZFS_IOC_DATASET_LIST_NEXT if name == "tank" && cmd.cookie() == 0 => {
+                cmd.set_name(&CString::new("tank/fs").unwrap());
+                cmd.set_cookie(1);
+                fixture("objset_stats-tank_fs.nvlist")
+            }
+            ZFS_IOC_DATASET_LIST_NEXT => return Err(IOError::from_raw_os_error(ESRCH)),
+            ZFS_IOC_SNAPSHOT_LIST_NEXT if name == "tank/fs" && cmd.cookie() < 2 => {
+                let snap = ["tank/fs@a", "tank/fs@b"][cmd.cookie() as usize];
+                cmd.set_name(&CString::new(snap).unwrap());
+                cmd.set_cookie(cmd.cookie() + 1);
+                fixture("objset_stats-tank_fs.nvlist")
+            }
+            ZFS_IOC_SNAPSHOT_LIST_NEXT => return Err(IOError::from_raw_os_error(ESRCH)),
+            ZFS_IOC_DIFF if name == "tank/fs@b" && cmd.value().to_bytes() == b"tank/fs@a" => {
+                // a no-op record, objects 1-5 changed, object 8 removed
+                let recs: [u64; 9] = [1, 0, 0, 2, 1, 5, 4, 8, 8];
+                let buf: Vec<u8> = recs.iter().flat_map(|n| n.to_ne_bytes()).collect();
+                let mut out = ManuallyDrop::new(unsafe { File::from_raw_fd(cmd.cookie() as i32) });
+                out.write_all(&buf)?;
+                return Ok(0);
+            }
+            ZFS_IOC_SEND_PROGRESS if name == "tank/fs@b" => {
+                // a send in progress on stdout, and nowhere else
+                if cmd.cookie() != 1 {
+                    return Err(IOError::from_raw_os_error(ENOENT));
+                }
+                cmd.set_cookie(1 << 20);
+                return Ok(0);
+            }
+            ZFS_IOC_WAIT if name == "tank" => {
+                // there's always a scrub just finishing
+                self.ops.lock().unwrap().push(Op::new(req, cmd));
+                let out = PairList::from_pairs([("waited", PairValue::BooleanValue(true))]);
+                nvpair::pack(&out).unwrap()
+            }
+            ZFS_IOC_RECV_NEW if name == "tank/fs" => {
+                // take the whole stream, like the kernel would
+                let op = Op::new(req, cmd);
+                let fd = match op.src.as_ref().unwrap().get("input_fd").unwrap().value() {
+                    PairValue::Int32(fd) => *fd,
+                    _ => panic!("input_fd not an int32"),
+                };
+                let mut input = ManuallyDrop::new(unsafe { File::from_raw_fd(fd) });
+                let n = std::io::copy(&mut *input, &mut std::io::sink())?;
+                self.ops.lock().unwrap().push(op);
+
+                let mut out = PairList::new();
+                out.add("read_bytes", PairValue::UInt64(n));
+                out.add("error_flags", PairValue::UInt64(0));
+                nvpair::pack(&out).unwrap()
+            }
+            _ => return Err(IOError::from_raw_os_error(ENOENT)),
+        };
+        if req == ZFS_IOC_OBJSET_STATS || req == ZFS_IOC_DATASET_LIST_NEXT {
+            cmd.set_objset_stats(&Replay::objset_stats(&nvbuf));
+        }
+        if cmd.simple() {
+            return Ok(0); // just the name and stats; the kernel writes no list back
+        }
+        cmd.write_dst(&nvbuf)?;
+        Ok(0)
+    }
+
+    // any fd will do, as nothing's going to be cleaned up
+    fn cleanup_fd(&self) -> IOResult<OwnedFd> {
+        Ok(File::open("/dev/null")?.into())
+    }
+
+    fn try_clone(&self) -> IOResult<Box<dyn Transport>> {
+        Ok(Box::new(Replay {
+            calls: self.calls.clone(),
+            reguids: self.reguids.clone(),
+            ops: self.ops.clone(),
+            fail: self.fail.clone(),
+            aux: self.aux,
+        }))
+    }
+}
+
+// a transport with one pool, whose config (and stats) is whatever it's given, for testing the
+// vdev tree code against shapes no fixture has
+#[derive(Debug)]
+pub struct OnePool {
+    name: String,
+    config: PairList,
+}
+
+impl Transport for OnePool {
+    fn ioctl(&mut self, req: c_ulong, cmd: &mut ZFSCommand) -> IOResult<c_uint> {
+        let nvbuf = match req {
+            ZFS_IOC_POOL_CONFIGS => {
+                let configs = PairList::from_pairs([(
+                    self.name.as_str(),
+                    PairValue::List(self.config.clone()),
+                )]);
+                nvpair::pack(&configs).unwrap()
+            }
+            ZFS_IOC_POOL_STATS if cmd.name().to_bytes() == self.name.as_bytes() => {
+                nvpair::pack(&self.config).unwrap()
+            }
+            _ => return Err(IOError::from_raw_os_error(ENOENT)),
+        };
+        cmd.write_dst(&nvbuf)?;
+        Ok(0)
+    }
+
+    fn try_clone(&self) -> IOResult<Box<dyn Transport>> {
+        Ok(Box::new(OnePool {
+            name: self.name.clone(),
+            config: self.config.clone(),
+        }))
+    }
+}
+
+// a leaf vdev, for a made-up tree
+pub fn disk_config(guid: u64, path: &str) -> PairList {
+    PairList::from_pairs([
+        ("type", PairValue::String(CString::new("disk").unwrap())),
+        ("guid", PairValue::UInt64(guid)),
+        ("path", PairValue::String(CString::new(path).unwrap())),
+    ])
+}
+
+// a pool config around a made-up root vdev, for one_pool_root()
+pub fn pool_config(name: &str, root: PairList) -> PairList {
+    let children = root.get_list_slice("children").unwrap_or_default().len();
+    PairList::from_pairs([
+        ("name", PairValue::String(CString::new(name).unwrap())),
+        (
+            "pool_guid",
+            PairValue::UInt64(root.get_u64("guid").unwrap_or_default()),
+        ),
+        ("vdev_children", PairValue::UInt64(children as u64)),
+        ("vdev_tree", PairValue::List(root)),
+    ])
+}
+
+pub fn one_pool_root(name: &str, config: PairList) -> zfs::Root {
+    let dev = OnePool {
+        name: name.into(),
+        config,
+    };
+    zfs::Root::from(ioc::Handle::with_transport(Box::new(dev)))
+}
+
+pub fn replay_handle() -> ioc::Handle {
+    ioc::Handle::with_transport(Box::new(Replay::default()))
+}
+
+pub fn replay_root() -> zfs::Root {
+    zfs::Root::from(replay_handle())
+}
+
+// a root, and a count of the ioctls it has made
+pub fn counted_root() -> (zfs::Root, Arc<AtomicUsize>) {
+    let replay = Replay::default();
+    let calls = replay.calls.clone();
+    let root = zfs::Root::from(ioc::Handle::with_transport(Box::new(replay)));
+    (root, calls)
+}
+
+// a root, and a switch to make every call fail with an errno (0 to work again). for things
+// that go away or become unreadable after they were found
+pub fn failing_root() -> (zfs::Root, Arc<AtomicI32>) {
+    let replay = Replay::default();
+    let fail = replay.fail.clone();
+    let root = zfs::Root::from(ioc::Handle::with_transport(Box::new(replay)));
+    (root, fail)
+}
+
+// a root whose pool has the aux vdevs too
+pub fn aux_root() -> zfs::Root {
+    let replay = Replay {
+        aux: true,
+        ..Default::default()
+    };
+    zfs::Root::from(ioc::Handle::with_transport(Box::new(replay)))
+}
+
+// a handle, and the list of changes made through it
+pub fn recorded_handle() -> (ioc::Handle, Arc<Mutex<Vec<Op>>>) {
+    let replay = Replay::default();
+    let ops = replay.ops.clone();
+    (ioc::Handle::with_transport(Box::new(replay)), ops)
+}
+
+// a root, and the list of changes made through it
+pub fn recorded_root() -> (zfs::Root, Arc<Mutex<Vec<Op>>>) {
+    let replay = Replay::default();
+    let ops = replay.ops.clone();
+    let root = zfs::Root::from(ioc::Handle::with_transport(Box::new(replay)));
+    (root, ops)
+}
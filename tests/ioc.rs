@@ -0,0 +1,466 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+// Copyright (c) 2023, Rob Norris <robn@despairlabs.com>
+
+mod common;
+
+use common::{recorded_handle, replay_handle, send_stream, Replay};
+use common::{ZFS_IOC_DESTROY_BOOKMARKS, ZFS_IOC_RECV_NEW, ZFS_IOC_SET_FSACL, ZFS_IOC_SMB_ACL};
+use std::ffi::CString;
+use std::io::Result as IOResult;
+use std::io::{Error as IOError, ErrorKind as IOErrorKind};
+use std::os::raw::{c_uint, c_ulong};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use veneer::ioc::{self, DiffKind, Transport, ZFSCommand};
+use veneer::nvenums::ObjsetType;
+use veneer::nvpair::{PairList, PairValue, ParseError};
+
+#[test]
+fn dsobj_to_dsname() {
+    let mut h = replay_handle();
+    let tank = CString::new("tank").unwrap();
+
+    // objsetid, as the objset stats have it
+    let stats = h.objset_stats(&tank).unwrap();
+    assert_eq!(
+        stats.get_list("objsetid").unwrap().get_u64("value"),
+        Some(54)
+    );
+    assert_eq!(h.dsobj_to_dsname(&tank, 54).unwrap(), tank);
+
+    let e = h.dsobj_to_dsname(&tank, 55).unwrap_err();
+    assert_eq!(e.downcast::<IOError>().unwrap().raw_os_error(), Some(2)); // ENOENT
+}
+
+#[test]
+fn read_diff() {
+    // DDR_INUSE, DDR_NONE, DDR_FREE
+    let recs: [u64; 9] = [2, 10, 20, 1, 0, 0, 4, 30, 30];
+    let buf: Vec<u8> = recs.iter().flat_map(|n| n.to_ne_bytes()).collect();
+
+    let entries = ioc::read_diff(buf.as_slice()).unwrap();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].kind, DiffKind::InUse);
+    assert_eq!(entries[1].kind, DiffKind::Free);
+    assert_eq!((entries[1].first, entries[1].last), (30, 30));
+
+    // not a record type
+    let bad: Vec<u8> = [3u64, 1, 1].iter().flat_map(|n| n.to_ne_bytes()).collect();
+    let e = ioc::read_diff(bad.as_slice()).unwrap_err();
+    assert_eq!(e.kind(), IOErrorKind::InvalidData);
+
+    // cut off part way through a record
+    let e = ioc::read_diff(&buf[..40]).unwrap_err();
+    assert_eq!(e.kind(), IOErrorKind::UnexpectedEof);
+}
+
+#[test]
+fn next_obj() {
+    let mut h = replay_handle();
+    let fs = CString::new("tank/fs").unwrap();
+
+    let mut objs = vec![];
+    let mut obj = 0;
+    while let Some(next) = h.next_obj(&fs, obj).unwrap() {
+        objs.push(next);
+        obj = next;
+    }
+    assert_eq!(objs, vec![1, 2, 3, 7, 34]);
+
+    // starting from a hole finds the next one along
+    assert_eq!(h.next_obj(&fs, 4).unwrap(), Some(7));
+
+    // anything other than ESRCH is still an error
+    let nope = CString::new("tank/nope").unwrap();
+    assert!(h.next_obj(&nope, 0).is_err());
+}
+
+#[test]
+fn set_fsacl() {
+    let (mut h, ops) = recorded_handle();
+    let fs = CString::new("tank/fs").unwrap();
+
+    let mut who = PairList::new();
+    who.add("snapshot", PairValue::Boolean);
+    let mut perms = PairList::new();
+    perms.add("ul$1000", PairValue::List(who));
+
+    h.set_fsacl(&fs, &perms, false).unwrap();
+    h.set_fsacl(&fs, &perms, true).unwrap();
+
+    let ops = ops.lock().unwrap();
+    assert_eq!(ops.len(), 2);
+    assert!(ops.iter().all(|op| op.req == ZFS_IOC_SET_FSACL));
+    assert_eq!(ops[0].src.as_ref(), Some(&perms));
+    assert_eq!((ops[0].perm_action, ops[1].perm_action), (0, 1));
+}
+
+#[test]
+fn set_props_partial() {
+    let (mut h, ops) = recorded_handle();
+    let fs = CString::new("tank/fs").unwrap();
+
+    let mut props = PairList::new();
+    props.add("atime", PairValue::UInt64(0));
+    h.set_props(&fs, &props).unwrap();
+
+    // some can't be set, but the others still are, and the error says which is which
+    props.add("bad:one", PairValue::UInt64(1));
+    props.add("readonly", PairValue::UInt64(1));
+    let e = h.set_props(&fs, &props).unwrap_err();
+    let pf = e.downcast::<ioc::PartialFailure>().unwrap();
+    let succeeded: Vec<_> = pf.succeeded.iter().map(|n| n.to_str().unwrap()).collect();
+    assert_eq!(succeeded, vec!["atime", "readonly"]);
+    assert_eq!(pf.failed, vec![(CString::new("bad:one").unwrap(), 1)]);
+    assert_eq!(pf.errno(&CString::new("bad:one").unwrap()), Some(1)); // EPERM
+    assert_eq!(pf.errno(&CString::new("atime").unwrap()), None);
+    assert!(pf.to_string().starts_with("1 of 3 failed: bad:one: "));
+
+    assert_eq!(ops.lock().unwrap().len(), 2);
+
+    // and a failure with no errlist is just that failure
+    let nope = CString::new("tank/nope").unwrap();
+    let e = h.set_props(&nope, &props).unwrap_err();
+    assert!(e.downcast_ref::<ioc::PartialFailure>().is_none());
+}
+
+#[test]
+fn destroy_bookmarks() {
+    let (mut h, ops) = recorded_handle();
+    let tank = CString::new("tank").unwrap();
+    let book = CString::new("tank/fs#book").unwrap();
+    let gone = CString::new("tank/fs#gone").unwrap();
+    let nope = CString::new("tank/nope#book").unwrap();
+
+    // one that's already gone isn't a failure
+    h.destroy_bookmarks(&tank, &[&book, &gone]).unwrap();
+    {
+        let ops = ops.lock().unwrap();
+        assert_eq!(ops.len(), 1);
+        assert_eq!(ops[0].req, ZFS_IOC_DESTROY_BOOKMARKS);
+        let src = ops[0].src.as_ref().unwrap();
+        assert_eq!(src.keys().count(), 2);
+        assert!(matches!(
+            src.get("tank/fs#book").unwrap().value(),
+            PairValue::Boolean
+        ));
+    }
+
+    // one in a dataset that's not there is
+    let e = h.destroy_bookmarks(&tank, &[&book, &nope]).unwrap_err();
+    let pf = e.downcast::<ioc::PartialFailure>().unwrap();
+    assert_eq!(pf.succeeded, vec![book.clone()]);
+    assert_eq!(pf.errno(&nope), Some(2)); // ENOENT
+}
+
+#[test]
+fn smb_acl() {
+    let (mut h, ops) = recorded_handle();
+    let fs = CString::new("tank/fs").unwrap();
+    let path = CString::new("/tank/fs").unwrap();
+    let res = |n: &str| CString::new(n).unwrap();
+
+    h.smb_acl(&fs, &path, &ioc::SmbAclOp::Add(res("one")))
+        .unwrap();
+    h.smb_acl(
+        &fs,
+        &path,
+        &ioc::SmbAclOp::Rename {
+            from: res("one"),
+            to: res("two"),
+        },
+    )
+    .unwrap();
+    h.smb_acl(&fs, &path, &ioc::SmbAclOp::Purge).unwrap();
+
+    let ops = ops.lock().unwrap();
+    assert!(ops
+        .iter()
+        .all(|op| op.req == ZFS_IOC_SMB_ACL && op.value == "/tank/fs"));
+    assert_eq!(
+        ops.iter().map(|op| op.cookie).collect::<Vec<_>>(),
+        [0, 2, 3]
+    );
+    assert_eq!(ops[0].string, "one");
+    let rename = ops[1].src.as_ref().unwrap();
+    assert_eq!(common::get_str(rename, "src").as_deref(), Some("one"));
+    assert_eq!(common::get_str(rename, "target").as_deref(), Some("two"));
+
+    // anywhere but illumos, there's no such thing
+    let tank = CString::new("tank").unwrap();
+    let e = h.smb_acl(&tank, &path, &ioc::SmbAclOp::Purge).unwrap_err();
+    let e = e.downcast::<IOError>().unwrap();
+    assert_eq!(e.raw_os_error(), Some(95)); // ENOTSUP
+}
+
+#[test]
+fn name_too_long() {
+    // the kernel's name buffer is MAXPATHLEN, at most 4096
+    let mut h = replay_handle();
+    let long = CString::new("tank/".to_string() + &"x".repeat(5000)).unwrap();
+    let e = h.objset_stats(&long).unwrap_err();
+    assert_eq!(
+        e.downcast::<IOError>().unwrap().kind(),
+        IOErrorKind::InvalidInput
+    );
+
+    let tank = CString::new("tank").unwrap();
+    let long = CString::new("tank/fs@".to_string() + &"x".repeat(9000)).unwrap();
+    let e = h.diff(&tank, &long, &std::io::stdout()).unwrap_err();
+    assert_eq!(
+        e.downcast::<IOError>().unwrap().kind(),
+        IOErrorKind::InvalidInput
+    );
+}
+
+#[test]
+fn recv() {
+    let (mut h, ops) = recorded_handle();
+    let path = std::env::temp_dir().join(format!("veneer-recv-{}", std::process::id()));
+    std::fs::write(&path, send_stream("other/fs@c", 1000)).unwrap();
+    let stream = std::fs::File::open(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let snap = CString::new("tank/fs@c").unwrap();
+    let origin = CString::new("tank/fs@a").unwrap();
+    let mut props = PairList::new();
+    props.add("compression", PairValue::UInt64(15));
+    let out = h
+        .recv(
+            &snap,
+            None,
+            &stream,
+            Some(&props),
+            Some(&origin),
+            false,
+            true,
+        )
+        .unwrap();
+    // the begin record was taken off before the kernel got it
+    assert_eq!(out.get_u64("read_bytes"), Some(1000));
+
+    let ops = ops.lock().unwrap();
+    assert_eq!(ops[0].req, ZFS_IOC_RECV_NEW);
+    assert_eq!(ops[0].name, "tank/fs");
+    let args = ops[0].src.as_ref().unwrap();
+    assert_eq!(args.get_list("props"), Some(&props));
+    assert_eq!(
+        common::get_str(args, "origin").as_deref(),
+        Some("tank/fs@a")
+    );
+    assert!(args.get("force").is_none());
+    assert!(matches!(
+        args.get("resumable").unwrap().value(),
+        PairValue::Boolean
+    ));
+}
+
+#[test]
+fn stream_header() {
+    let mut begin = send_stream("tank/fs@c", 0);
+    begin[16..24].copy_from_slice(&((0x4u64 << 2) | 1).to_ne_bytes()); // LARGE_BLOCKS, substream
+    begin[24..32].copy_from_slice(&1_700_000_000u64.to_ne_bytes());
+    begin[32..36].copy_from_slice(&2u32.to_ne_bytes()); // DMU_OST_ZFS
+    begin[40..48].copy_from_slice(&0xc0ffeeu64.to_ne_bytes());
+
+    let hdr = ioc::parse_stream_header(&begin).unwrap();
+    assert_eq!(hdr.toname.to_bytes(), b"tank/fs@c");
+    assert_eq!(hdr.toguid, 0xc0ffee);
+    assert!(!hdr.is_incremental());
+    assert_eq!(hdr.creation_time, 1_700_000_000);
+    assert!(matches!(hdr.objset_type, ObjsetType::Zfs));
+    assert!(!hdr.compound);
+    assert_eq!(hdr.features, 0x4);
+    assert!(!hdr.swapped);
+
+    // the same stream from the other byte order reads the same, once swapped back
+    let mut swapped = begin.clone();
+    for (start, len) in [(8, 8), (16, 8), (24, 8), (32, 4), (36, 4), (40, 8), (48, 8)] {
+        swapped[start..start + len].reverse();
+    }
+    swapped[48..56].copy_from_slice(&0xbeefu64.swap_bytes().to_ne_bytes());
+    let hdr = ioc::parse_stream_header(&swapped).unwrap();
+    assert!(hdr.swapped);
+    assert_eq!(hdr.toguid, 0xc0ffee);
+    assert_eq!(hdr.fromguid, 0xbeef);
+    assert!(hdr.is_incremental());
+    assert_eq!(hdr.features, 0x4);
+}
+
+#[test]
+fn recv_bad_stream() {
+    let begin = send_stream("tank/fs@c", 0);
+    assert_eq!(ioc::stream_toname(&begin).unwrap().to_bytes(), b"tank/fs@c");
+    let mut swapped = begin.clone();
+    swapped[8..16].reverse();
+    assert!(ioc::stream_toname(&swapped).is_ok());
+
+    let mut bad = begin.clone();
+    bad[8] ^= 1;
+    assert!(ioc::stream_toname(&bad).is_err());
+    assert!(ioc::stream_toname(&begin[..100]).is_err());
+
+    // a snapshot name is needed, not just the filesystem
+    let mut h = replay_handle();
+    let fs = CString::new("tank/fs").unwrap();
+    let e = h
+        .recv(&fs, None, &std::io::stdin(), None, None, false, false)
+        .unwrap_err();
+    assert_eq!(
+        e.downcast::<IOError>().unwrap().kind(),
+        IOErrorKind::InvalidInput
+    );
+
+    // a compound stream (zfs send -R) is turned away before the kernel sees it
+    let (mut h, ops) = recorded_handle();
+    let mut compound = begin.clone();
+    compound[16..24].copy_from_slice(&2u64.to_ne_bytes()); // DMU_COMPOUNDSTREAM
+    let snap = CString::new("tank/fs@c").unwrap();
+    let e = h
+        .recv(
+            &snap,
+            Some(&compound),
+            &std::io::stdin(),
+            None,
+            None,
+            false,
+            false,
+        )
+        .unwrap_err();
+    assert_eq!(
+        e.downcast::<IOError>().unwrap().kind(),
+        IOErrorKind::Unsupported
+    );
+    assert!(ops.lock().unwrap().is_empty());
+}
+
+#[test]
+fn send_progress() {
+    let mut h = replay_handle();
+    let snap = CString::new("tank/fs@b").unwrap();
+    assert_eq!(h.send_progress(&snap, &std::io::stdout()).unwrap(), 1 << 20);
+
+    let e = h.send_progress(&snap, &std::io::stdin()).unwrap_err();
+    assert_eq!(e.downcast::<IOError>().unwrap().raw_os_error(), Some(2)); // ENOENT
+}
+
+// succeeds, with an empty result list
+#[derive(Debug)]
+struct Empty;
+
+impl Transport for Empty {
+    fn ioctl(&mut self, _: c_ulong, cmd: &mut ZFSCommand) -> IOResult<c_uint> {
+        cmd.write_dst(&[])?;
+        Ok(0)
+    }
+
+    fn try_clone(&self) -> IOResult<Box<dyn Transport>> {
+        Ok(Box::new(Empty))
+    }
+}
+
+#[test]
+fn empty_result() {
+    let mut h = ioc::Handle::with_transport(Box::new(Empty));
+    let e = h.pool_configs().unwrap_err();
+    assert!(matches!(
+        e.downcast::<ParseError>().unwrap().as_ref(),
+        ParseError::ShortRead(0)
+    ));
+}
+
+// claims a result larger than the buffer it was given
+#[derive(Debug)]
+struct Oversized;
+
+impl Transport for Oversized {
+    fn ioctl(&mut self, _: c_ulong, cmd: &mut ZFSCommand) -> IOResult<c_uint> {
+        let _ = cmd.write_dst(&vec![0; 1 << 20]);
+        Ok(0)
+    }
+
+    fn try_clone(&self) -> IOResult<Box<dyn Transport>> {
+        Ok(Box::new(Oversized))
+    }
+}
+
+#[test]
+fn oversized_result() {
+    let mut h = ioc::Handle::with_transport(Box::new(Oversized));
+    let e = h.pool_configs().unwrap_err();
+    assert_eq!(
+        e.downcast::<IOError>().unwrap().kind(),
+        IOErrorKind::InvalidData
+    );
+}
+
+// interrupted by a signal the first n times, then answers as Replay would
+#[derive(Debug)]
+struct Interrupted {
+    n: Arc<AtomicUsize>,
+    calls: Arc<AtomicUsize>,
+}
+
+impl Transport for Interrupted {
+    fn ioctl(&mut self, req: c_ulong, cmd: &mut ZFSCommand) -> IOResult<c_uint> {
+        self.calls.fetch_add(1, Ordering::Relaxed);
+        if self.n.load(Ordering::Relaxed) > 0 {
+            self.n.fetch_sub(1, Ordering::Relaxed);
+            return Err(IOError::from(IOErrorKind::Interrupted));
+        }
+        Replay::default().ioctl(req, cmd)
+    }
+
+    fn try_clone(&self) -> IOResult<Box<dyn Transport>> {
+        unimplemented!()
+    }
+}
+
+#[test]
+fn eintr_retry() {
+    let n = Arc::new(AtomicUsize::new(0));
+    let calls = Arc::new(AtomicUsize::new(0));
+    let mut h = ioc::Handle::with_transport(Box::new(Interrupted {
+        n: n.clone(),
+        calls: calls.clone(),
+    }));
+    let tank = CString::new("tank").unwrap();
+
+    // a few interruptions go unnoticed
+    n.store(3, Ordering::Relaxed);
+    h.pool_sync(&tank, false).unwrap();
+    assert_eq!(calls.swap(0, Ordering::Relaxed), 4);
+
+    // but not an endless stream of them
+    n.store(1000, Ordering::Relaxed);
+    let e = h.pool_sync(&tank, false).unwrap_err();
+    assert_eq!(
+        e.downcast::<IOError>().unwrap().kind(),
+        IOErrorKind::Interrupted
+    );
+    assert_eq!(calls.swap(0, Ordering::Relaxed), 17);
+
+    // a command that changes something may have done it before it was interrupted, so it's
+    // left to the caller
+    n.store(1, Ordering::Relaxed);
+    let e = h.pool_reguid(&tank).unwrap_err();
+    assert_eq!(
+        e.downcast::<IOError>().unwrap().kind(),
+        IOErrorKind::Interrupted
+    );
+    assert_eq!(calls.swap(0, Ordering::Relaxed), 1);
+
+    // and a stream can't be started again, so that's never retried
+    n.store(1, Ordering::Relaxed);
+    let snap = CString::new("tank/fs@b").unwrap();
+    let from = CString::new("tank/fs@a").unwrap();
+    let e = h.diff(&snap, &from, &std::io::stdout()).unwrap_err();
+    assert_eq!(
+        e.downcast::<IOError>().unwrap().kind(),
+        IOErrorKind::Interrupted
+    );
+    assert_eq!(calls.load(Ordering::Relaxed), 1);
+}
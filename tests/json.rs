@@ -0,0 +1,88 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+// Copyright (c) 2023, Rob Norris <robn@despairlabs.com>
+
+#![cfg(feature = "json")]
+
+mod common;
+
+use common::fixture;
+use serde_json::{json, Value};
+use std::ffi::CString;
+use veneer::nvpair::{self, PairList, PairValue};
+
+#[test]
+fn nested_to_json() {
+    let l = nvpair::parse(fixture("nested.nvlist").as_slice()).unwrap();
+    assert_eq!(
+        l.to_json(),
+        json!({
+            "before": 1,
+            "a": {
+                "a1": 1,
+                "b": {
+                    "b1": 2,
+                    "c": { "c1": 3, "deep": "x" },
+                    "b2": 4,
+                },
+                "a2": 5,
+            },
+            "arr": [
+                { "x": 1, "n": { "y": 2 } },
+                { "x": 3 },
+            ],
+            "after": 6,
+        })
+    );
+}
+
+#[test]
+fn value_types() {
+    let mut l = PairList::new();
+    l.add("flag", PairValue::Boolean);
+    l.add("off", PairValue::BooleanValue(false));
+    l.add("neg", PairValue::Int8(-1));
+    l.add("big", PairValue::UInt64(u64::MAX));
+    l.add("half", PairValue::Double(0.5));
+    l.add("nan", PairValue::Double(f64::NAN));
+    l.add("bytes", PairValue::ByteArray(vec![1, 2]));
+    l.add(
+        "strs",
+        PairValue::StringArray(vec![CString::new("a").unwrap(), CString::new("b").unwrap()]),
+    );
+    l.add("empty", PairValue::List(PairList::new()));
+    l.add(
+        "odd",
+        PairValue::Unknown {
+            type_code: 99,
+            raw: vec![0; 8],
+        },
+    );
+
+    let v = Value::from(&l);
+    assert_eq!(
+        v,
+        json!({
+            "flag": true,
+            "off": false,
+            "neg": -1,
+            "big": u64::MAX,
+            "half": 0.5,
+            "nan": null,
+            "bytes": [1, 2],
+            "strs": ["a", "b"],
+            "empty": {},
+            "odd": null,
+        })
+    );
+
+    // it's a plain tree, so it can be picked apart and changed
+    let mut v = v;
+    v.as_object_mut().unwrap().retain(|k, _| k.starts_with('b'));
+    assert_eq!(
+        v.to_string(),
+        r#"{"big":18446744073709551615,"bytes":[1,2]}"#
+    );
+}
@@ -0,0 +1,602 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+// Copyright (c) 2023, Rob Norris <robn@despairlabs.com>
+
+mod common;
+
+use common::{fixture, fixture_path, get_str};
+use std::borrow::Cow;
+use std::ffi::CString;
+use std::fs::File;
+use veneer::nvpair::{self, Change, Encoding, Endian, PackError, PairList, PairValue, ParseError};
+
+#[test]
+fn parse_header() {
+    let p = nvpair::parse_full(fixture("small.nvlist").as_slice()).unwrap();
+    assert_eq!(p.encoding, Encoding::Native);
+    assert_eq!(p.endian, Endian::Little);
+    assert_eq!(p.version, 0);
+    assert_eq!(p.flags, 1);
+}
+
+#[test]
+fn parse_xdr() {
+    // the same list as small.nvlist, XDR-encoded the way zpool.cache is, read straight from the
+    // file
+    let p = nvpair::parse_full(File::open(fixture_path("small-xdr.nvlist")).unwrap()).unwrap();
+    assert_eq!(p.encoding, Encoding::XDR);
+    assert_eq!(p.version, 0);
+    assert_eq!(p.flags, 1);
+
+    let l = p.list;
+    assert_eq!(l.get_u64("a"), Some(1));
+    assert_eq!(get_str(&l, "s"), Some("hello".into()));
+    assert_eq!(l.get_list("l").unwrap().get_u64("x"), Some(2));
+    assert_eq!(l.get_u64_slice("arr"), Some([1, 2, 3].as_slice()));
+    assert!(matches!(l.get("flag").unwrap().value(), PairValue::Boolean));
+
+    let native = nvpair::parse(fixture("small.nvlist").as_slice()).unwrap();
+    assert_eq!(l.to_string(), native.to_string());
+}
+
+#[test]
+fn parse_slice() {
+    // straight from memory, no Read involved, and the same answer either way
+    for name in ["small.nvlist", "small-xdr.nvlist", "pool_stats-tank.nvlist"] {
+        let buf = fixture(name);
+        let p = nvpair::parse_full_slice(&buf).unwrap();
+        let l = nvpair::parse(buf.as_slice()).unwrap();
+        assert_eq!(p.list, l);
+        assert_eq!(nvpair::parse_slice(&buf).unwrap(), l);
+    }
+
+    assert!(matches!(
+        nvpair::parse_slice(&[0, 1]),
+        Err(ParseError::ShortRead(0))
+    ));
+}
+
+#[test]
+fn parse_pool_configs() {
+    let l = nvpair::parse(fixture("pool_configs.nvlist").as_slice()).unwrap();
+    assert_eq!(
+        l.keys().map(|k| k.to_str().unwrap()).collect::<Vec<_>>(),
+        vec!["tank"]
+    );
+
+    let config = l.get_list("tank").unwrap();
+    assert_eq!(config.get_u64("version"), Some(5000));
+    assert_eq!(get_str(config, "name").as_deref(), Some("tank"));
+    assert_eq!(config.get_u64("pool_guid"), Some(0x1f2e3d4c5b6a7988));
+    assert!(matches!(
+        config
+            .get("com.delphix:has_per_vdev_zaps")
+            .map(|p| p.value()),
+        Some(PairValue::Boolean)
+    ));
+
+    // three levels of nesting: root -> mirror -> file
+    let root = config.get_list("vdev_tree").unwrap();
+    assert_eq!(get_str(root, "type").as_deref(), Some("root"));
+    let mirror = &root.get_list_slice("children").unwrap()[0];
+    assert_eq!(get_str(mirror, "type").as_deref(), Some("mirror"));
+    let leaves = mirror.get_list_slice("children").unwrap();
+    assert_eq!(leaves.len(), 2);
+    assert_eq!(get_str(&leaves[1], "path").as_deref(), Some("/var/tmp/d2"));
+
+    // pairs after a nested list must still line up
+    assert!(config.get_list("features_for_read").is_some());
+}
+
+#[test]
+fn parse_pool_stats() {
+    let l = nvpair::parse(fixture("pool_stats-tank.nvlist").as_slice()).unwrap();
+    let root = l.get_list("vdev_tree").unwrap();
+    assert_eq!(root.get_u64_slice("vdev_stats").map(|s| s.len()), Some(47));
+    assert_eq!(l.get_u64("error_count"), Some(0));
+    assert_eq!(
+        l.get_list("feature_stats")
+            .and_then(|f| f.get_u64("com.delphix:hole_birth")),
+        Some(1)
+    );
+}
+
+#[test]
+fn parse_objset_stats() {
+    let l = nvpair::parse(fixture("objset_stats-tank.nvlist").as_slice()).unwrap();
+    let used = l.get_list("used").unwrap();
+    assert_eq!(used.get_u64("value"), Some(1048576));
+    assert!(used.get("source").is_none());
+    let mp = l.get_list("mountpoint").unwrap();
+    assert_eq!(get_str(mp, "value").as_deref(), Some("/tank"));
+    assert_eq!(get_str(mp, "source").as_deref(), Some("tank"));
+}
+
+#[test]
+fn display() {
+    let l = nvpair::parse(fixture("small.nvlist").as_slice()).unwrap();
+    assert_eq!(
+        l.to_string(),
+        "a = 1\n\
+         s = hello\n\
+         l\n    \
+             x = 2\n\
+         arr = [1, 2, 3]\n\
+         flag\n"
+    );
+}
+
+// tiny deterministic generator, so failures are reproducible
+fn xorshift(state: &mut u64) -> u64 {
+    *state ^= *state << 13;
+    *state ^= *state >> 7;
+    *state ^= *state << 17;
+    *state
+}
+
+#[test]
+fn truncated() {
+    // every prefix of a valid buffer must fail cleanly, not panic
+    for name in [
+        "small.nvlist",
+        "small-xdr.nvlist",
+        "pool_configs.nvlist",
+        "objset_stats-tank.nvlist",
+    ] {
+        let buf = fixture(name);
+        for n in 0..buf.len() {
+            assert!(
+                nvpair::parse(&buf[..n]).is_err(),
+                "{} truncated to {}",
+                name,
+                n
+            );
+        }
+    }
+}
+
+#[test]
+fn corrupted() {
+    // random byte damage may or may not parse, but must never panic
+    let buf = fixture("pool_stats-tank.nvlist");
+    let mut state = 0x2545f4914f6cdd1d;
+    for _ in 0..2000 {
+        let mut bad = buf.clone();
+        for _ in 0..(xorshift(&mut state) % 8 + 1) {
+            let i = (xorshift(&mut state) as usize) % bad.len();
+            bad[i] = xorshift(&mut state) as u8;
+        }
+        let _ = nvpair::parse(bad.as_slice());
+    }
+}
+
+#[test]
+fn corrupt_name_len() {
+    // first pair header starts after the 12-byte list header: size, name_len, reserve, nelems
+    let mut buf = fixture("small.nvlist");
+    buf[16..18].copy_from_slice(&0x7fffi16.to_le_bytes());
+    assert!(matches!(
+        nvpair::parse(buf.as_slice()),
+        Err(ParseError::ShortRead(_))
+    ));
+
+    let mut buf = fixture("small.nvlist");
+    buf[16..18].copy_from_slice(&(-1i16).to_le_bytes());
+    assert!(matches!(
+        nvpair::parse(buf.as_slice()),
+        Err(ParseError::ShortRead(_))
+    ));
+}
+
+#[test]
+fn corrupt_nelems() {
+    let mut buf = fixture("small.nvlist");
+    buf[20..24].copy_from_slice(&i32::MAX.to_le_bytes());
+    assert!(matches!(
+        nvpair::parse(buf.as_slice()),
+        Err(ParseError::ShortRead(_))
+    ));
+
+    let mut buf = fixture("small.nvlist");
+    buf[20..24].copy_from_slice(&(-1i32).to_le_bytes());
+    assert!(matches!(
+        nvpair::parse(buf.as_slice()),
+        Err(ParseError::ShortRead(_))
+    ));
+}
+
+#[test]
+fn corrupt_array_nelems() {
+    // "arr" is three uint64s, 24 bytes. claim more than would fit, but not so many that the
+    // cheap one-byte-per-element check catches it
+    let mut buf = fixture("small.nvlist");
+    let at = buf.windows(4).position(|w| w == b"arr\0").unwrap();
+    buf[at - 8..at - 4].copy_from_slice(&20i32.to_le_bytes());
+    assert!(matches!(
+        nvpair::parse(buf.as_slice()),
+        Err(ParseError::ShortRead(off)) if off == at + 8
+    ));
+
+    // xdr: the name, then type, nelems, and the array's own count, which must agree
+    let mut buf = fixture("small-xdr.nvlist");
+    let at = buf.windows(4).position(|w| w == b"arr\0").unwrap();
+    let n = ((buf.len() - at - 16) / 4) as i32;
+    buf[at + 8..at + 12].copy_from_slice(&n.to_be_bytes());
+    buf[at + 12..at + 16].copy_from_slice(&n.to_be_bytes());
+    assert!(matches!(
+        nvpair::parse(buf.as_slice()),
+        Err(ParseError::ShortRead(off)) if off == at + 16
+    ));
+}
+
+#[test]
+fn unique_name_flags() {
+    // NV_UNIQUE_NAME, then NV_UNIQUE_NAME_TYPE
+    for flags in [1u32, 2] {
+        let mut buf = fixture("small.nvlist");
+        buf[8..12].copy_from_slice(&flags.to_le_bytes());
+        let p = nvpair::parse_full(buf.as_slice()).unwrap();
+        assert_eq!(p.flags, flags);
+        assert_eq!(p.list.get_u64("a"), Some(1));
+    }
+
+    // with NV_UNIQUE_NAME_TYPE, rename "s" to "a", so there's an "a" of two types. get() finds
+    // the first, the typed getters find the one of their type
+    let mut buf = fixture("small.nvlist");
+    buf[8..12].copy_from_slice(&2u32.to_le_bytes());
+    let at = buf.windows(2).position(|w| w == b"s\0").unwrap();
+    buf[at] = b'a';
+    let l = nvpair::parse(buf.as_slice()).unwrap();
+    assert!(matches!(l.get("a").unwrap().value(), PairValue::UInt64(1)));
+    assert_eq!(l.get_u64("a"), Some(1));
+    assert_eq!(get_str(&l, "a"), Some("hello".into()));
+
+    // anything else is unknown
+    let mut buf = fixture("small.nvlist");
+    buf[8..12].copy_from_slice(&4u32.to_le_bytes());
+    assert!(matches!(
+        nvpair::parse(buf.as_slice()),
+        Err(ParseError::InvalidFlags)
+    ));
+}
+
+#[test]
+fn error_offsets() {
+    // cut off part way through the list flags, which start at 8
+    let buf = fixture("small.nvlist");
+    let e = nvpair::parse(&buf[..10]).unwrap_err();
+    assert!(matches!(e, ParseError::ShortRead(8)));
+    assert_eq!(e.to_string(), "short read at offset 8");
+
+    // nothing at all, or not even the whole header
+    for len in [0, 1, 3] {
+        let e = nvpair::parse(&buf[..len]).unwrap_err();
+        assert!(matches!(e, ParseError::ShortRead(0)));
+    }
+
+    // the header, but not the list version after it
+    for len in [4, 7] {
+        let e = nvpair::parse(&buf[..len]).unwrap_err();
+        assert!(matches!(e, ParseError::ShortRead(4)));
+    }
+
+    // first pair's name and value, with no NUL anywhere in them
+    let mut buf = fixture("small.nvlist");
+    buf[28..44].copy_from_slice(b"aaaaaaaaaaaaaaaa");
+    let e = nvpair::parse(buf.as_slice()).unwrap_err();
+    assert!(matches!(e, ParseError::UnterminatedString(28)));
+}
+
+#[test]
+fn unknown_pair_type() {
+    // first pair's type, "a" (u64 1). native has it after size, name_len, reserve and nelems;
+    // XDR after the header, both sizes and the name. anything outside data_type_t is unknown:
+    // 0 is DATA_TYPE_DONTCARE, never on the wire, and 27 (double) is the last
+    for typ in [0i32, 28, 99, -1] {
+        let mut buf = fixture("small.nvlist");
+        buf[24..28].copy_from_slice(&typ.to_le_bytes());
+        let native = nvpair::parse(buf.as_slice()).unwrap();
+
+        let mut buf = fixture("small-xdr.nvlist");
+        buf[28..32].copy_from_slice(&typ.to_be_bytes());
+        let xdr = nvpair::parse(buf.as_slice()).unwrap();
+
+        for (l, raw) in [(&native, 1u64.to_le_bytes()), (&xdr, 1u64.to_be_bytes())] {
+            assert_eq!(
+                l.get("a").unwrap().value(),
+                &PairValue::Unknown {
+                    type_code: typ,
+                    raw: raw.to_vec()
+                }
+            );
+
+            // and everything after it is still there
+            assert_eq!(get_str(l, "s"), Some("hello".into()));
+            assert_eq!(l.get_list("l").unwrap().get_u64("x"), Some(2));
+            assert!(l.get("flag").is_some());
+        }
+    }
+
+    let mut buf = fixture("small.nvlist");
+    buf[24..28].copy_from_slice(&99i32.to_le_bytes());
+    let l = nvpair::parse(buf.as_slice()).unwrap();
+    assert!(l
+        .to_string()
+        .starts_with("a = <unknown type 99, 8 bytes>\ns = hello\n"));
+
+    // can't be written back out, and that's an error, not a pair quietly lost
+    let e = nvpair::pack(&l).unwrap_err();
+    assert!(matches!(e, PackError::UnknownPairType(ref name, 99) if name.to_bytes() == b"a"));
+
+    // nor can a list holding one
+    let mut outer = PairList::new();
+    outer.add("inner", PairValue::List(l));
+    assert!(nvpair::pack(&outer).is_err());
+}
+
+#[test]
+fn string_access() {
+    let mut l = nvpair::parse(fixture("small.nvlist").as_slice()).unwrap();
+    l.add(
+        "bad",
+        PairValue::String(CString::new(b"a\xffb".to_vec()).unwrap()),
+    );
+
+    // borrowed straight from the list when it can be
+    assert!(matches!(l.get_str("s"), Some(Cow::Borrowed("hello"))));
+    assert_eq!(l.get_str("bad").as_deref(), Some("a\u{fffd}b"));
+    assert_eq!(
+        l.get("bad").unwrap().to_c_string().unwrap().as_bytes(),
+        b"a\xffb"
+    );
+
+    // not strings
+    assert!(l.get_str("a").is_none());
+    assert!(l.get("l").unwrap().as_str().is_none());
+}
+
+#[test]
+fn key_types() {
+    let l = nvpair::parse(fixture("small.nvlist").as_slice()).unwrap();
+    let key = CString::new("s").unwrap();
+
+    // the same pair, however the key is held
+    let want = l.get("s").unwrap();
+    assert_eq!(l.get(key.as_c_str()), Some(want));
+    assert_eq!(l.get(&key), Some(want));
+    assert_eq!(l.get(String::from("s")), Some(want));
+    assert_eq!(l.get(b"s".as_slice()), Some(want));
+    assert_eq!(l.get_str(key.as_c_str()).as_deref(), Some("hello"));
+
+    // keys can't have a NUL in them, so one with a NUL matches nothing
+    assert!(l.get("s\0").is_none());
+    assert!(l.get("").is_none());
+}
+
+#[test]
+fn parse_nested() {
+    let l = nvpair::parse(fixture("nested.nvlist").as_slice()).unwrap();
+    assert_eq!(l.keys().count(), 4);
+    assert_eq!(l.get_u64("before"), Some(1));
+    assert_eq!(l.get_u64("after"), Some(6));
+
+    let a = l.get_list("a").unwrap();
+    assert_eq!(a.get_u64("a1"), Some(1));
+    assert_eq!(a.get_u64("a2"), Some(5));
+    let b = a.get_list("b").unwrap();
+    assert_eq!(b.get_u64("b1"), Some(2));
+    assert_eq!(b.get_u64("b2"), Some(4));
+    let c = b.get_list("c").unwrap();
+    assert_eq!(c.get_u64("c1"), Some(3));
+    assert_eq!(get_str(c, "deep"), Some("x".into()));
+
+    let arr = l.get_list_slice("arr").unwrap();
+    assert_eq!(arr.len(), 2);
+    assert_eq!(arr[0].get_u64("x"), Some(1));
+    assert_eq!(arr[0].get_list("n").unwrap().get_u64("y"), Some(2));
+    assert_eq!(arr[1].get_u64("x"), Some(3));
+}
+
+#[test]
+fn embedded_header() {
+    // the nvlist_t copy in the value of "l" carries the embedded list's version
+    let mut buf = fixture("small.nvlist");
+    let at = buf.windows(2).position(|w| w == b"l\0").unwrap() + 8;
+    buf[at..at + 4].copy_from_slice(&1i32.to_le_bytes());
+    assert!(matches!(
+        nvpair::parse(buf.as_slice()),
+        Err(ParseError::InvalidVersion)
+    ));
+}
+
+#[test]
+fn pack_roundtrip() {
+    // packing what we parsed should give back exactly what we started with
+    for name in [
+        "small.nvlist",
+        "nested.nvlist",
+        "pool_configs.nvlist",
+        "pool_stats-tank.nvlist",
+        "objset_stats-tank.nvlist",
+    ] {
+        let buf = fixture(name);
+        let l = nvpair::parse(buf.as_slice()).unwrap();
+        assert_eq!(nvpair::pack(&l).unwrap(), buf, "{}", name);
+    }
+}
+
+#[test]
+fn time_and_float_types() {
+    // the types the old HashMap parser never learned. there's only the one parser now, so
+    // this just pins them down
+    let mut l = PairList::new();
+    l.add("hrtime", PairValue::HiResTime(-1_000_000_007));
+    l.add("double", PairValue::Double(-2.5e-3));
+    l.add("on", PairValue::BooleanValue(true));
+    l.add("off", PairValue::BooleanValue(false));
+
+    let p = nvpair::parse(nvpair::pack(&l).unwrap().as_slice()).unwrap();
+    assert_eq!(p, l);
+    assert!(matches!(
+        p.get("hrtime").unwrap().value(),
+        PairValue::HiResTime(-1_000_000_007)
+    ));
+    assert_eq!(
+        p.get("double").unwrap().value(),
+        &PairValue::Double(-2.5e-3)
+    );
+}
+
+#[test]
+fn pack_build() {
+    let mut inner = PairList::new();
+    inner.add("x", PairValue::UInt64(2));
+
+    let mut l = PairList::new();
+    l.add("a", PairValue::UInt64(1));
+    l.add("s", PairValue::String(CString::new("hello").unwrap()));
+    l.add("l", PairValue::List(inner));
+    l.add("arr", PairValue::UInt64Array(vec![1, 2, 3]));
+    l.add("flag", PairValue::Boolean);
+    assert_eq!(nvpair::pack(&l).unwrap(), fixture("small.nvlist"));
+
+    // keys are unique; adding again replaces
+    l.add("a", PairValue::UInt64(5));
+    assert_eq!(l.pairs().count(), 5);
+    assert_eq!(l.get_u64("a"), Some(5));
+}
+
+#[test]
+fn merge() {
+    let mut l = PairList::new();
+    l.add(
+        "compression",
+        PairValue::String(CString::new("off").unwrap()),
+    );
+    l.add("atime", PairValue::UInt64(1));
+
+    let mut overlay = PairList::new();
+    overlay.add(
+        "compression",
+        PairValue::String(CString::new("lz4").unwrap()),
+    );
+    overlay.add("recordsize", PairValue::UInt64(1048576));
+    l.merge(overlay);
+
+    assert_eq!(l.pairs().count(), 3);
+    assert_eq!(get_str(&l, "compression"), Some("lz4".into()));
+    assert_eq!(l.get_u64("atime"), Some(1));
+    assert_eq!(l.get_u64("recordsize"), Some(1048576));
+
+    // replaced where it was, new ones after
+    let keys: Vec<_> = l.keys().map(|k| k.to_str().unwrap()).collect();
+    assert_eq!(keys, ["compression", "atime", "recordsize"]);
+}
+
+#[test]
+fn equality() {
+    let buf = fixture("pool_stats-tank.nvlist");
+    let a = nvpair::parse(buf.as_slice()).unwrap();
+    let b = nvpair::parse(buf.as_slice()).unwrap();
+    assert_eq!(a, b);
+
+    // order doesn't matter, type does
+    let mut x = PairList::new();
+    x.add("a", PairValue::UInt64(1));
+    x.add("b", PairValue::UInt64Array(vec![1, 2]));
+    let mut y = PairList::new();
+    y.add("b", PairValue::UInt64Array(vec![1, 2]));
+    y.add("a", PairValue::UInt64(1));
+    assert_eq!(x, y);
+    y.add("a", PairValue::UInt32(1));
+    assert_ne!(x, y);
+}
+
+// a list with these names and values, duplicates and all, as only a parsed list can be. the
+// names are single letters
+fn with_dups(pairs: &[(&str, u64)]) -> PairList {
+    let mut l = PairList::new();
+    for (i, (_, v)) in pairs.iter().enumerate() {
+        l.add([b'A' + i as u8], PairValue::UInt64(*v));
+    }
+    let mut buf = nvpair::pack(&l).unwrap();
+    buf[8..12].copy_from_slice(&0u32.to_le_bytes()); // no NV_UNIQUE_NAME
+    for (i, (k, _)) in pairs.iter().enumerate() {
+        let at = buf
+            .windows(2)
+            .position(|w| w == [b'A' + i as u8, 0])
+            .unwrap();
+        buf[at] = k.as_bytes()[0];
+    }
+    nvpair::parse(buf.as_slice()).unwrap()
+}
+
+#[test]
+fn equality_dups() {
+    // same length, and every pair is in the other, but not as many times
+    let x = with_dups(&[("a", 1), ("a", 1), ("b", 2)]);
+    let y = with_dups(&[("a", 1), ("b", 2), ("b", 2)]);
+    assert_eq!(x.pairs().count(), 3);
+    assert_ne!(x, y);
+    assert_ne!(y, x);
+    assert_eq!(x, with_dups(&[("b", 2), ("a", 1), ("a", 1)]));
+}
+
+#[test]
+fn diff_dups() {
+    // the second a changed, and the third is new
+    let x = with_dups(&[("a", 1), ("a", 2), ("b", 3)]);
+    let y = with_dups(&[("a", 1), ("a", 5), ("b", 3), ("a", 6)]);
+    let a = || CString::new("a").unwrap();
+    assert_eq!(
+        x.diff(&y),
+        vec![
+            Change::Modified(a(), PairValue::UInt64(2), PairValue::UInt64(5)),
+            Change::Added(a(), PairValue::UInt64(6)),
+        ]
+    );
+    assert_eq!(
+        y.diff(&x),
+        vec![
+            Change::Modified(a(), PairValue::UInt64(5), PairValue::UInt64(2)),
+            Change::Removed(a(), PairValue::UInt64(6)),
+        ]
+    );
+}
+
+#[test]
+fn diff() {
+    let before = nvpair::parse(fixture("objset_stats-tank_fs.nvlist").as_slice()).unwrap();
+    assert!(before.diff(&before).is_empty());
+
+    let mut after = before.clone();
+    let mut used = after.get_list("used").unwrap().clone();
+    let old = used.clone();
+    used.add("value", PairValue::UInt64(123456));
+    after.add("used", PairValue::List(used.clone()));
+
+    assert_eq!(
+        before.diff(&after),
+        vec![Change::Modified(
+            CString::new("used").unwrap(),
+            PairValue::List(old),
+            PairValue::List(used)
+        )]
+    );
+
+    let mut x = PairList::new();
+    x.add("gone", PairValue::Boolean);
+    x.add("same", PairValue::UInt64(1));
+    let mut y = PairList::new();
+    y.add("same", PairValue::UInt64(1));
+    y.add("new", PairValue::UInt64(2));
+    assert_eq!(
+        x.diff(&y),
+        vec![
+            Change::Removed(CString::new("gone").unwrap(), PairValue::Boolean),
+            Change::Added(CString::new("new").unwrap(), PairValue::UInt64(2)),
+        ]
+    );
+}
@@ -0,0 +1,143 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+// Copyright (c) 2023, Rob Norris <robn@despairlabs.com>
+
+use veneer::nvpair::{self, PairList, PairValue};
+use veneer::nvtypes::{VdevStats, VdevStatsEx};
+
+#[test]
+fn vdev_stats_short() {
+    // an older ZFS that only sends the first few fields
+    let s: Vec<u64> = (1..=10).collect();
+    let (vs, n) = VdevStats::from_slice(&s);
+    assert_eq!(n, 10);
+    assert_eq!(vs.timestamp, 1);
+    assert_eq!(vs.esize, 8);
+    assert_eq!(vs.ops[..2], [9, 10]);
+    assert_eq!(vs.ops[2..], [0; 4]);
+    assert_eq!(vs.pspace, 0);
+}
+
+#[test]
+fn vdev_stats_long() {
+    // a newer ZFS with fields we don't know about yet
+    let s: Vec<u64> = vec![7; VdevStats::fields() + 5];
+    let (vs, n) = VdevStats::from_slice(&s);
+    assert_eq!(n, VdevStats::fields());
+    assert_eq!(vs.pspace, 7);
+}
+
+#[test]
+fn vdev_stats_byte_order() {
+    // the same stats through a native (little-endian) and an XDR (big-endian) nvlist must come
+    // out the same, and in host order. every byte of each value differs, so a swap would show
+    let s: Vec<u64> = (0..VdevStats::fields() as u64)
+        .map(|n| 0x0102030405060708 + (n << 56))
+        .collect();
+
+    let mut l = PairList::new();
+    l.add("vdev_stats", PairValue::UInt64Array(s.clone()));
+    let native = nvpair::parse(nvpair::pack(&l).unwrap().as_slice()).unwrap();
+
+    // XDR header, nvlist version and flags (NV_UNIQUE_NAME), then one UINT64_ARRAY pair: sizes,
+    // name, type, nelems, count, values. then the zero sizes that end the list
+    let be = |n: i32| n.to_be_bytes().to_vec();
+    let mut pair = [
+        be(10),
+        b"vdev_stats\0\0".to_vec(),
+        be(16),
+        be(s.len() as i32),
+    ]
+    .concat();
+    pair.extend(be(s.len() as i32));
+    pair.extend(s.iter().flat_map(|n| n.to_be_bytes()));
+    let xdr = [
+        vec![1, 0, 0, 0],
+        be(0),
+        be(1),
+        be(pair.len() as i32 + 8),
+        be(0),
+        pair,
+        be(0),
+        be(0),
+    ]
+    .concat();
+    let xdr = nvpair::parse(xdr.as_slice()).unwrap();
+
+    for l in [native, xdr] {
+        let (vs, n) = VdevStats::from_slice(l.get_u64_slice("vdev_stats").unwrap());
+        assert_eq!(n, VdevStats::fields());
+        assert_eq!(vs.timestamp, 0x0102030405060708);
+        assert_eq!(vs.state, 0x0202030405060708);
+        assert_eq!(vs.ops[0], 0x0902030405060708);
+        assert_eq!(vs.pspace, s[s.len() - 1]);
+    }
+}
+
+#[test]
+fn vdev_stats_zio_types() {
+    // ops and bytes are indexed by zio_type_t: null, read, write, free, claim, ioctl (trim)
+    let vs = VdevStats {
+        ops: [10, 11, 12, 13, 14, 15],
+        bytes: [20, 21, 22, 23, 24, 25],
+        ..Default::default()
+    };
+    assert_eq!(
+        [
+            vs.read_ops(),
+            vs.write_ops(),
+            vs.free_ops(),
+            vs.claim_ops(),
+            vs.trim_ops()
+        ],
+        [11, 12, 13, 14, 15]
+    );
+    assert_eq!(
+        [
+            vs.read_bytes(),
+            vs.write_bytes(),
+            vs.free_bytes(),
+            vs.claim_bytes(),
+            vs.trim_bytes()
+        ],
+        [21, 22, 23, 24, 25]
+    );
+}
+
+#[test]
+fn vdev_stats_ex_latency_percentiles() {
+    // 100 reads: half around 1us, most of the rest around 4us, a few slow ones
+    let mut histo = vec![0u64; 37];
+    histo[10] = 50;
+    histo[12] = 45;
+    histo[14] = 4;
+    histo[20] = 1;
+    let vse = VdevStatsEx {
+        tot_r_lat_histo: histo,
+        ..Default::default()
+    };
+    assert_eq!(vse.read_latency_percentiles(), (1 << 10, 1 << 12, 1 << 14));
+
+    // no writes yet
+    assert_eq!(vse.write_latency_percentiles(), (0, 0, 0));
+
+    // p99 is where the 99th read lands, so a single slow one in 100 doesn't show
+    let mut histo = vec![0u64; 37];
+    histo[10] = 99;
+    histo[30] = 1;
+    assert_eq!(
+        VdevStatsEx::latency_percentiles(&histo),
+        (1 << 10, 1 << 10, 1 << 10)
+    );
+
+    // any histogram can be given, even one longer than a u64 can count up to
+    let mut histo = vec![0u64; 100];
+    histo[63] = 50;
+    histo[80] = 50;
+    assert_eq!(
+        VdevStatsEx::latency_percentiles(&histo),
+        (1 << 63, u64::MAX, u64::MAX)
+    );
+}
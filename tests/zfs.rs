@@ -0,0 +1,1665 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+// Copyright (c) 2023, Rob Norris <robn@despairlabs.com>
+
+mod common;
+
+use common::{
+    aux_root, counted_root, disk_config, failing_root, fixture_path, get_str, one_pool_root,
+    pool_config, recorded_root, replay_root, send_stream,
+};
+use common::{ZFS_IOC_CLEAR_FAULT, ZFS_IOC_INJECT_FAULT, ZFS_IOC_RECV_NEW, ZFS_IOC_SET_PROP};
+use common::{
+    ZFS_IOC_DESTROY_BOOKMARKS, ZFS_IOC_VDEV_ADD, ZFS_IOC_VDEV_ATTACH, ZFS_IOC_VDEV_DETACH,
+};
+use common::{
+    ZFS_IOC_HOLD, ZFS_IOC_REDACT, ZFS_IOC_RELEASE, ZFS_IOC_REMAP, ZFS_IOC_SHARE, ZFS_IOC_SNAPSHOT,
+    ZFS_IOC_TMP_SNAPSHOT,
+};
+use common::{
+    ZFS_IOC_POOL_SET_PROPS, ZFS_IOC_POOL_SYNC, ZFS_IOC_VDEV_REMOVE, ZFS_IOC_VDEV_SET_STATE,
+};
+use std::error::Error;
+use std::ffi::CString;
+use std::io::{Error as IOError, ErrorKind as IOErrorKind};
+use std::path::PathBuf;
+use std::sync::atomic::Ordering;
+use veneer::ioc::{self, DiffEntry, DiffKind};
+use veneer::nvenums::{
+    AllocBias, CanMount, FeatureState, ObjsetType, PoolState, PropSource, VdevState, VdevType,
+};
+use veneer::nvpair::{PairList, PairValue};
+use veneer::nvtypes::VdevStats;
+use veneer::zfs::error::VeneerError;
+use veneer::zfs::inject::{IoType, ZInjectBuilder};
+use veneer::zfs::{
+    self, DatasetFilter, IoDelta, IoStatSampler, Objset, PoolConfigInfo, PoolHealth, PropValue,
+    ShareProtocol, SpaceUsage,
+};
+
+#[test]
+fn pools() {
+    let pools = replay_root().pools().unwrap();
+    assert_eq!(pools.len(), 1);
+    assert_eq!(pools[0].name().as_str(), "tank");
+}
+
+#[test]
+fn pool_by_name() {
+    let root = replay_root();
+    assert_eq!(root.pool("tank").unwrap().unwrap().name().as_str(), "tank");
+    assert!(root.pool("nope").unwrap().is_none());
+    assert!(root.pool("tan").unwrap().is_none());
+}
+
+#[test]
+fn dataset_by_name() {
+    let (root, calls) = counted_root();
+    let pool = root.pool("tank").unwrap().unwrap();
+    let n = calls.load(Ordering::Relaxed);
+
+    assert_eq!(
+        pool.dataset("tank").unwrap().unwrap().name().as_str(),
+        "tank"
+    );
+    let fs = pool.dataset("tank/fs").unwrap().unwrap();
+    assert_eq!(fs.kind().unwrap(), ObjsetType::Zfs);
+    assert!(pool.dataset("tank/nope").unwrap().is_none());
+    assert_eq!(calls.load(Ordering::Relaxed), n + 4);
+
+    // not in this pool, or not a dataset, so not even asked for
+    assert!(pool.dataset("tankfs").unwrap().is_none());
+    assert!(pool.dataset("other/fs").unwrap().is_none());
+    assert!(pool.dataset("tank/fs@snap").unwrap().is_none());
+    assert!(pool.dataset("tank/fs#book").unwrap().is_none());
+    assert_eq!(calls.load(Ordering::Relaxed), n + 4);
+}
+
+#[test]
+fn all_snapshots() {
+    let pool = replay_root().pools().unwrap().remove(0);
+    assert_eq!(
+        pool.all_snapshots()
+            .unwrap()
+            .iter()
+            .map(|s| s.name().as_str())
+            .collect::<Vec<_>>(),
+        vec!["tank/fs@a", "tank/fs@b"]
+    );
+
+    // tank has no snapshots, so the first one needs both datasets listed (one call for tank,
+    // three for tank/fs), but the second is already there
+    let (root, calls) = counted_root();
+    let pool = root.pool("tank").unwrap().unwrap();
+    let mut snaps = pool.all_snapshots_iter();
+    let n = calls.load(Ordering::Relaxed);
+    assert_eq!(snaps.next().unwrap().unwrap().name().as_str(), "tank/fs@a");
+    assert_eq!(calls.load(Ordering::Relaxed), n + 4);
+    assert_eq!(snaps.next().unwrap().unwrap().name().as_str(), "tank/fs@b");
+    assert!(snaps.next().is_none());
+    assert_eq!(calls.load(Ordering::Relaxed), n + 4);
+}
+
+#[test]
+fn pools_iter() {
+    let (root, calls) = counted_root();
+    let pool = root
+        .pools_iter()
+        .find(|p| p.as_ref().unwrap().name().as_str() == "tank");
+    assert_eq!(pool.unwrap().unwrap().name().as_str(), "tank");
+
+    // just the pool list; nothing is fetched for the pool itself until it's used
+    assert_eq!(calls.load(Ordering::Relaxed), 1);
+}
+
+#[test]
+fn vdev_tree() {
+    let pool = replay_root().pools().unwrap().remove(0);
+    let root = pool.root_vdev().unwrap();
+    assert!(matches!(root.typ(), VdevType::Root));
+
+    let children = root.children().unwrap();
+    assert_eq!(children.len(), 1);
+    assert_eq!(pool.vdev_children().unwrap(), 1);
+    assert!(matches!(children[0].typ(), VdevType::Mirror));
+    assert_eq!(children[0].ashift(), Some(12));
+    assert_eq!(children[0].nparity(), None);
+
+    let leaves = children[0].children().unwrap();
+    assert_eq!(leaves.len(), 2);
+    assert!(leaves.iter().all(|vd| matches!(vd.typ(), VdevType::File)));
+    assert!(leaves.iter().all(|vd| vd.ashift().is_none()));
+    assert_eq!(leaves[1].stats().unwrap().checksum_errors, 1);
+}
+
+#[test]
+fn pool_vdev_tree() {
+    let (root, calls) = counted_root();
+    let pool = root.pool("tank").unwrap().unwrap();
+    let n = calls.load(Ordering::Relaxed);
+
+    let tree = pool.vdev_tree().unwrap();
+    assert!(matches!(tree.typ, VdevType::Root));
+    assert_eq!(tree.path, None);
+    assert_eq!(tree.guid, pool.root_vdev().unwrap().guid());
+    assert_eq!(tree.stats.checksum_errors, 1);
+
+    assert_eq!(tree.children.len(), 1);
+    let mirror = &tree.children[0];
+    assert!(matches!(mirror.typ, VdevType::Mirror));
+    assert_eq!(mirror.children.len(), 2);
+    for leaf in &mirror.children {
+        assert!(matches!(leaf.typ, VdevType::File));
+        assert!(leaf.path.as_deref().unwrap().starts_with('/'));
+        assert!(leaf.children.is_empty());
+    }
+    assert_eq!(mirror.children[1].stats.checksum_errors, 1);
+    assert_eq!(calls.load(Ordering::Relaxed), n + 1);
+}
+
+#[test]
+fn aux_vdevs() {
+    let root = aux_root();
+    let pool = root.pool("tank").unwrap().unwrap();
+
+    // the log is a top-level vdev, but marked as one
+    let top = pool.root_vdev().unwrap().children().unwrap();
+    assert_eq!(top.len(), 2);
+    assert!(!top[0].is_log());
+    assert_eq!(top[0].alloc_bias(), None);
+    assert!(top[1].is_log());
+    assert_eq!(top[1].alloc_bias(), Some(AllocBias::Log));
+
+    let logs = pool.logs().unwrap();
+    assert_eq!(logs.len(), 1);
+    assert_eq!(logs[0].guid(), top[1].guid());
+
+    // cache devices and spares are kept to the side
+    let cache = pool.cache_devices().unwrap();
+    assert_eq!(cache.len(), 1);
+    assert_eq!(cache[0].guid(), 0x5a5a000000000002);
+    assert!(matches!(cache[0].typ(), VdevType::File));
+    assert_eq!(
+        VdevState::from(cache[0].stats().unwrap().state),
+        VdevState::Healthy
+    );
+
+    let spares = pool.spares().unwrap();
+    assert_eq!(spares.len(), 1);
+    assert_eq!(spares[0].guid(), 0x5a5a000000000003);
+
+    let tree = pool.vdev_tree().unwrap();
+    assert_eq!(tree.children.len(), 2);
+    assert!(!tree.children[0].is_log);
+    assert!(tree.children[1].is_log);
+
+    assert!(cache[0].is_cache() && !cache[0].is_spare() && !cache[0].is_log());
+    assert!(spares[0].is_spare() && !spares[0].is_cache());
+    assert!(!top[0].is_cache() && !top[0].is_spare());
+
+    // and a pool with none has none
+    let pool = replay_root().pool("tank").unwrap().unwrap();
+    assert!(pool.logs().unwrap().is_empty());
+    assert!(pool.cache_devices().unwrap().is_empty());
+    assert!(pool.spares().unwrap().is_empty());
+}
+
+#[test]
+fn pool_status() {
+    let (root, calls) = counted_root();
+    let pool = root.pool("tank").unwrap().unwrap();
+    let n = calls.load(Ordering::Relaxed);
+
+    let st = pool.status().unwrap();
+    assert_eq!(calls.load(Ordering::Relaxed), n + 1);
+    assert_eq!(st.name, "tank");
+    assert_eq!(st.state, VdevState::Healthy);
+    assert_eq!(st.tree.name, "tank");
+    assert_eq!(st.tree.children[0].name, "mirror-0");
+    assert_eq!(st.tree.children[0].children[1].name, "/var/tmp/d2");
+    assert!(st.spares.is_empty());
+    assert!(st.cache.is_empty());
+    assert_eq!(st.error_count, 0);
+
+    let scan = st.scan.unwrap();
+    assert_eq!((scan.func, scan.state), (1, 2)); // scrub, finished
+    assert_eq!(scan.end_time - scan.start_time, 100);
+    assert_eq!(scan.examined, 1 << 20);
+}
+
+#[test]
+fn pool_status_golden() {
+    let pool = aux_root().pool("tank").unwrap().unwrap();
+    let want = std::fs::read_to_string(fixture_path("pool_status-tank_aux.txt")).unwrap();
+    assert_eq!(pool.status().unwrap().to_string(), want);
+}
+
+#[test]
+fn vdev_by_guid() {
+    let pool = replay_root().pools().unwrap().remove(0);
+    let root = pool.root_vdev().unwrap();
+    let leaf = root.children().unwrap()[0].children().unwrap().remove(1);
+
+    let vd = pool.vdev(leaf.guid()).unwrap().unwrap();
+    assert_eq!(vd.guid(), leaf.guid());
+    assert!(matches!(vd.typ(), VdevType::File));
+    assert!(matches!(
+        pool.vdev(root.guid()).unwrap().unwrap().typ(),
+        VdevType::Root
+    ));
+    assert!(pool.vdev(0x1234).unwrap().is_none());
+}
+
+#[test]
+fn datasets() {
+    let pool = replay_root().pools().unwrap().remove(0);
+    let datasets = pool.datasets().unwrap();
+    assert_eq!(
+        datasets
+            .iter()
+            .map(|ds| ds.name().as_str())
+            .collect::<Vec<_>>(),
+        vec!["tank", "tank/fs"]
+    );
+    assert_eq!(datasets[0].get_prop_u64("used").unwrap(), Some(1048576));
+    let used = CString::new("used").unwrap();
+    assert_eq!(
+        datasets[0]
+            .get_prop_u64(used.as_c_str().to_bytes())
+            .unwrap(),
+        Some(1048576)
+    );
+    assert_eq!(datasets[0].get_prop_u64(used).unwrap(), Some(1048576));
+    assert_eq!(
+        datasets[0]
+            .get_prop_string("mountpoint")
+            .unwrap()
+            .as_deref(),
+        Some("/tank")
+    );
+    assert_eq!(datasets[1].kind().unwrap(), ObjsetType::Zfs);
+}
+
+#[test]
+fn dataset_stats() {
+    let pool = replay_root().pools().unwrap().remove(0);
+    let ds = pool.datasets().unwrap().remove(1);
+    assert_eq!(
+        ds.guid().unwrap(),
+        ds.get_prop_u64("guid").unwrap().unwrap()
+    );
+    assert_eq!(ds.creation_txg().unwrap(), 1);
+    assert!(!ds.is_snapshot().unwrap());
+    assert!(ds.origin().unwrap().is_none());
+}
+
+#[test]
+fn dataset_space() {
+    let (root, calls) = counted_root();
+    let fs = root
+        .pool("tank")
+        .unwrap()
+        .unwrap()
+        .datasets()
+        .unwrap()
+        .remove(1);
+    let n = calls.load(Ordering::Relaxed);
+
+    let space = fs.space().unwrap();
+    assert_eq!(
+        space,
+        SpaceUsage {
+            available: 1000000000,
+            used: 1048576,
+            snapshots: 0,
+            dataset: 98304,
+            children: 950272,
+            refreservation: 0,
+        }
+    );
+    assert_eq!(
+        space.used,
+        space.snapshots + space.dataset + space.children + space.refreservation
+    );
+    assert_eq!(calls.load(Ordering::Relaxed), n + 1);
+}
+
+#[test]
+fn dataset_mountpoint() {
+    let pool = replay_root().pools().unwrap().remove(0);
+    let datasets = pool.datasets().unwrap();
+
+    // neither has mountpoint set, so they get the default, under /
+    assert_eq!(
+        datasets[0].mountpoint().unwrap(),
+        Some(PathBuf::from("/tank"))
+    );
+    assert_eq!(
+        datasets[1].mountpoint().unwrap(),
+        Some(PathBuf::from("/tank/fs"))
+    );
+
+    // nothing from the fixtures is actually mounted
+    #[cfg(target_os = "linux")]
+    assert!(!datasets[1].is_mounted().unwrap());
+}
+
+#[test]
+fn dataset_permissions() {
+    let pool = replay_root().pools().unwrap().remove(0);
+    let fs = pool.datasets().unwrap().remove(1);
+    let perms = fs.permissions().unwrap();
+
+    // its own, and what it gets from the pool root
+    let mut sources: Vec<_> = perms.keys().map(|k| k.to_string_lossy()).collect();
+    sources.sort();
+    assert_eq!(sources, vec!["tank", "tank/fs"]);
+
+    let names = |l: &PairList| -> Vec<String> {
+        l.keys().map(|k| k.to_string_lossy().to_string()).collect()
+    };
+
+    let local = perms.get_list("tank/fs").unwrap();
+    assert_eq!(
+        names(local.get_list("ul$1000").unwrap()),
+        vec!["create", "mount", "snapshot"]
+    );
+    assert_eq!(names(local.get_list("c-$").unwrap()), vec!["destroy"]);
+    assert_eq!(
+        names(local.get_list("s-$@backup").unwrap()),
+        vec!["hold", "send", "snapshot"]
+    );
+
+    let inherited = perms.get_list("tank").unwrap();
+    assert_eq!(
+        names(inherited.get_list("gd$100").unwrap()),
+        vec!["snapshot"]
+    );
+    assert!(inherited.get_list("ul$1000").is_none());
+}
+
+#[test]
+fn all_vdev_stats() {
+    let pool = replay_root().pools().unwrap().remove(0);
+    let stats = pool.all_vdev_stats().unwrap();
+    assert_eq!(stats.len(), 4); // root, mirror, two leaves
+
+    let root = pool.root_vdev().unwrap();
+    assert_eq!(stats[0].0, root.guid());
+    assert_eq!(stats[0].1.read_bytes(), root.stats().unwrap().read_bytes());
+
+    let leaves = root.children().unwrap().remove(0).children().unwrap();
+    assert_eq!(stats[3].0, leaves[1].guid());
+    assert_eq!(stats[3].1.checksum_errors, 1);
+}
+
+#[test]
+fn pool_health() {
+    let (root, calls) = counted_root();
+    let pool = root.pool("tank").unwrap().unwrap();
+    let n = calls.load(Ordering::Relaxed);
+
+    let health = pool.health().unwrap();
+    assert_eq!(
+        health,
+        PoolHealth {
+            state: VdevState::Healthy,
+            read_errors: 0,
+            write_errors: 0,
+            checksum_errors: 3, // one bad read, seen by the disk, the mirror and the root
+            scrubbing: false,   // finished a while ago
+            error_count: 0,
+        }
+    );
+    assert_eq!(calls.load(Ordering::Relaxed), n + 1);
+}
+
+#[test]
+fn iostat_sampler() {
+    let (root, calls) = counted_root();
+    let pool = root.pool("tank").unwrap().unwrap();
+    let vs = pool.root_vdev().unwrap().stats().unwrap();
+
+    // the first is everything so far, and the counters in the fixture never move after that
+    let mut sampler = IoStatSampler::new(&pool);
+    let n = calls.load(Ordering::Relaxed);
+    assert_eq!(
+        sampler.sample().unwrap(),
+        IoDelta {
+            read_bytes: vs.read_bytes(),
+            write_bytes: vs.write_bytes(),
+            read_ops: vs.read_ops(),
+            write_ops: vs.write_ops(),
+        }
+    );
+    assert_eq!(sampler.sample().unwrap(), IoDelta::default());
+    assert_eq!(calls.load(Ordering::Relaxed), n + 2);
+
+    // counters that went backwards were reset, not negative
+    let old = VdevStats {
+        ops: [0, 10, 20, 0, 0, 0],
+        bytes: [0, 1000, 2000, 0, 0, 0],
+        ..Default::default()
+    };
+    let new = VdevStats {
+        ops: [0, 15, 5, 0, 0, 0],
+        bytes: [0, 1500, 500, 0, 0, 0],
+        ..Default::default()
+    };
+    assert_eq!(
+        IoDelta::between(&old, &new),
+        IoDelta {
+            read_bytes: 500,
+            write_bytes: 0,
+            read_ops: 5,
+            write_ops: 0,
+        }
+    );
+}
+
+#[test]
+fn pool_config_info() {
+    let pool = replay_root().pools().unwrap().remove(0);
+    assert_eq!(
+        pool.config_info().unwrap(),
+        PoolConfigInfo {
+            name: "tank".into(),
+            guid: 0x1f2e3d4c5b6a7988,
+            version: 5000,
+            txg: 42,
+            hostid: Some(0xc0ffee),
+            hostname: Some("testhost".into()),
+            comment: None,
+        }
+    );
+}
+
+#[test]
+fn pool_refresh() {
+    let (root, calls) = counted_root();
+    let pool = root.pools().unwrap().remove(0);
+    let base = calls.load(Ordering::Relaxed);
+
+    // stats are fetched once and then reused
+    let root = pool.root_vdev().unwrap();
+    let _ = pool.all_vdev_stats().unwrap();
+    assert_eq!(calls.load(Ordering::Relaxed), base + 1);
+
+    // vdevs carry their own part of the tree, so walking it costs nothing
+    for vd in root.children().unwrap() {
+        let _ = vd.stats().unwrap();
+        let _ = vd.children().unwrap();
+    }
+    assert_eq!(calls.load(Ordering::Relaxed), base + 1);
+
+    // until asked to fetch again
+    pool.refresh();
+    let _ = pool.all_vdev_stats().unwrap();
+    assert_eq!(calls.load(Ordering::Relaxed), base + 2);
+}
+
+#[test]
+fn dataset_refresh() {
+    let (root, calls) = counted_root();
+    let fs = root
+        .pool("tank")
+        .unwrap()
+        .unwrap()
+        .datasets()
+        .unwrap()
+        .remove(1);
+    let base = calls.load(Ordering::Relaxed);
+
+    // stats and properties are fetched once and then reused
+    let _ = fs.guid().unwrap();
+    let _ = fs.kind().unwrap();
+    let _ = fs.get_prop_u64("used").unwrap();
+    let _ = fs.get_prop_string("compression").unwrap();
+    let _ = fs.space().unwrap();
+    let _ = fs.mountpoint().unwrap();
+    assert_eq!(calls.load(Ordering::Relaxed), base + 1);
+
+    // until asked to fetch again
+    fs.refresh();
+    let _ = fs.creation_txg().unwrap();
+    assert_eq!(calls.load(Ordering::Relaxed), base + 2);
+}
+
+#[test]
+fn pool_reguid() {
+    let pool = replay_root().pools().unwrap().remove(0);
+    let guid = pool.guid().unwrap();
+    pool.reguid().unwrap();
+    assert_ne!(pool.guid().unwrap(), guid);
+}
+
+#[test]
+fn pool_sync() {
+    let (root, ops) = recorded_root();
+    let pool = root.pools().unwrap().remove(0);
+    pool.sync().unwrap();
+
+    let ops = ops.lock().unwrap();
+    assert_eq!(ops.len(), 1);
+    assert_eq!(ops[0].req, ZFS_IOC_POOL_SYNC);
+    let args = ops[0].src.as_ref().unwrap();
+    assert!(matches!(
+        args.get("force").unwrap().value(),
+        PairValue::BooleanValue(false)
+    ));
+}
+
+#[test]
+fn vdev_attach_detach() {
+    let (root, ops) = recorded_root();
+    let pool = root.pools().unwrap().remove(0);
+    let mirror = pool.root_vdev().unwrap().children().unwrap().remove(0);
+    let leaf = mirror.children().unwrap().remove(0);
+
+    let mut new = PairList::new();
+    new.add("type", PairValue::String(CString::new("file").unwrap()));
+    new.add(
+        "path",
+        PairValue::String(CString::new("/var/tmp/d3").unwrap()),
+    );
+    leaf.attach(&new).unwrap();
+    leaf.replace(&new).unwrap();
+    leaf.detach().unwrap();
+
+    let ops = ops.lock().unwrap();
+    assert_eq!(ops.len(), 3);
+
+    // the new leaf arrives wrapped in a root vdev, attached to the existing one by guid
+    assert_eq!(ops[0].req, ZFS_IOC_VDEV_ATTACH);
+    assert_eq!(ops[0].name, "tank");
+    assert_eq!(ops[0].guid, leaf.guid());
+    assert_eq!(ops[0].cookie, 0);
+    let conf = ops[0].conf.as_ref().unwrap();
+    assert_eq!(get_str(conf, "type").as_deref(), Some("root"));
+    let children = conf.get_list_slice("children").unwrap();
+    assert_eq!(
+        get_str(&children[0], "path").as_deref(),
+        Some("/var/tmp/d3")
+    );
+
+    assert_eq!(ops[1].req, ZFS_IOC_VDEV_ATTACH);
+    assert_eq!(ops[1].cookie, 1);
+
+    assert_eq!(ops[2].req, ZFS_IOC_VDEV_DETACH);
+    assert_eq!(ops[2].guid, leaf.guid());
+    assert!(ops[2].conf.is_none());
+}
+
+#[test]
+fn vdev_add_remove() {
+    let (root, ops) = recorded_root();
+    let pool = root.pools().unwrap().remove(0);
+
+    let mut cache = PairList::new();
+    cache.add("type", PairValue::String(CString::new("disk").unwrap()));
+    cache.add(
+        "path",
+        PairValue::String(CString::new("/dev/nvme0n1").unwrap()),
+    );
+    let mut config = PairList::new();
+    config.add("type", PairValue::String(CString::new("root").unwrap()));
+    config.add("l2cache", PairValue::ListArray(vec![cache]));
+    pool.add_vdev(&config).unwrap();
+
+    let mirror = pool.root_vdev().unwrap().children().unwrap().remove(0);
+    mirror.remove().unwrap();
+
+    let ops = ops.lock().unwrap();
+    assert_eq!(ops[0].req, ZFS_IOC_VDEV_ADD);
+    assert_eq!(ops[0].flags, 1); // check ashift
+    let conf = ops[0].conf.as_ref().unwrap();
+    let l2 = conf.get_list_slice("l2cache").unwrap();
+    assert_eq!(get_str(&l2[0], "path").as_deref(), Some("/dev/nvme0n1"));
+
+    assert_eq!(ops[1].req, ZFS_IOC_VDEV_REMOVE);
+    assert_eq!(ops[1].guid, mirror.guid());
+}
+
+#[test]
+fn vdev_set_state() {
+    let (root, ops) = recorded_root();
+    let pool = root.pools().unwrap().remove(0);
+    let mirror = pool.root_vdev().unwrap().children().unwrap().remove(0);
+    let leaf = mirror.children().unwrap().remove(1);
+
+    // online says what it did in the cookie. the rest don't, so the state comes from the
+    // pool's stats, which the replay kernel never changes: the leaf is still healthy there
+    assert_eq!(leaf.offline().unwrap(), VdevState::Healthy);
+    assert_eq!(leaf.online().unwrap(), VdevState::Healthy);
+    assert_eq!(leaf.fault().unwrap(), VdevState::Healthy);
+
+    let ops = ops.lock().unwrap();
+    assert!(ops
+        .iter()
+        .all(|op| op.req == ZFS_IOC_VDEV_SET_STATE && op.guid == leaf.guid()));
+    assert_eq!(
+        ops.iter().map(|op| (op.cookie, op.obj)).collect::<Vec<_>>(),
+        vec![(2, 0), (7, 0), (5, 14)]
+    );
+}
+
+#[test]
+fn pool_features() {
+    let pool = replay_root().pools().unwrap().remove(0);
+    let features = pool.features().unwrap();
+    let state = |name: &str| {
+        features
+            .iter()
+            .find(|(f, _)| f == name)
+            .map(|(_, s)| *s)
+            .unwrap()
+    };
+    assert_eq!(state("com.delphix:hole_birth"), FeatureState::Active);
+    assert_eq!(state("com.delphix:bookmarks"), FeatureState::Enabled);
+    assert_eq!(state("com.delphix:async_destroy"), FeatureState::Disabled);
+
+    // not one we know about, but the pool has it, so it's listed
+    assert_eq!(state("org.openzfs:zstd_compress"), FeatureState::Enabled);
+}
+
+#[test]
+fn pool_upgrade() {
+    let (root, ops) = recorded_root();
+    let pool = root.pools().unwrap().remove(0);
+    pool.upgrade().unwrap();
+
+    // already on the feature flags version, so only the missing features are enabled, one at
+    // a time. this kernel doesn't know block_cloning, which is passed over
+    let ops = ops.lock().unwrap();
+    assert!(ops.len() > 1);
+    assert!(ops.iter().all(
+        |op| op.req == ZFS_IOC_POOL_SET_PROPS && op.src.as_ref().unwrap().pairs().count() == 1
+    ));
+    let enabled = |f: &str| {
+        ops.iter()
+            .any(|op| op.src.as_ref().unwrap().get_u64(f) == Some(0))
+    };
+    assert!(enabled("feature@async_destroy"));
+    assert!(enabled("feature@redaction_list_spill"));
+    assert!(!enabled("feature@block_cloning"));
+    assert!(!enabled("feature@hole_birth"));
+    assert!(!enabled("feature@bookmarks"));
+}
+
+#[test]
+fn dataset_toggles() {
+    let (root, ops) = recorded_root();
+    let pool = root.pools().unwrap().remove(0);
+    let fs = pool.dataset("tank/fs").unwrap().unwrap();
+
+    // readonly and atime are set; canmount isn't, so it's the default
+    assert!(fs.readonly().unwrap());
+    assert!(!fs.atime().unwrap());
+    assert_eq!(fs.canmount().unwrap(), CanMount::On);
+
+    fs.set_readonly(false).unwrap();
+    fs.set_atime(true).unwrap();
+    fs.set_canmount(CanMount::NoAuto).unwrap();
+    fs.set_prop_string("org.example:note", "hello").unwrap();
+
+    let ops = ops.lock().unwrap();
+    assert_eq!(ops.len(), 4);
+    assert!(ops.iter().all(|op| op.req == ZFS_IOC_SET_PROP));
+    assert!(ops.iter().all(|op| op.name == "tank/fs"));
+    let props: Vec<_> = ops.iter().map(|op| op.src.as_ref().unwrap()).collect();
+    assert_eq!(props[0].get_u64("readonly"), Some(0));
+    assert_eq!(props[1].get_u64("atime"), Some(1));
+    assert_eq!(props[2].get_u64("canmount"), Some(2));
+    assert_eq!(get_str(props[3], "org.example:note"), Some("hello".into()));
+}
+
+#[test]
+fn snapshot_diff() {
+    let pool = replay_root().pools().unwrap().remove(0);
+    let fs = pool.datasets().unwrap().remove(1);
+    let snaps = fs.snapshots().unwrap();
+    assert_eq!(
+        snaps.iter().map(|s| s.name().as_str()).collect::<Vec<_>>(),
+        vec!["tank/fs@a", "tank/fs@b"]
+    );
+
+    let diff = snaps[1].diff_from(&snaps[0]).unwrap();
+    assert_eq!(
+        diff,
+        vec![
+            DiffEntry {
+                kind: DiffKind::InUse,
+                first: 1,
+                last: 5
+            },
+            DiffEntry {
+                kind: DiffKind::Free,
+                first: 8,
+                last: 8
+            },
+        ]
+    );
+
+    // the other way round isn't something the replay kernel knows about
+    assert!(snaps[0].diff_from(&snaps[1]).is_err());
+}
+
+#[test]
+fn inject_builder() {
+    let rec = ZInjectBuilder::new()
+        .device(0x1234)
+        .io_type(IoType::Write)
+        .frequency(25.0)
+        .build()
+        .unwrap();
+    assert_eq!(rec.cmd, 2);
+    assert_eq!(rec.guid, 0x1234);
+    assert_eq!(rec.iotype, 2);
+    assert_eq!(rec.error, 6);
+    assert_eq!(rec.freq, u32::MAX / 4); // ZI_PERCENTAGE_MAX / 4
+
+    // the smallest fraction there is, and not a legacy whole percent
+    let rec = ZInjectBuilder::new()
+        .device(0x1234)
+        .frequency(0.00000001)
+        .build()
+        .unwrap();
+    assert_eq!(rec.freq, 4294); // ZI_PERCENTAGE_MIN
+
+    let rec = ZInjectBuilder::new()
+        .object(54, 128, 0)
+        .range(10, 20)
+        .build()
+        .unwrap();
+    assert_eq!(rec.cmd, 1);
+    assert_eq!((rec.objset, rec.object, rec.level), (54, 128, 0));
+    assert_eq!((rec.start, rec.end), (10, 20));
+    assert_eq!(rec.error, 5);
+    assert_eq!(rec.freq, 0);
+
+    assert!(ZInjectBuilder::new().build().is_err());
+    assert!(ZInjectBuilder::new()
+        .device(1)
+        .object(1, 1, 0)
+        .build()
+        .is_err());
+    assert!(ZInjectBuilder::new().device(1).range(0, 1).build().is_err());
+    assert!(ZInjectBuilder::new()
+        .object(1, 1, 0)
+        .io_type(IoType::Read)
+        .build()
+        .is_err());
+    assert!(ZInjectBuilder::new()
+        .object(1, 1, 0)
+        .range(2, 1)
+        .build()
+        .is_err());
+    assert!(ZInjectBuilder::new()
+        .device(1)
+        .frequency(0.0)
+        .build()
+        .is_err());
+    assert!(ZInjectBuilder::new()
+        .device(1)
+        .frequency(101.0)
+        .build()
+        .is_err());
+    assert!(ZInjectBuilder::new().device(1).error(0).build().is_err());
+}
+
+#[test]
+fn inject_fault() {
+    let (root, ops) = recorded_root();
+    let pool = root.pools().unwrap().remove(0);
+    let rec = ZInjectBuilder::new().device(0x1234).build().unwrap();
+    let id = pool.inject_fault(&rec, true).unwrap();
+    assert_eq!(id, 1);
+    root.clear_fault(id).unwrap();
+
+    let ops = ops.lock().unwrap();
+    assert_eq!(ops.len(), 2);
+    assert_eq!(ops[0].req, ZFS_IOC_INJECT_FAULT);
+    assert_eq!(ops[0].name, "tank");
+    assert_eq!(ops[0].guid, 0x2); // ZINJECT_FLUSH_ARC
+    assert_eq!(ops[0].inject, rec);
+    assert_eq!(ops[1].req, ZFS_IOC_CLEAR_FAULT);
+    assert_eq!(ops[1].guid, 1);
+    drop(ops);
+
+    // the error names the fault
+    let err = root.clear_fault(7).err().unwrap();
+    assert_eq!(err.to_string(), "cannot clear '7': fault does not exist");
+}
+
+#[test]
+fn fault_list() {
+    let faults = replay_root().faults().unwrap();
+    assert_eq!(faults.len(), 1);
+    assert_eq!(faults[0].id, 1);
+    assert_eq!(faults[0].pool.to_string(), "tank");
+    assert_eq!(faults[0].record.cmd, 2);
+    assert_eq!(faults[0].record.error, 6);
+}
+
+#[test]
+fn dataset_receive() {
+    let (root, ops) = recorded_root();
+    let pool = root.pools().unwrap().remove(0);
+    let fs = pool.dataset("tank/fs").unwrap().unwrap();
+
+    // not a send stream, so the kernel never sees it
+    let junk = std::io::Cursor::new(vec![0u8; 1024]);
+    assert!(fs.receive_from(junk, false, false).is_err());
+
+    // more than a pipe holds, so it has to be fed in while it's being read
+    let stream = send_stream("other/fs@c", 1 << 20);
+    let begin = stream[..ioc::BEGIN_RECORD_SIZE].to_vec();
+    let snap = fs
+        .receive_from(std::io::Cursor::new(stream), true, false)
+        .unwrap();
+    assert_eq!(snap.name().as_str(), "tank/fs@c");
+
+    let ops = ops.lock().unwrap();
+    assert_eq!(ops.len(), 1);
+    assert_eq!(ops[0].req, ZFS_IOC_RECV_NEW);
+    assert_eq!(ops[0].name, "tank/fs");
+    let args = ops[0].src.as_ref().unwrap();
+    assert_eq!(get_str(args, "snapname").as_deref(), Some("tank/fs@c"));
+    assert_eq!(
+        args.get("begin_record").unwrap().value(),
+        &PairValue::ByteArray(begin)
+    );
+    assert!(matches!(
+        args.get("force").unwrap().value(),
+        PairValue::Boolean
+    ));
+    assert!(args.get("resumable").is_none());
+
+    assert_eq!(fs.receive_resume_token().unwrap(), None);
+}
+
+#[test]
+fn open_dev() {
+    let e = zfs::open_dev("/nonexistent/zfs").err().unwrap();
+    assert_eq!(
+        e.downcast::<std::io::Error>().unwrap().kind(),
+        std::io::ErrorKind::NotFound
+    );
+
+    // opens fine, but it's not a zfs device, so the first ioctl fails
+    let root = zfs::Root::open_dev("/dev/null").unwrap();
+    assert!(root.pools().is_err());
+}
+
+#[test]
+fn exists() {
+    let (root, fail) = failing_root();
+    let pool = root.pool("tank").unwrap().unwrap();
+    let fs = pool.dataset("tank/fs").unwrap().unwrap();
+    assert!(pool.exists().unwrap());
+    assert!(fs.exists().unwrap());
+
+    fail.store(2, Ordering::Relaxed); // ENOENT
+    assert!(!pool.exists().unwrap());
+    assert!(!fs.exists().unwrap());
+
+    // can't see it, but that doesn't mean it's not there
+    fail.store(1, Ordering::Relaxed); // EPERM
+    for e in [pool.exists().unwrap_err(), fs.exists().unwrap_err()] {
+        assert_eq!(e.downcast::<VeneerError>().unwrap().raw_os_error(), Some(1));
+    }
+}
+
+#[test]
+fn error_messages() {
+    let (root, fail) = failing_root();
+    let pool = root.pool("tank").unwrap().unwrap();
+    let fs = pool.dataset("tank/fs").unwrap().unwrap();
+
+    fail.store(2, Ordering::Relaxed); // ENOENT
+    let e = fs.get_prop_u64("used").unwrap_err();
+    assert_eq!(
+        e.to_string(),
+        "cannot open 'tank/fs': dataset does not exist"
+    );
+    let e = e.downcast::<VeneerError>().unwrap();
+    assert_eq!(e.name(), "tank/fs");
+    assert_eq!(e.kind(), std::io::ErrorKind::NotFound);
+    let src = e.source().unwrap().downcast_ref::<std::io::Error>();
+    assert_eq!(src.unwrap().raw_os_error(), Some(2));
+
+    fail.store(1, Ordering::Relaxed); // EPERM
+    assert_eq!(
+        pool.sync().unwrap_err().to_string(),
+        "cannot sync 'tank': permission denied"
+    );
+
+    fail.store(17, Ordering::Relaxed); // EEXIST
+    assert_eq!(
+        pool.root_vdev().err().unwrap().to_string(),
+        "cannot open 'tank': pool already exists"
+    );
+
+    // anything else says what the system says
+    fail.store(5, Ordering::Relaxed); // EIO
+    let e = fs.snapshots().err().unwrap().to_string();
+    assert!(e.starts_with("cannot list snapshots of 'tank/fs': "));
+    assert!(e.ends_with("(os error 5)"));
+
+    // and errors that aren't about a target are left alone
+    fail.store(2, Ordering::Relaxed);
+    let e = root.pools().err().unwrap();
+    assert!(e.downcast_ref::<VeneerError>().is_none());
+    assert!(e.downcast_ref::<std::io::Error>().is_some());
+}
+
+#[test]
+fn dataset_all_props() {
+    let pool = replay_root().pools().unwrap().remove(0);
+    let props = pool
+        .dataset("tank/fs")
+        .unwrap()
+        .unwrap()
+        .all_props()
+        .unwrap();
+    assert_eq!(props.len(), 18);
+    let get = |name: &str| props.iter().find(|(n, _, _)| n == name).cloned().unwrap();
+
+    assert_eq!(
+        get("type"),
+        ("type".into(), PropValue::Number(2), PropSource::None)
+    );
+    assert_eq!(
+        get("compression"),
+        (
+            "compression".into(),
+            PropValue::Number(15),
+            PropSource::Inherited("tank".into())
+        )
+    );
+    assert_eq!(get("readonly").2, PropSource::Local);
+    assert_eq!(get("atime").2, PropSource::Received);
+
+    let props = pool.dataset("tank").unwrap().unwrap().all_props().unwrap();
+    assert!(props.contains(&(
+        "com.example:backup".into(),
+        PropValue::String("yes".into()),
+        PropSource::Local
+    )));
+}
+
+// a file standing in for a device, with a label for the given top-level vdev. the front two
+// copies are written with txg, the back two with back_txg, as if the last sync didn't reach
+// the front
+fn label_file(
+    path: &std::path::Path,
+    pool: &str,
+    pool_guid: u64,
+    id: u64,
+    txg: u64,
+    back_txg: u64,
+    holes: &[u64],
+) {
+    use std::os::unix::fs::FileExt;
+    use veneer::zfs::import::{LABEL_NVLIST_OFFSET, LABEL_SIZE};
+
+    let leaf = pool_guid + 0x100 + id;
+    let label = |txg: u64| {
+        let mut vt = PairList::new();
+        vt.add("type", PairValue::String(CString::new("file").unwrap()));
+        vt.add("id", PairValue::UInt64(id));
+        vt.add("guid", PairValue::UInt64(leaf));
+        vt.add(
+            "path",
+            PairValue::String(CString::new("/old/place").unwrap()),
+        );
+        let mut l = PairList::new();
+        l.add("version", PairValue::UInt64(5000));
+        l.add("name", PairValue::String(CString::new(pool).unwrap()));
+        l.add("state", PairValue::UInt64(1)); // POOL_STATE_EXPORTED
+        l.add("txg", PairValue::UInt64(txg));
+        l.add("pool_guid", PairValue::UInt64(pool_guid));
+        let host = if txg >= 20 { "new" } else { "old" };
+        l.add("hostname", PairValue::String(CString::new(host).unwrap()));
+        l.add("top_guid", PairValue::UInt64(leaf));
+        l.add("guid", PairValue::UInt64(leaf));
+        l.add("vdev_children", PairValue::UInt64(2));
+        if !holes.is_empty() {
+            l.add("hole_array", PairValue::UInt64Array(holes.to_vec()));
+        }
+        l.add("vdev_tree", PairValue::List(vt));
+        veneer::nvpair::pack(&l).unwrap()
+    };
+
+    let f = std::fs::File::create(path).unwrap();
+    f.set_len(16 * LABEL_SIZE).unwrap();
+    for (n, txg) in [txg, txg, back_txg, back_txg].into_iter().enumerate() {
+        let at = if n < 2 { n as u64 } else { 14 + n as u64 } * LABEL_SIZE;
+        f.write_all_at(&label(txg), at + LABEL_NVLIST_OFFSET)
+            .unwrap();
+    }
+}
+
+#[test]
+fn importable_pools() {
+    let dir = std::env::temp_dir().join(format!("veneer-import-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    label_file(&dir.join("a0"), "alpha", 0xa000, 0, 10, 20, &[]);
+    label_file(&dir.join("a1"), "alpha", 0xa000, 1, 15, 15, &[]);
+    label_file(&dir.join("b1"), "beta", 0xb000, 1, 5, 5, &[]);
+    // gamma once had a log at 0, which was removed
+    label_file(&dir.join("g1"), "gamma", 0xc000, 1, 5, 5, &[0]);
+    // a big enough file with no labels, and one too small to have any
+    std::fs::File::create(dir.join("junk"))
+        .unwrap()
+        .set_len(4 << 20)
+        .unwrap();
+    std::fs::write(dir.join("small"), b"hello").unwrap();
+
+    let pools = replay_root().importable_pools(&[&dir]);
+    let (root, fail) = failing_root();
+    fail.store(5, Ordering::Relaxed); // EIO
+    let failed = root.importable_pools(&[&dir]);
+    std::fs::remove_dir_all(&dir).unwrap();
+    let pools = pools.unwrap();
+
+    assert_eq!(pools.len(), 3);
+    let (alpha, beta, gamma) = (&pools[0], &pools[1], &pools[2]);
+
+    assert_eq!(alpha.name, "alpha");
+    assert_eq!(alpha.guid, 0xa000);
+    assert_eq!(alpha.state, PoolState::Exported);
+    assert_eq!(alpha.txg, 20);
+    assert_eq!(alpha.hostname.as_deref(), Some("new"));
+    assert_eq!(alpha.devices, vec![dir.join("a0"), dir.join("a1")]);
+    assert_eq!(alpha.vdev_children, 2);
+    assert!(alpha.missing.is_empty());
+    assert_eq!(alpha.health, Some(VdevState::Healthy));
+
+    // each leaf's path is where it was found, not where the label says it was
+    let tops = alpha
+        .config
+        .get_list("vdev_tree")
+        .unwrap()
+        .get_list_slice("children")
+        .unwrap();
+    assert_eq!(
+        get_str(&tops[1], "path"),
+        Some(dir.join("a1").to_string_lossy().into_owned())
+    );
+
+    assert_eq!(beta.name, "beta");
+    assert_eq!(beta.devices, vec![dir.join("b1")]);
+    assert_eq!(beta.missing, vec![0]);
+    assert_eq!(beta.health, Some(VdevState::CantOpen));
+
+    // a hole isn't missing; it's there in the config as a hole
+    assert!(gamma.missing.is_empty());
+    let tops = gamma
+        .config
+        .get_list("vdev_tree")
+        .unwrap()
+        .get_list_slice("children")
+        .unwrap();
+    assert_eq!(get_str(&tops[0], "type").as_deref(), Some("hole"));
+    assert_eq!(gamma.config.get_u64_slice("hole_array"), Some(&[0][..]));
+
+    // the kernel not answering doesn't hide the pools, it just leaves their health unknown
+    let failed = failed.unwrap();
+    assert_eq!(failed.len(), 3);
+    assert!(failed.iter().all(|p| p.health.is_none()));
+}
+
+#[test]
+fn importable_pools_symlinks() {
+    use std::os::unix::fs::symlink;
+
+    let root = replay_root();
+    let tank_guid = root.pool("tank").unwrap().unwrap().guid().unwrap();
+
+    // devices in one place, and only links to them in the directory scanned, like by-id
+    let base = std::env::temp_dir().join(format!("veneer-import-links-{}", std::process::id()));
+    let (dev, byid) = (base.join("dev"), base.join("by-id"));
+    std::fs::create_dir_all(&dev).unwrap();
+    std::fs::create_dir_all(&byid).unwrap();
+    label_file(&dev.join("a0"), "alpha", 0xa000, 0, 10, 10, &[]);
+    label_file(&dev.join("a1"), "alpha", 0xa000, 1, 10, 10, &[]);
+    label_file(&dev.join("t0"), "tank", tank_guid, 0, 10, 10, &[]);
+    symlink(dev.join("a0"), byid.join("disk-a0")).unwrap();
+    symlink(dev.join("a0"), byid.join("wwn-a0")).unwrap(); // the same device again
+    symlink(dev.join("a1"), byid.join("disk-a1")).unwrap();
+    symlink(dev.join("t0"), byid.join("disk-t0")).unwrap(); // tank is already imported
+    symlink(dev.join("gone"), byid.join("dangling")).unwrap();
+
+    let pools = root.importable_pools(&[&byid]);
+    std::fs::remove_dir_all(&base).unwrap();
+    let pools = pools.unwrap();
+
+    assert_eq!(pools.len(), 1);
+    assert_eq!(pools[0].name, "alpha");
+    assert_eq!(
+        pools[0].devices,
+        vec![byid.join("disk-a0"), byid.join("disk-a1")]
+    );
+    assert!(pools[0].missing.is_empty());
+}
+
+#[test]
+fn user_props() {
+    let pool = replay_root().pool("tank").unwrap().unwrap();
+    let props = pool.dataset("tank").unwrap().unwrap().user_props().unwrap();
+    assert_eq!(props.len(), 1);
+    assert_eq!(props["com.example:backup"], "yes");
+
+    // native properties only
+    let props = pool
+        .dataset("tank/fs")
+        .unwrap()
+        .unwrap()
+        .user_props()
+        .unwrap();
+    assert!(props.is_empty());
+}
+
+#[test]
+fn create_snapshots() {
+    let (root, ops) = recorded_root();
+    let pool = root.pool("tank").unwrap().unwrap();
+    let snap_names = |snaps: Vec<zfs::Snapshot>| -> Vec<String> {
+        snaps.iter().map(|s| s.name().to_string()).collect()
+    };
+    let snaps_asked = |op: &common::Op| -> Vec<String> {
+        let snaps = op.src.as_ref().unwrap().get_list("snaps").unwrap().clone();
+        snaps
+            .keys()
+            .map(|k| k.to_string_lossy().into_owned())
+            .collect()
+    };
+
+    // just the one
+    let fs = pool.dataset("tank/fs").unwrap().unwrap();
+    assert_eq!(snap_names(fs.snapshot("c", false).unwrap()), ["tank/fs@c"]);
+
+    // the whole tree, in one command to the pool
+    let tank = pool.dataset("tank").unwrap().unwrap();
+    assert_eq!(
+        snap_names(tank.snapshot("c", true).unwrap()),
+        ["tank@c", "tank/fs@c"]
+    );
+
+    let ops = ops.lock().unwrap();
+    assert_eq!(ops.len(), 2);
+    assert!(ops
+        .iter()
+        .all(|op| op.req == ZFS_IOC_SNAPSHOT && op.name == "tank"));
+    assert_eq!(snaps_asked(&ops[0]), ["tank/fs@c"]);
+    assert_eq!(snaps_asked(&ops[1]), ["tank@c", "tank/fs@c"]);
+    drop(ops);
+
+    // tank/fs@a is already there, so neither is taken; tank@a is the one that would have been
+    let err = tank.snapshot("a", true).err().unwrap();
+    let pf = err.downcast_ref::<ioc::PartialFailure>().unwrap();
+    assert_eq!(pf.succeeded, [CString::new("tank@a").unwrap()]);
+    assert_eq!(
+        pf.errno(&CString::new("tank/fs@a").unwrap()),
+        Some(17) // EEXIST
+    );
+}
+
+#[test]
+fn dataset_navigation() {
+    let (root, calls) = counted_root();
+    let pool = root.pool("tank").unwrap().unwrap();
+    let fs = pool.dataset("tank/fs").unwrap().unwrap();
+    let before = calls.load(Ordering::Relaxed);
+
+    let parent = fs.parent().unwrap();
+    assert_eq!(parent.name().as_str(), "tank");
+    assert!(parent.parent().is_none());
+    assert!(fs.pool() == pool);
+    assert!(parent.pool() == pool);
+
+    // just names, until they're used
+    assert_eq!(calls.load(Ordering::Relaxed), before);
+    assert_eq!(
+        parent.guid().unwrap(),
+        pool.dataset("tank").unwrap().unwrap().guid().unwrap()
+    );
+}
+
+#[test]
+fn creation_time() {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    let pool = replay_root().pool("tank").unwrap().unwrap();
+    let fs = pool.dataset("tank/fs").unwrap().unwrap();
+    let secs = fs.get_prop_u64("creation").unwrap().unwrap();
+    assert!(secs > 0);
+    assert_eq!(
+        fs.creation_time().unwrap(),
+        UNIX_EPOCH + Duration::from_secs(secs)
+    );
+
+    let snap = &fs.snapshots().unwrap()[0];
+    assert_eq!(
+        snap.creation_time().unwrap(),
+        UNIX_EPOCH + Duration::from_secs(secs)
+    );
+}
+
+#[test]
+fn userspace() {
+    let pool = replay_root().pool("tank").unwrap().unwrap();
+    let fs = pool.dataset("tank/fs").unwrap().unwrap();
+    assert_eq!(fs.user_used(1000).unwrap(), 3 << 20);
+    assert_eq!(fs.group_used(100).unwrap(), 5 << 20);
+
+    // the ids aren't mixed up between users and groups, and strangers have nothing
+    assert_eq!(fs.user_used(100).unwrap(), 0);
+    assert_eq!(fs.group_used(1000).unwrap(), 0);
+
+    let err = pool
+        .dataset("tank")
+        .unwrap()
+        .unwrap()
+        .user_used(0)
+        .err()
+        .unwrap();
+    assert_eq!(
+        err.to_string(),
+        "cannot get space used in 'tank': dataset does not exist"
+    );
+}
+
+#[test]
+fn made_up_vdev_tree() {
+    // a raidz2 of three disks, a mirrored special vdev, and the hole left by a removed
+    // vdev, which no fixture has
+    let s = |v: &str| PairValue::String(CString::new(v).unwrap());
+    let raidz = PairList::from_pairs([
+        ("type", s("raidz")),
+        ("guid", PairValue::UInt64(10)),
+        ("nparity", PairValue::UInt64(2)),
+        ("ashift", PairValue::UInt64(12)),
+        (
+            "children",
+            PairValue::ListArray(vec![
+                disk_config(11, "/dev/a"),
+                disk_config(12, "/dev/b"),
+                disk_config(13, "/dev/c"),
+            ]),
+        ),
+    ]);
+    let special = PairList::from_pairs([
+        ("type", s("mirror")),
+        ("guid", PairValue::UInt64(20)),
+        ("alloc_bias", s("special")),
+        (
+            "children",
+            PairValue::ListArray(vec![disk_config(21, "/dev/d"), disk_config(22, "/dev/e")]),
+        ),
+    ]);
+    let hole = PairList::from_pairs([("type", s("hole")), ("guid", PairValue::UInt64(30))]);
+    let root = PairList::from_pairs([
+        ("type", s("root")),
+        ("guid", PairValue::UInt64(1)),
+        ("children", PairValue::ListArray(vec![raidz, special, hole])),
+    ]);
+    let config = pool_config("made", root);
+
+    let pool = one_pool_root("made", config).pool("made").unwrap().unwrap();
+    let tops = pool.root_vdev().unwrap().children().unwrap();
+    assert_eq!(tops.len(), 3);
+    assert!(matches!(tops[0].typ(), VdevType::Raidz));
+    assert_eq!(tops[0].nparity(), Some(2));
+    assert_eq!(tops[0].ashift(), Some(12));
+    assert_eq!(tops[0].children().unwrap().len(), 3);
+    assert_eq!(tops[1].alloc_bias(), Some(AllocBias::Special));
+    assert!(!tops[1].is_log());
+
+    let leaf = pool.vdev(22).unwrap().unwrap();
+    assert!(matches!(leaf.typ(), VdevType::Disk));
+    assert!(pool.vdev(99).unwrap().is_none());
+
+    // zpool status puts the special vdev under its own heading, and leaves the hole out
+    let status = pool.status().unwrap().to_string();
+    let names: Vec<_> = status
+        .lines()
+        .skip_while(|l| !l.contains("NAME"))
+        .skip(1)
+        .take_while(|l| !l.is_empty())
+        .filter_map(|l| l.split_whitespace().next())
+        .collect();
+    assert_eq!(
+        names,
+        ["made", "raidz2-0", "a", "b", "c", "special", "mirror-0", "d", "e"]
+    );
+}
+
+#[test]
+fn share() {
+    let (root, ops) = recorded_root();
+    let pool = root.pool("tank").unwrap().unwrap();
+    let fs = pool.dataset("tank/fs").unwrap().unwrap();
+    fs.share(ShareProtocol::Nfs).unwrap();
+    fs.unshare(ShareProtocol::Nfs).unwrap();
+    fs.share(ShareProtocol::Smb).unwrap();
+    fs.unshare(ShareProtocol::Smb).unwrap();
+
+    let ops = ops.lock().unwrap();
+    assert!(ops
+        .iter()
+        .all(|op| op.req == ZFS_IOC_SHARE && op.name == "tank/fs"));
+    let types: Vec<_> = ops.iter().map(|op| op.sharetype).collect();
+    assert_eq!(types, [0, 1, 2, 3]); // ZFS_SHARE_NFS, ZFS_UNSHARE_NFS, _SMB, _SMB
+
+    // the mountpoint, and the options from sharenfs or sharesmb
+    let mp = fs.mountpoint().unwrap().unwrap();
+    let nfs = fs.get_prop_string("sharenfs").unwrap().unwrap_or_default();
+    assert_eq!(ops[0].share, (mp.to_string_lossy().into(), nfs));
+
+    let err = pool
+        .dataset("tank")
+        .unwrap()
+        .unwrap()
+        .share(ShareProtocol::Nfs)
+        .err()
+        .unwrap();
+    let err = err.downcast::<VeneerError>().unwrap();
+    assert_eq!(err.raw_os_error(), Some(38)); // ENOSYS
+    assert!(err.to_string().starts_with("cannot share 'tank': "));
+}
+
+#[test]
+fn remap() {
+    let (root, ops) = recorded_root();
+    let pool = root.pool("tank").unwrap().unwrap();
+    pool.dataset("tank/fs").unwrap().unwrap().remap().unwrap();
+
+    let ops = ops.lock().unwrap();
+    assert_eq!(ops.len(), 1);
+    assert_eq!(ops[0].req, ZFS_IOC_REMAP);
+    assert_eq!(ops[0].name, "tank/fs");
+    assert_eq!(ops[0].src.as_ref().unwrap().keys().count(), 0);
+}
+
+#[test]
+fn redact() {
+    let (root, ops) = recorded_root();
+    let pool = root.pool("tank").unwrap().unwrap();
+    let snaps = pool
+        .dataset("tank/fs")
+        .unwrap()
+        .unwrap()
+        .snapshots()
+        .unwrap();
+    snaps[0].redact("book", &[&snaps[1]]).unwrap();
+
+    let ops = ops.lock().unwrap();
+    assert_eq!(ops.len(), 1);
+    assert_eq!(ops[0].req, ZFS_IOC_REDACT);
+    assert_eq!(ops[0].name, "tank/fs@a");
+    let args = ops[0].src.as_ref().unwrap();
+    assert_eq!(get_str(args, "bookname"), Some("book".into()));
+    let with: Vec<_> = args.get_list("snapnv").unwrap().keys().collect();
+    assert_eq!(with, [CString::new("tank/fs@b").unwrap().as_c_str()]);
+}
+
+#[test]
+fn hold_guard() {
+    let (root, ops) = recorded_root();
+    let pool = root.pool("tank").unwrap().unwrap();
+    let snap = &pool
+        .dataset("tank/fs")
+        .unwrap()
+        .unwrap()
+        .snapshots()
+        .unwrap()[0];
+    let held = |op: &common::Op| -> Vec<(String, String)> {
+        let args = op.src.as_ref().unwrap();
+        let holds = match op.req {
+            ZFS_IOC_HOLD => args.get_list("holds").unwrap().clone(),
+            _ => args.clone(),
+        };
+        holds
+            .pairs()
+            .map(|p| {
+                let tag = match p.value() {
+                    PairValue::String(tag) => tag.to_string_lossy().into_owned(),
+                    PairValue::List(tags) => tags.keys().next().unwrap().to_string_lossy().into(),
+                    v => panic!("unexpected hold value {:?}", v),
+                };
+                (p.key().to_string_lossy().into_owned(), tag)
+            })
+            .collect()
+    };
+
+    {
+        let guard = snap.hold_guard("backup").unwrap();
+        assert!(guard.snapshot() == snap);
+        assert_eq!(ops.lock().unwrap().len(), 1);
+    }
+    // and released, on the way out
+    let want = vec![("tank/fs@a".to_string(), "backup".to_string())];
+    {
+        let ops = ops.lock().unwrap();
+        assert_eq!(ops.len(), 2);
+        assert_eq!((ops[0].req, ops[0].name.as_str()), (ZFS_IOC_HOLD, "tank"));
+        assert_eq!(
+            (ops[1].req, ops[1].name.as_str()),
+            (ZFS_IOC_RELEASE, "tank")
+        );
+        assert_eq!(held(&ops[0]), want);
+        assert_eq!(held(&ops[1]), want);
+        // tied to a cleanup fd, in case the guard is never dropped
+        let fd = ops[0].src.as_ref().unwrap().get("cleanup_fd").unwrap();
+        assert!(matches!(fd.value(), PairValue::Int32(fd) if *fd > 0));
+    }
+
+    // released by hand, just once
+    snap.hold_guard("backup").unwrap().release().unwrap();
+    assert_eq!(ops.lock().unwrap().len(), 4);
+
+    // the replay doesn't keep new snapshots, so this one is gone as soon as it's made. it
+    // can't be held, and there's no guard to release it
+    let fs = pool.dataset("tank/fs").unwrap().unwrap();
+    let gone = fs.snapshot("gone", false).unwrap().remove(0);
+    let err = gone.hold_guard("backup").err().unwrap();
+    let pf = err.downcast_ref::<ioc::PartialFailure>().unwrap();
+    assert_eq!(pf.errno(gone.name().as_c_str()), Some(2)); // ENOENT
+    assert_eq!(ops.lock().unwrap().len(), 6);
+}
+
+#[test]
+fn bookmark() {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    let (root, ops) = recorded_root();
+    let pool = root.pool("tank").unwrap().unwrap();
+    let fs = pool.dataset("tank/fs").unwrap().unwrap();
+    assert!(fs.bookmark("nope").unwrap().is_none());
+
+    let bm = fs.bookmark("book").unwrap().unwrap();
+    assert_eq!(bm.name().as_str(), "tank/fs#book");
+    assert_eq!(bm.dataset().name().as_str(), "tank/fs");
+    assert_eq!(bm.guid().unwrap(), 0x0b0c0b0c0b0c0b0c);
+    assert_eq!(bm.creation_txg().unwrap(), 1234);
+    assert_eq!(
+        bm.creation_time().unwrap(),
+        UNIX_EPOCH + Duration::from_secs(1_700_000_000)
+    );
+    assert!(bm.props().unwrap().get_list("redact_snaps").is_none());
+
+    bm.destroy().unwrap();
+
+    // sent to the pool, naming the bookmark
+    let ops = ops.lock().unwrap();
+    assert_eq!(ops.len(), 1);
+    assert_eq!(ops[0].req, ZFS_IOC_DESTROY_BOOKMARKS);
+    assert_eq!(ops[0].name, "tank");
+    assert!(ops[0].src.as_ref().unwrap().get("tank/fs#book").is_some());
+}
+
+#[test]
+fn temp_snapshot() {
+    let (root, ops) = recorded_root();
+    let pool = root.pool("tank").unwrap().unwrap();
+    let fs = pool.dataset("tank/fs").unwrap().unwrap();
+
+    let tmp = fs.temp_snapshot("backup").unwrap();
+    assert_eq!(
+        tmp.snapshot().name().as_str(),
+        "tank/fs@backup-0000000000c0ffee"
+    );
+    assert!(tmp.snapshot().dataset() == fs);
+
+    let ops = ops.lock().unwrap();
+    assert_eq!(ops.len(), 1);
+    assert_eq!(ops[0].req, ZFS_IOC_TMP_SNAPSHOT);
+    assert_eq!(ops[0].name, "tank/fs");
+    drop(tmp);
+
+    assert!(fs.temp_snapshot("bad\0prefix").is_err());
+}
+
+#[test]
+fn datasets_filtered() {
+    let pool = replay_root().pool("tank").unwrap().unwrap();
+    let names = |filter| -> Vec<String> {
+        pool.datasets_filtered(filter)
+            .unwrap()
+            .iter()
+            .map(|o| o.name().to_string())
+            .collect()
+    };
+
+    assert_eq!(
+        names(DatasetFilter::default()),
+        ["tank", "tank/fs", "tank/fs@a", "tank/fs@b"]
+    );
+    let fs = DatasetFilter {
+        fs: true,
+        vol: false,
+        snap: false,
+    };
+    assert_eq!(names(fs), ["tank", "tank/fs"]);
+    let snap = DatasetFilter {
+        fs: false,
+        vol: false,
+        snap: true,
+    };
+    assert_eq!(names(snap), ["tank/fs@a", "tank/fs@b"]);
+    let vol = DatasetFilter {
+        fs: false,
+        vol: true,
+        snap: false,
+    };
+    assert!(names(vol).is_empty());
+
+    let all = pool.datasets_filtered(DatasetFilter::default()).unwrap();
+    assert!(matches!(&all[1], Objset::Dataset(ds) if ds.name().as_str() == "tank/fs"));
+    assert!(matches!(&all[2], Objset::Snapshot(_)));
+}
+
+#[test]
+fn vdev_bad_child() {
+    // a child with no guid is an error, not a gap in the tree
+    let s = |v: &str| PairValue::String(CString::new(v).unwrap());
+    let nameless = PairList::from_pairs([("type", s("disk"))]);
+    let mirror = PairList::from_pairs([
+        ("type", s("mirror")),
+        ("guid", PairValue::UInt64(10)),
+        (
+            "children",
+            PairValue::ListArray(vec![disk_config(11, "/dev/a"), nameless]),
+        ),
+    ]);
+    let root = PairList::from_pairs([
+        ("type", s("root")),
+        ("guid", PairValue::UInt64(1)),
+        ("children", PairValue::ListArray(vec![mirror])),
+    ]);
+
+    let pool = one_pool_root("made", pool_config("made", root))
+        .pool("made")
+        .unwrap()
+        .unwrap();
+    let tops = pool.root_vdev().unwrap().children().unwrap();
+    let err = tops[0].children().err().unwrap();
+    assert_eq!(
+        err.downcast::<IOError>().unwrap().kind(),
+        IOErrorKind::NotFound
+    );
+}
+
+#[test]
+fn vdev_sections() {
+    // a mirror with one side replaced by a hot spare, a mirrored log, and a cache device
+    let s = |v: &str| PairValue::String(CString::new(v).unwrap());
+    let mut spare_in_use = disk_config(13, "/dev/s");
+    spare_in_use.add("is_spare", PairValue::UInt64(1));
+    let spare = PairList::from_pairs([
+        ("type", s("spare")),
+        ("guid", PairValue::UInt64(12)),
+        (
+            "children",
+            PairValue::ListArray(vec![disk_config(14, "/dev/b"), spare_in_use]),
+        ),
+    ]);
+    let mirror = PairList::from_pairs([
+        ("type", s("mirror")),
+        ("guid", PairValue::UInt64(10)),
+        (
+            "children",
+            PairValue::ListArray(vec![disk_config(11, "/dev/a"), spare]),
+        ),
+    ]);
+    let log = PairList::from_pairs([
+        ("type", s("mirror")),
+        ("guid", PairValue::UInt64(20)),
+        ("is_log", PairValue::UInt64(1)),
+        (
+            "children",
+            PairValue::ListArray(vec![disk_config(21, "/dev/c"), disk_config(22, "/dev/d")]),
+        ),
+    ]);
+    let root = PairList::from_pairs([
+        ("type", s("root")),
+        ("guid", PairValue::UInt64(1)),
+        ("children", PairValue::ListArray(vec![mirror, log])),
+        (
+            "spares",
+            PairValue::ListArray(vec![disk_config(13, "/dev/s")]),
+        ),
+        (
+            "l2cache",
+            PairValue::ListArray(vec![disk_config(30, "/dev/e")]),
+        ),
+    ]);
+    let config = pool_config("made", root);
+
+    let pool = one_pool_root("made", config).pool("made").unwrap().unwrap();
+    let vdev = |guid| pool.vdev(guid).unwrap().unwrap();
+
+    // everything under a log is part of it
+    assert!(vdev(20).is_log());
+    assert!(vdev(21).is_log() && vdev(22).is_log());
+    assert!(!vdev(10).is_log() && !vdev(11).is_log());
+
+    // and the same in the plain tree
+    let tree = pool.vdev_tree().unwrap();
+    assert!(tree.children[1].children.iter().all(|vd| vd.is_log));
+    assert!(!tree.is_log && !tree.children[0].children[0].is_log);
+
+    // the spare standing in is found in the tree, and is still a spare
+    assert!(vdev(13).is_spare());
+    assert!(!vdev(14).is_spare() && !vdev(12).is_spare());
+    assert!(pool.spares().unwrap()[0].is_spare());
+
+    // cache devices are found too, though they're not in the tree
+    assert!(vdev(30).is_cache());
+    assert!(!vdev(30).is_log() && !vdev(30).is_spare());
+    assert!(!vdev(11).is_cache());
+}